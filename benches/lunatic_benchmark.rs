@@ -1,6 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use lunatic_runtime::api::default::DefaultApi;
-use lunatic_runtime::api::process::MemoryChoice;
+use lunatic_runtime::api::process::{MemoryChoice, ProcessConfig};
 use lunatic_runtime::linker::*;
 use lunatic_runtime::module::{LunaticModule, Runtime};
 
@@ -47,7 +47,11 @@ fn lunatic_bench(c: &mut Criterion) {
                 MemoryChoice::New(None),
             )
             .unwrap();
-            linker.add_api(DefaultApi::new(None, module.clone()));
+            linker.add_api(DefaultApi::new(
+                None,
+                module.clone(),
+                ProcessConfig::default(),
+            ));
             criterion::black_box(linker.instance().unwrap())
         });
     });
@@ -67,7 +71,11 @@ fn lunatic_bench(c: &mut Criterion) {
                     MemoryChoice::New(None),
                 )
                 .unwrap();
-                linker.add_api(DefaultApi::new(None, module.clone()));
+                linker.add_api(DefaultApi::new(
+                    None,
+                    module.clone(),
+                    ProcessConfig::default(),
+                ));
                 criterion::black_box(linker.instance().unwrap());
             });
             start.elapsed()
@@ -97,10 +97,10 @@ impl ChannelState {
     fn channel(&self, bound: u32) -> (ChannelSender, ChannelReceiver) {
         if bound > 0 {
             let (sender, receiver) = bounded(bound as usize);
-            (ChannelSender(sender), ChannelReceiver(receiver))
+            (ChannelSender::from(sender), ChannelReceiver(receiver))
         } else {
             let (sender, receiver) = unbounded();
-            (ChannelSender(sender), ChannelReceiver(receiver))
+            (ChannelSender::from(sender), ChannelReceiver(receiver))
         }
     }
 
@@ -124,6 +124,8 @@ impl ChannelState {
     /// - A serialized binary buffer passed by the host, with references to host resources
     /// - Host resources held by the instance
     ///
+    /// Messages to channels on other nodes can only contain channel senders as host resources.
+    ///
     /// Returns 0 if successful, otherwise 1
     async fn channel_send(&self, channel: ChannelSender, buffer: &[u8]) -> u32 {
         let resources = &mut self.inner.borrow_mut().next_message_host_resources;
@@ -1,4 +1,8 @@
 use crate::api::networking::tcp::{TcpListener, TcpStream};
+use crate::api::networking::tls::TlsStream;
+use crate::api::networking::udp::UdpSocket;
+#[cfg(unix)]
+use crate::api::networking::unix::{UnixListener, UnixStream};
 use crate::api::process::Process;
 
 use std::convert::TryFrom;
@@ -12,6 +16,32 @@ pub enum Resource {
     ChannelReceiver(ChannelReceiver),
     TcpListener(TcpListener),
     TcpStream(TcpStream),
+    UdpSocket(UdpSocket),
+    TlsStream(TlsStream),
+    #[cfg(unix)]
+    UnixListener(UnixListener),
+    #[cfg(unix)]
+    UnixStream(UnixStream),
+}
+
+impl Resource {
+    /// Name of the resource's type, used in error messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resource::Empty => "empty resource",
+            Resource::Process(_) => "Process",
+            Resource::ChannelSender(_) => "ChannelSender",
+            Resource::ChannelReceiver(_) => "ChannelReceiver",
+            Resource::TcpListener(_) => "TcpListener",
+            Resource::TcpStream(_) => "TcpStream",
+            Resource::UdpSocket(_) => "UdpSocket",
+            Resource::TlsStream(_) => "TlsStream",
+            #[cfg(unix)]
+            Resource::UnixListener(_) => "UnixListener",
+            #[cfg(unix)]
+            Resource::UnixStream(_) => "UnixStream",
+        }
+    }
 }
 
 impl From<Process> for Resource {
@@ -98,3 +128,75 @@ impl TryFrom<Resource> for TcpStream {
         }
     }
 }
+
+impl From<UdpSocket> for Resource {
+    fn from(udp_socket: UdpSocket) -> Self {
+        Resource::UdpSocket(udp_socket)
+    }
+}
+
+impl TryFrom<Resource> for UdpSocket {
+    type Error = ();
+
+    fn try_from(resource: Resource) -> Result<UdpSocket, ()> {
+        match resource {
+            Resource::UdpSocket(udp_socket) => Ok(udp_socket),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<TlsStream> for Resource {
+    fn from(tls_stream: TlsStream) -> Self {
+        Resource::TlsStream(tls_stream)
+    }
+}
+
+impl TryFrom<Resource> for TlsStream {
+    type Error = ();
+
+    fn try_from(resource: Resource) -> Result<TlsStream, ()> {
+        match resource {
+            Resource::TlsStream(tls_stream) => Ok(tls_stream),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixListener> for Resource {
+    fn from(unix_listener: UnixListener) -> Self {
+        Resource::UnixListener(unix_listener)
+    }
+}
+
+#[cfg(unix)]
+impl TryFrom<Resource> for UnixListener {
+    type Error = ();
+
+    fn try_from(resource: Resource) -> Result<UnixListener, ()> {
+        match resource {
+            Resource::UnixListener(unix_listener) => Ok(unix_listener),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixStream> for Resource {
+    fn from(unix_stream: UnixStream) -> Self {
+        Resource::UnixStream(unix_stream)
+    }
+}
+
+#[cfg(unix)]
+impl TryFrom<Resource> for UnixStream {
+    type Error = ();
+
+    fn try_from(resource: Resource) -> Result<UnixStream, ()> {
+        match resource {
+            Resource::UnixStream(unix_stream) => Ok(unix_stream),
+            _ => Err(()),
+        }
+    }
+}
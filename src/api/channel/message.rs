@@ -15,6 +15,14 @@ unsafe impl Send for Message {}
 
 impl Message {
     pub fn new(source: *const u8, len: usize, host_resources: Vec<Resource>) -> Self {
+        // Allocating zero bytes is undefined behaviour, empty messages point to nothing instead.
+        if len == 0 {
+            return Self {
+                ptr: ptr::NonNull::<u128>::dangling().as_ptr() as *mut u8,
+                len,
+                host_resources,
+            };
+        }
         unsafe {
             let layout = Layout::from_size_align(len, 16).expect("Invalid layout");
             let ptr: *mut u8 = mem::transmute(alloc(layout));
@@ -36,10 +44,17 @@ impl Message {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
 }
 
 impl Drop for Message {
     fn drop(&mut self) {
+        if self.len == 0 {
+            return;
+        }
         let layout = Layout::from_size_align(self.len, 16).expect("Invalid layout");
         unsafe { dealloc(self.ptr, layout) };
     }
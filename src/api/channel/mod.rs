@@ -12,4 +12,4 @@ mod sender;
 
 pub use message::Message;
 pub use receiver::{ChannelReceiver, ChannelReceiverResult};
-pub use sender::{ChannelSender, ChannelSenderResult};
+pub use sender::{ChannelSender, ChannelSenderResult, LocalSender};
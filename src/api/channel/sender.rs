@@ -1,10 +1,51 @@
 use super::{api::ChannelState, host_resources::Resource, Message};
+use crate::node::RemoteChannel;
 
-use smol::channel::{SendError, Sender};
+use anyhow::{anyhow, Result};
+use smol::channel::Sender;
 use uptown_funk::{Executor, FromWasm, ToWasm};
 
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub enum ChannelSender {
+    Local(LocalSender),
+    /// Sender of a channel living on another node.
+    Remote(Arc<RemoteChannel>),
+}
+
+/// Sender of a channel living on this node, clones share the id of the channel.
 #[derive(Clone)]
-pub struct ChannelSender(pub Sender<Message>);
+pub struct LocalSender {
+    id: u64,
+    sender: Sender<Message>,
+}
+
+impl LocalSender {
+    pub fn new(sender: Sender<Message>) -> Self {
+        static NEXT_CHANNEL_ID: AtomicU64 = AtomicU64::new(1);
+        Self {
+            id: NEXT_CHANNEL_ID.fetch_add(1, Ordering::Relaxed),
+            sender,
+        }
+    }
+}
+
+impl Deref for LocalSender {
+    type Target = Sender<Message>;
+
+    fn deref(&self) -> &Sender<Message> {
+        &self.sender
+    }
+}
+
+impl From<Sender<Message>> for ChannelSender {
+    fn from(sender: Sender<Message>) -> Self {
+        ChannelSender::Local(LocalSender::new(sender))
+    }
+}
 
 impl ToWasm<&mut ChannelState> for ChannelSender {
     type To = u32;
@@ -33,13 +74,52 @@ impl FromWasm<&mut ChannelState> for ChannelSender {
 }
 
 impl ChannelSender {
-    pub async fn send(
-        &self,
-        slice: &[u8],
-        host_resources: Vec<Resource>,
-    ) -> Result<(), SendError<Message>> {
-        let buffer = Message::new(slice.as_ptr(), slice.len(), host_resources);
-        self.0.send(buffer).await
+    /// Returns true if both senders belong to the same channel.
+    pub fn same_channel(&self, other: &ChannelSender) -> bool {
+        match (self, other) {
+            (ChannelSender::Local(a), ChannelSender::Local(b)) => a.id == b.id,
+            (ChannelSender::Remote(a), ChannelSender::Remote(b)) => {
+                a.node() == b.node() && a.channel() == b.channel()
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the channel can't receive messages anymore. A closed channel on another
+    /// node is only noticed once sending to it fails.
+    pub fn is_closed(&self) -> bool {
+        match self {
+            ChannelSender::Local(sender) => sender.is_closed(),
+            ChannelSender::Remote(_) => false,
+        }
+    }
+
+    /// Fails if the channel is closed or if a message to another node contains host resources that
+    /// can't leave this one.
+    pub async fn send(&self, slice: &[u8], host_resources: Vec<Resource>) -> Result<()> {
+        match self {
+            ChannelSender::Local(sender) => {
+                let buffer = Message::new(slice.as_ptr(), slice.len(), host_resources);
+                sender
+                    .send(buffer)
+                    .await
+                    .map_err(|_| anyhow!("Channel is closed"))
+            }
+            ChannelSender::Remote(channel) => channel.send(slice, host_resources),
+        }
+    }
+
+    /// Like `send`, but fails instead of waiting if the channel is full.
+    pub fn try_send(&self, slice: &[u8], host_resources: Vec<Resource>) -> Result<()> {
+        match self {
+            ChannelSender::Local(sender) => {
+                let buffer = Message::new(slice.as_ptr(), slice.len(), host_resources);
+                sender
+                    .try_send(buffer)
+                    .map_err(|_| anyhow!("Channel is closed or full"))
+            }
+            ChannelSender::Remote(channel) => channel.send(slice, host_resources),
+        }
     }
 }
 
@@ -1,19 +1,28 @@
 use uptown_funk::{Executor, HostFunctions};
 
 use crate::api::channel::ChannelReceiver;
+use crate::api::process::{next_process_id, ProcessConfig};
 use crate::module::LunaticModule;
 
 use crate::api::{channel, networking, process, wasi};
 pub struct DefaultApi {
+    id: u64,
     context_receiver: Option<ChannelReceiver>,
     module: LunaticModule,
+    config: ProcessConfig,
 }
 
 impl DefaultApi {
-    pub fn new(context_receiver: Option<ChannelReceiver>, module: LunaticModule) -> Self {
+    pub fn new(
+        context_receiver: Option<ChannelReceiver>,
+        module: LunaticModule,
+        config: ProcessConfig,
+    ) -> Self {
         Self {
+            id: next_process_id(),
             context_receiver,
             module,
+            config,
         }
     }
 }
@@ -27,13 +36,34 @@ impl HostFunctions for DefaultApi {
         E: Executor + Clone + 'static,
     {
         let channel_state = channel::api::ChannelState::new(self.context_receiver);
-        let process_state = process::api::ProcessState::new(self.module, channel_state.clone());
-        let networking_state = networking::TcpState::new(channel_state.clone());
-        let wasi_state = wasi::api::WasiState::new();
+        let wasi_state = wasi::api::WasiState::new(self.id, &self.config.wasi);
+        let networking_state = networking::TcpState::new(
+            channel_state.clone(),
+            wasi_state.fd_table(),
+            wasi_state.filesystem(),
+            self.config.network.clone(),
+            self.config.resolver.clone(),
+            self.config.network_backend.clone(),
+        );
+        #[cfg(unix)]
+        let unix_state = networking::UnixState::new(
+            channel_state.clone(),
+            self.config.network.clone(),
+            self.config.network_backend.clone(),
+            networking_state.last_error.clone(),
+        );
+        let process_state = process::api::ProcessState::new(
+            self.module,
+            channel_state.clone(),
+            self.config,
+            networking_state.last_error.clone(),
+        );
 
         channel_state.add_to_linker(executor.clone(), linker);
         process_state.add_to_linker(executor.clone(), linker);
         networking_state.add_to_linker(executor.clone(), linker);
+        #[cfg(unix)]
+        unix_state.add_to_linker(executor.clone(), linker);
         wasi_state.add_to_linker(executor, linker);
     }
 
@@ -48,13 +78,34 @@ impl HostFunctions for DefaultApi {
         E: Executor + Clone + 'static,
     {
         let channel_state = channel::api::ChannelState::new(self.context_receiver);
-        let process_state = process::api::ProcessState::new(self.module, channel_state.clone());
-        let networking_state = networking::TcpState::new(channel_state.clone());
-        let wasi_state = wasi::api::WasiState::new();
+        let wasi_state = wasi::api::WasiState::new(self.id, &self.config.wasi);
+        let networking_state = networking::TcpState::new(
+            channel_state.clone(),
+            wasi_state.fd_table(),
+            wasi_state.filesystem(),
+            self.config.network.clone(),
+            self.config.resolver.clone(),
+            self.config.network_backend.clone(),
+        );
+        #[cfg(unix)]
+        let unix_state = networking::UnixState::new(
+            channel_state.clone(),
+            self.config.network.clone(),
+            self.config.network_backend.clone(),
+            networking_state.last_error.clone(),
+        );
+        let process_state = process::api::ProcessState::new(
+            self.module,
+            channel_state.clone(),
+            self.config,
+            networking_state.last_error.clone(),
+        );
 
         channel_state.add_to_wasmer_linker(executor.clone(), linker, store);
         process_state.add_to_wasmer_linker(executor.clone(), linker, store);
         networking_state.add_to_wasmer_linker(executor.clone(), linker, store);
+        #[cfg(unix)]
+        unix_state.add_to_wasmer_linker(executor.clone(), linker, store);
         wasi_state.add_to_wasmer_linker(executor, linker, store);
     }
 }
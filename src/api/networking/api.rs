@@ -1,4 +1,7 @@
-use super::{resolver::*, tcp::*};
+use super::{
+    backend::NetworkBackend, dns::NameResolver, error::*, policy::NetworkPolicy, resolver::*,
+    tcp::*, timeout::*, tls::*, udp::*,
+};
 use smol::io::{AsyncReadExt, AsyncWriteExt};
 use std::io::{IoSlice, IoSliceMut};
 use uptown_funk::host_functions;
@@ -8,34 +11,132 @@ type OptionTrap = Result<u32, uptown_funk::Trap>;
 
 use super::resolver::Resolver;
 use super::tcp::{TcpListener, TcpStream};
+use super::udp::UdpSocket;
+#[cfg(unix)]
+use super::unix::*;
 use uptown_funk::state::HashMapStore;
 
 use crate::api::channel::api::ChannelState;
+use crate::api::wasi::fd_table::FdTable;
+use crate::api::wasi::fs::{read_file, FileSystem};
+use crate::api::wasi::state::Descriptor;
+use crate::api::wasi::types::{Sdflags, Status, SDFLAGS_RD, SDFLAGS_WR};
+
+use std::{
+    cell::RefCell,
+    io,
+    net::{Shutdown, SocketAddr},
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
 
 pub struct TcpState {
     channel_state: ChannelState,
+    fds: Rc<RefCell<FdTable<Descriptor>>>,
+    fs: Arc<dyn FileSystem>,
+    policy: NetworkPolicy,
+    resolver: Arc<dyn NameResolver>,
+    backend: NetworkBackend,
+    pub last_error: LastError,
     pub resolvers: HashMapStore<Resolver>,
     pub listeners: HashMapStore<TcpListener>,
     pub streams: HashMapStore<TcpStream>,
+    pub udp_sockets: HashMapStore<UdpSocket>,
+    pub tls_client_configs: HashMapStore<TlsClientConfig>,
+    pub tls_server_configs: HashMapStore<TlsServerConfig>,
+    pub tls_streams: HashMapStore<TlsStream>,
 }
 
 impl TcpState {
-    pub fn new(channel_state: ChannelState) -> Self {
+    pub fn new(
+        channel_state: ChannelState,
+        fds: Rc<RefCell<FdTable<Descriptor>>>,
+        fs: Arc<dyn FileSystem>,
+        policy: NetworkPolicy,
+        resolver: Arc<dyn NameResolver>,
+        backend: NetworkBackend,
+    ) -> Self {
         Self {
             channel_state,
-            resolvers: HashMapStore::new(),
-            listeners: HashMapStore::new(),
-            streams: HashMapStore::new(),
+            fds,
+            fs,
+            policy,
+            resolver,
+            backend,
+            last_error: LastError::default(),
+            resolvers: HashMapStore::new_nonzero(),
+            listeners: HashMapStore::new_nonzero(),
+            streams: HashMapStore::new_nonzero(),
+            udp_sockets: HashMapStore::new_nonzero(),
+            tls_client_configs: HashMapStore::new_nonzero(),
+            tls_server_configs: HashMapStore::new_nonzero(),
+            tls_streams: HashMapStore::new_nonzero(),
         }
     }
 }
 
+// Functions returning a result code use 0 for success and a WASI status code for errors. This is a
+// breaking change to earlier versions, which returned 1 for all errors, guests need to check for a
+// non-zero result instead.
+// Functions creating a resource return the id 0 if they fail, no resource ever uses it. The reason
+// of the failure can be retrieved afterwards with the `last_error_*` functions.
 #[host_functions(namespace = "lunatic")]
 impl TcpState {
+    // Returns the WASI status code of the last failed call, or 0 if no call failed yet.
+    fn last_error_status(&self) -> u32 {
+        match self.last_error.get() {
+            Some(err) => err.status as u32,
+            None => 0,
+        }
+    }
+
+    fn last_error_message_len(&self) -> u32 {
+        match self.last_error.get() {
+            Some(err) => err.message.len() as u32,
+            None => 0,
+        }
+    }
+
+    // Copies the message of the last failed call into `buf`, truncating it if `buf` is too small.
+    // Returns the number of bytes written.
+    fn last_error_message(&self, buf: &mut [u8]) -> u32 {
+        let message = match self.last_error.get() {
+            Some(err) => err.message,
+            None => return 0,
+        };
+        let len = message.len().min(buf.len());
+        buf[..len].copy_from_slice(&message.as_bytes()[..len]);
+        len as u32
+    }
+
     async fn resolve(&self, name: &str) -> (u32, ResolverResult) {
-        match Resolver::resolve(name).await {
+        let resolve = async {
+            self.policy.check_dns(name)?;
+            Resolver::resolve(self.resolver.clone(), name).await
+        };
+        match resolve.await {
+            Ok(resolver) => (0, ResolverResult::Ok(resolver)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, ResolverResult::Err(err))
+            }
+        }
+    }
+
+    // Like `resolve`, but fails with `timedout` if the name can't be resolved within `timeout_ms`
+    // milliseconds. A timeout of 0 waits forever.
+    async fn resolve_timeout(&self, name: &str, timeout_ms: u64) -> (u32, ResolverResult) {
+        let resolve = async {
+            self.policy.check_dns(name)?;
+            Resolver::resolve(self.resolver.clone(), name).await
+        };
+        match with_timeout(from_millis(timeout_ms), resolve).await {
             Ok(resolver) => (0, ResolverResult::Ok(resolver)),
-            Err(err) => (1, ResolverResult::Err(err.to_string())),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, ResolverResult::Err(err))
+            }
         }
     }
 
@@ -46,27 +147,13 @@ impl TcpState {
         &self,
         resolver: Resolver,
         addr: Ptr<u8>,
-        mut addr_len: Ptr<u32>,
-        mut port: Ptr<u16>,
-        mut flowinfo: Ptr<u32>,
-        mut scope_id: Ptr<u32>,
+        addr_len: Ptr<u32>,
+        port: Ptr<u16>,
+        flowinfo: Ptr<u32>,
+        scope_id: Ptr<u32>,
     ) -> OptionTrap {
         if let Some(address) = resolver.next() {
-            match address {
-                smol::net::SocketAddr::V4(v4) => {
-                    let octets = v4.ip().octets();
-                    addr.copy_slice(&octets)?;
-                    addr_len.set(octets.len() as u32);
-                }
-                smol::net::SocketAddr::V6(v6) => {
-                    let octets = v6.ip().octets();
-                    addr.copy_slice(&octets)?;
-                    addr_len.set(octets.len() as u32);
-                    flowinfo.set(v6.flowinfo());
-                    scope_id.set(v6.scope_id());
-                }
-            }
-            port.set(address.port());
+            write_socket_addr(address, addr, addr_len, port, flowinfo, scope_id)?;
             Ok(0)
         } else {
             Ok(1)
@@ -78,55 +165,210 @@ impl TcpState {
     }
 
     async fn tcp_bind(&self, address: &[u8], port: u32) -> (u32, TcpListenerResult) {
-        match TcpListener::bind(address, port as u16).await {
+        let bind = async {
+            self.policy.check_bind(address, port as u16)?;
+            self.backend.tcp_bind(address, port as u16).await
+        };
+        match bind.await {
             Ok(listener) => (0, TcpListenerResult::Ok(listener)),
-            Err(err) => (1, TcpListenerResult::Err(err.to_string())),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, TcpListenerResult::Err(err))
+            }
         }
     }
 
     async fn tcp_accept(&self, tcp_listener: TcpListener) -> (u32, TcpStreamResult) {
         match tcp_listener.accept().await {
             Ok(stream) => (0, TcpStreamResult::Ok(stream)),
-            Err(err) => (1, TcpStreamResult::Err(err.to_string())),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, TcpStreamResult::Err(err))
+            }
+        }
+    }
+
+    // Like `tcp_accept`, but fails with `timedout` if no connection arrives within `timeout_ms`
+    // milliseconds. A timeout of 0 waits forever.
+    async fn tcp_accept_timeout(
+        &self,
+        tcp_listener: TcpListener,
+        timeout_ms: u64,
+    ) -> (u32, TcpStreamResult) {
+        match with_timeout(from_millis(timeout_ms), tcp_listener.accept()).await {
+            Ok(stream) => (0, TcpStreamResult::Ok(stream)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, TcpStreamResult::Err(err))
+            }
         }
     }
 
     async fn tcp_connect(&self, address: &[u8], port: u32) -> (u32, TcpStreamResult) {
-        match TcpStream::connect(address, port as u16).await {
+        let connect = async {
+            self.policy.check_connect(address, port as u16)?;
+            self.backend.tcp_connect(address, port as u16).await
+        };
+        match connect.await {
             Ok(tcp_stream) => (0, TcpStreamResult::Ok(tcp_stream)),
-            Err(err) => (1, TcpStreamResult::Err(err.to_string())),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, TcpStreamResult::Err(err))
+            }
         }
     }
 
-    async fn tcp_write_vectored(
+    // Like `tcp_connect`, but fails with `timedout` if the connection isn't established within
+    // `timeout_ms` milliseconds. A timeout of 0 waits forever.
+    async fn tcp_connect_timeout(
         &self,
-        mut tcp_stream: TcpStream,
-        ciovs: &[IoSlice<'_>],
-    ) -> (u32, u32) {
-        match tcp_stream.0.write_vectored(ciovs).await {
-            Ok(bytes_written) => (0, bytes_written as u32),
-            Err(_) => (1, 0),
+        address: &[u8],
+        port: u32,
+        timeout_ms: u64,
+    ) -> (u32, TcpStreamResult) {
+        let connect = async {
+            self.policy.check_connect(address, port as u16)?;
+            self.backend.tcp_connect(address, port as u16).await
+        };
+        match with_timeout(from_millis(timeout_ms), connect).await {
+            Ok(tcp_stream) => (0, TcpStreamResult::Ok(tcp_stream)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, TcpStreamResult::Err(err))
+            }
         }
     }
 
-    async fn tcp_flush(&self, mut tcp_stream: TcpStream) -> u32 {
-        match tcp_stream.0.flush().await {
-            Ok(()) => 0,
-            Err(_) => 1,
+    async fn tcp_write_vectored(&self, tcp_stream: TcpStream, ciovs: &[IoSlice<'_>]) -> (u32, u32) {
+        match tcp_stream.write_vectored(ciovs).await {
+            Ok(bytes_written) => (0, bytes_written as u32),
+            Err(err) => (Status::from(err) as u32, 0),
         }
     }
 
-    async fn tcp_read_vectored<'a>(
+    async fn tcp_flush(&self, tcp_stream: TcpStream) -> u32 {
+        status_code(tcp_stream.flush().await)
+    }
+
+    async fn tcp_read_vectored(
         &self,
-        tcp_stream: &'a mut TcpStream,
-        iovs: &'a mut [IoSliceMut<'a>],
+        tcp_stream: TcpStream,
+        iovs: &mut [IoSliceMut<'_>],
     ) -> (u32, u32) {
-        match tcp_stream.0.read_vectored(iovs).await {
-            Ok(bytes_written) => (0, bytes_written as u32),
-            Err(_) => (1, 0),
+        match tcp_stream.read_vectored(iovs).await {
+            Ok(bytes_read) => (0, bytes_read as u32),
+            Err(err) => (Status::from(err) as u32, 0),
         }
     }
 
+    // Reads of the stream, also through `fd_read` and `sock_recv`, fail with `timedout` if no data
+    // arrives within `timeout_ms` milliseconds. A timeout of 0 waits forever.
+    fn tcp_set_read_timeout(&self, tcp_stream: TcpStream, timeout_ms: u64) {
+        tcp_stream.set_read_timeout(from_millis(timeout_ms));
+    }
+
+    // Writes of the stream fail with `timedout` if they can't complete within `timeout_ms`
+    // milliseconds. A timeout of 0 waits forever.
+    fn tcp_set_write_timeout(&self, tcp_stream: TcpStream, timeout_ms: u64) {
+        tcp_stream.set_write_timeout(from_millis(timeout_ms));
+    }
+
+    // Writes the address of the remote end in the same format as `resolve_next`.
+    fn tcp_stream_peer_addr(
+        &self,
+        tcp_stream: TcpStream,
+        addr: Ptr<u8>,
+        addr_len: Ptr<u32>,
+        port: Ptr<u16>,
+        flowinfo: Ptr<u32>,
+        scope_id: Ptr<u32>,
+    ) -> OptionTrap {
+        write_address_result(
+            tcp_stream.peer_addr(),
+            addr,
+            addr_len,
+            port,
+            flowinfo,
+            scope_id,
+        )
+    }
+
+    fn tcp_stream_local_addr(
+        &self,
+        tcp_stream: TcpStream,
+        addr: Ptr<u8>,
+        addr_len: Ptr<u32>,
+        port: Ptr<u16>,
+        flowinfo: Ptr<u32>,
+        scope_id: Ptr<u32>,
+    ) -> OptionTrap {
+        write_address_result(
+            tcp_stream.local_addr(),
+            addr,
+            addr_len,
+            port,
+            flowinfo,
+            scope_id,
+        )
+    }
+
+    // Also returns the port picked by the OS if the listener was bound to port 0.
+    fn tcp_listener_local_addr(
+        &self,
+        tcp_listener: TcpListener,
+        addr: Ptr<u8>,
+        addr_len: Ptr<u32>,
+        port: Ptr<u16>,
+        flowinfo: Ptr<u32>,
+        scope_id: Ptr<u32>,
+    ) -> OptionTrap {
+        write_address_result(
+            tcp_listener.local_addr(),
+            addr,
+            addr_len,
+            port,
+            flowinfo,
+            scope_id,
+        )
+    }
+
+    // `how` uses the WASI `sdflags`: 1 shuts down reading, 2 writing and 3 both.
+    fn tcp_shutdown(&self, tcp_stream: TcpStream, how: u32) -> u32 {
+        let how = match how as Sdflags {
+            SDFLAGS_RD => Shutdown::Read,
+            SDFLAGS_WR => Shutdown::Write,
+            flags if flags == SDFLAGS_RD | SDFLAGS_WR => Shutdown::Both,
+            _ => return Status::Inval as u32,
+        };
+        status_code(tcp_stream.shutdown(how))
+    }
+
+    fn tcp_set_nodelay(&self, tcp_stream: TcpStream, nodelay: u32) -> u32 {
+        status_code(tcp_stream.set_nodelay(nodelay != 0))
+    }
+
+    fn tcp_set_ttl(&self, tcp_stream: TcpStream, ttl: u32) -> u32 {
+        status_code(tcp_stream.set_ttl(ttl))
+    }
+
+    // Sends keepalive probes after the connection was idle for `idle_secs` seconds, 0 disables them.
+    fn tcp_set_keepalive(&self, tcp_stream: TcpStream, idle_secs: u32) -> u32 {
+        let idle = match idle_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs as u64)),
+        };
+        status_code(tcp_stream.set_keepalive(idle))
+    }
+
+    // If `linger` is non-zero closing the stream waits up to `linger_secs` seconds for unsent data.
+    fn tcp_set_linger(&self, tcp_stream: TcpStream, linger: u32, linger_secs: u32) -> u32 {
+        let linger = match linger {
+            0 => None,
+            _ => Some(Duration::from_secs(linger_secs as u64)),
+        };
+        status_code(tcp_stream.set_linger(linger))
+    }
+
     fn close_tcp_listener(&mut self, id: u32) {
         self.listeners.remove(id);
     }
@@ -135,6 +377,54 @@ impl TcpState {
         self.streams.remove(id);
     }
 
+    // Moves the stream into the WASI file descriptor table, so that it can be used with `fd_read`,
+    // `fd_write` and `sock_*` functions. If successful, the stream id can't be used anymore.
+    // Result:
+    // 0: Success
+    // otherwise: WASI status code, `mfile` if there are too many open file descriptors
+    fn tcp_stream_into_fd(&mut self, id: u32, mut fd: Ptr<u32>) -> OptionTrap {
+        let tcp_stream = match self.streams.get(id) {
+            Some(tcp_stream) => tcp_stream.clone(),
+            None => return Err(uptown_funk::Trap::new("TcpStream not found")),
+        };
+        match self
+            .fds
+            .borrow_mut()
+            .insert(Descriptor::TcpStream(tcp_stream))
+        {
+            Ok(new_fd) => {
+                self.streams.remove(id);
+                fd.set(new_fd);
+                Ok(0)
+            }
+            Err(status) => Ok(status as u32),
+        }
+    }
+
+    // Moves the listener into the WASI file descriptor table, so that it can be used with
+    // `sock_accept`. If successful, the listener id can't be used anymore.
+    // Result:
+    // 0: Success
+    // otherwise: WASI status code, `mfile` if there are too many open file descriptors
+    fn tcp_listener_into_fd(&mut self, id: u32, mut fd: Ptr<u32>) -> OptionTrap {
+        let tcp_listener = match self.listeners.get(id) {
+            Some(tcp_listener) => tcp_listener.clone(),
+            None => return Err(uptown_funk::Trap::new("TcpListener not found")),
+        };
+        match self
+            .fds
+            .borrow_mut()
+            .insert(Descriptor::TcpListener(tcp_listener))
+        {
+            Ok(new_fd) => {
+                self.listeners.remove(id);
+                fd.set(new_fd);
+                Ok(0)
+            }
+            Err(status) => Ok(status as u32),
+        }
+    }
+
     fn tcp_stream_serialize(&self, tcp_stream: TcpStream) -> u32 {
         self.channel_state.serialize_host_resource(tcp_stream) as u32
     }
@@ -142,9 +432,12 @@ impl TcpState {
     fn tcp_stream_deserialize(&self, index: u32) -> TcpStreamResult {
         match self.channel_state.deserialize_host_resource(index as usize) {
             Some(tcp_stream) => TcpStreamResult::Ok(tcp_stream),
-            None => TcpStreamResult::Err(format!(
-                "No TcpStream found under index: {}, while deserializing",
-                index
+            None => TcpStreamResult::Err(NetworkError::new(
+                Status::Inval,
+                format!(
+                    "No TcpStream found under index: {}, while deserializing",
+                    index
+                ),
             )),
         }
     }
@@ -156,10 +449,521 @@ impl TcpState {
     fn tcp_listener_deserialize(&self, index: u32) -> TcpListenerResult {
         match self.channel_state.deserialize_host_resource(index as usize) {
             Some(tcp_listener) => TcpListenerResult::Ok(tcp_listener),
-            None => TcpListenerResult::Err(format!(
-                "No TcpStream found under index: {}, while deserializing",
-                index
+            None => TcpListenerResult::Err(NetworkError::new(
+                Status::Inval,
+                format!(
+                    "No TcpStream found under index: {}, while deserializing",
+                    index
+                ),
+            )),
+        }
+    }
+
+    async fn udp_bind(&self, address: &[u8], port: u32) -> (u32, UdpSocketResult) {
+        let bind = async {
+            self.policy.check_bind(address, port as u16)?;
+            self.backend.udp_bind(address, port as u16).await
+        };
+        match bind.await {
+            Ok(socket) => (0, UdpSocketResult::Ok(socket)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, UdpSocketResult::Err(err))
+            }
+        }
+    }
+
+    // Sets the default destination of `udp_send` and only receives datagrams from it.
+    async fn udp_connect(&self, udp_socket: UdpSocket, address: &[u8], port: u32) -> u32 {
+        let connect = async {
+            self.policy.check_connect(address, port as u16)?;
+            udp_socket.connect(address, port as u16).await
+        };
+        status_code(connect.await)
+    }
+
+    async fn udp_send(&self, udp_socket: UdpSocket, buf: &[u8]) -> (u32, u32) {
+        match udp_socket.send(buf).await {
+            Ok(bytes_sent) => (0, bytes_sent as u32),
+            Err(err) => (Status::from(err) as u32, 0),
+        }
+    }
+
+    async fn udp_send_to(
+        &self,
+        udp_socket: UdpSocket,
+        buf: &[u8],
+        address: &[u8],
+        port: u32,
+    ) -> (u32, u32) {
+        let send = async {
+            self.policy.check_connect(address, port as u16)?;
+            udp_socket.send_to(buf, address, port as u16).await
+        };
+        match send.await {
+            Ok(bytes_sent) => (0, bytes_sent as u32),
+            Err(err) => (Status::from(err) as u32, 0),
+        }
+    }
+
+    async fn udp_recv(&self, udp_socket: UdpSocket, buf: &mut [u8]) -> (u32, u32) {
+        match udp_socket.recv(buf).await {
+            Ok(bytes_received) => (0, bytes_received as u32),
+            Err(err) => (Status::from(err) as u32, 0),
+        }
+    }
+
+    // Receives a datagram into `buf` and writes the address of the sender in the same format as
+    // `resolve_next`. Bytes of the datagram not fitting into `buf` are discarded.
+    async fn udp_recv_from(
+        &self,
+        udp_socket: UdpSocket,
+        buf: &mut [u8],
+        mut bytes_received: Ptr<u32>,
+        addr: Ptr<u8>,
+        addr_len: Ptr<u32>,
+        port: Ptr<u16>,
+        flowinfo: Ptr<u32>,
+        scope_id: Ptr<u32>,
+    ) -> OptionTrap {
+        match udp_socket.recv_from(buf).await {
+            Ok((received, address)) => {
+                bytes_received.set(received as u32);
+                write_socket_addr(address, addr, addr_len, port, flowinfo, scope_id)?;
+                Ok(0)
+            }
+            Err(err) => Ok(Status::from(err) as u32),
+        }
+    }
+
+    fn udp_local_addr(
+        &self,
+        udp_socket: UdpSocket,
+        addr: Ptr<u8>,
+        addr_len: Ptr<u32>,
+        port: Ptr<u16>,
+        flowinfo: Ptr<u32>,
+        scope_id: Ptr<u32>,
+    ) -> OptionTrap {
+        write_address_result(
+            udp_socket.local_addr(),
+            addr,
+            addr_len,
+            port,
+            flowinfo,
+            scope_id,
+        )
+    }
+
+    fn udp_set_broadcast(&self, udp_socket: UdpSocket, broadcast: u32) -> u32 {
+        status_code(udp_socket.set_broadcast(broadcast != 0))
+    }
+
+    fn udp_set_ttl(&self, udp_socket: UdpSocket, ttl: u32) -> u32 {
+        status_code(udp_socket.set_ttl(ttl))
+    }
+
+    fn udp_join_multicast_v4(
+        &self,
+        udp_socket: UdpSocket,
+        multiaddr: &[u8],
+        interface: &[u8],
+    ) -> u32 {
+        status_code(udp_socket.join_multicast_v4(multiaddr, interface))
+    }
+
+    fn udp_leave_multicast_v4(
+        &self,
+        udp_socket: UdpSocket,
+        multiaddr: &[u8],
+        interface: &[u8],
+    ) -> u32 {
+        status_code(udp_socket.leave_multicast_v4(multiaddr, interface))
+    }
+
+    fn udp_join_multicast_v6(
+        &self,
+        udp_socket: UdpSocket,
+        multiaddr: &[u8],
+        interface: u32,
+    ) -> u32 {
+        status_code(udp_socket.join_multicast_v6(multiaddr, interface))
+    }
+
+    fn udp_leave_multicast_v6(
+        &self,
+        udp_socket: UdpSocket,
+        multiaddr: &[u8],
+        interface: u32,
+    ) -> u32 {
+        status_code(udp_socket.leave_multicast_v6(multiaddr, interface))
+    }
+
+    fn udp_set_multicast_loop_v4(&self, udp_socket: UdpSocket, multicast_loop: u32) -> u32 {
+        status_code(udp_socket.set_multicast_loop_v4(multicast_loop != 0))
+    }
+
+    fn udp_set_multicast_loop_v6(&self, udp_socket: UdpSocket, multicast_loop: u32) -> u32 {
+        status_code(udp_socket.set_multicast_loop_v6(multicast_loop != 0))
+    }
+
+    fn udp_set_multicast_ttl_v4(&self, udp_socket: UdpSocket, ttl: u32) -> u32 {
+        status_code(udp_socket.set_multicast_ttl_v4(ttl))
+    }
+
+    fn close_udp_socket(&mut self, id: u32) {
+        self.udp_sockets.remove(id);
+    }
+
+    fn udp_socket_serialize(&self, udp_socket: UdpSocket) -> u32 {
+        self.channel_state.serialize_host_resource(udp_socket) as u32
+    }
+
+    fn udp_socket_deserialize(&self, index: u32) -> UdpSocketResult {
+        match self.channel_state.deserialize_host_resource(index as usize) {
+            Some(udp_socket) => UdpSocketResult::Ok(udp_socket),
+            None => UdpSocketResult::Err(NetworkError::new(
+                Status::Inval,
+                format!(
+                    "No UdpSocket found under index: {}, while deserializing",
+                    index
+                ),
+            )),
+        }
+    }
+
+    // Trusts the PEM encoded CA certificates at `ca_path` in the filesystem of the process, or the
+    // Mozilla root certificates if the path is empty.
+    async fn tls_client_config(&self, ca_path: &str) -> (u32, TlsClientConfigResult) {
+        let ca_pem = if ca_path.is_empty() {
+            None
+        } else {
+            match read_file(self.fs.clone(), ca_path).await {
+                Ok(ca_pem) => Some(ca_pem),
+                Err(status) => {
+                    let err = NetworkError::new(status, format!("Can't read {}", ca_path));
+                    return (status as u32, TlsClientConfigResult::Err(err));
+                }
+            }
+        };
+        match TlsClientConfig::new(ca_pem.as_deref()) {
+            Ok(config) => (0, TlsClientConfigResult::Ok(config)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, TlsClientConfigResult::Err(err))
+            }
+        }
+    }
+
+    // Uses the PEM encoded certificate chain and private key at `cert_path` and `key_path` in the
+    // filesystem of the process.
+    async fn tls_server_config(
+        &self,
+        cert_path: &str,
+        key_path: &str,
+    ) -> (u32, TlsServerConfigResult) {
+        let pem = match read_file(self.fs.clone(), cert_path).await {
+            Ok(cert_pem) => match read_file(self.fs.clone(), key_path).await {
+                Ok(key_pem) => Ok((cert_pem, key_pem)),
+                Err(status) => Err((status, key_path)),
+            },
+            Err(status) => Err((status, cert_path)),
+        };
+        let (cert_pem, key_pem) = match pem {
+            Ok(pem) => pem,
+            Err((status, path)) => {
+                let err = NetworkError::new(status, format!("Can't read {}", path));
+                return (status as u32, TlsServerConfigResult::Err(err));
+            }
+        };
+        match TlsServerConfig::new(&cert_pem, &key_pem) {
+            Ok(config) => (0, TlsServerConfigResult::Ok(config)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, TlsServerConfigResult::Err(err))
+            }
+        }
+    }
+
+    fn close_tls_client_config(&mut self, id: u32) {
+        self.tls_client_configs.remove(id);
+    }
+
+    fn close_tls_server_config(&mut self, id: u32) {
+        self.tls_server_configs.remove(id);
+    }
+
+    // Performs the TLS handshake as client on top of `tcp_stream`. The TCP stream is moved into the
+    // TLS stream, its id can't be used anymore afterwards, even if the handshake fails.
+    async fn tls_connect(
+        &mut self,
+        config: TlsClientConfig,
+        tcp_stream: TakenTcpStream,
+        domain: &str,
+    ) -> (u32, TlsStreamResult) {
+        match TlsStream::connect(&config, tcp_stream.0, domain).await {
+            Ok(tls_stream) => (0, TlsStreamResult::Ok(tls_stream)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, TlsStreamResult::Err(err))
+            }
+        }
+    }
+
+    // Performs the TLS handshake as server on top of `tcp_stream`. Like with `tls_connect`, the id
+    // of the TCP stream can't be used anymore afterwards.
+    async fn tls_accept(
+        &mut self,
+        config: TlsServerConfig,
+        tcp_stream: TakenTcpStream,
+    ) -> (u32, TlsStreamResult) {
+        match TlsStream::accept(&config, tcp_stream.0).await {
+            Ok(tls_stream) => (0, TlsStreamResult::Ok(tls_stream)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, TlsStreamResult::Err(err))
+            }
+        }
+    }
+
+    async fn tls_write_vectored(&self, tls_stream: TlsStream, ciovs: &[IoSlice<'_>]) -> (u32, u32) {
+        match tls_stream.write_vectored(ciovs).await {
+            Ok(bytes_written) => (0, bytes_written as u32),
+            Err(err) => (Status::from(err) as u32, 0),
+        }
+    }
+
+    async fn tls_flush(&self, tls_stream: TlsStream) -> u32 {
+        status_code(tls_stream.flush().await)
+    }
+
+    async fn tls_read_vectored(
+        &self,
+        tls_stream: TlsStream,
+        iovs: &mut [IoSliceMut<'_>],
+    ) -> (u32, u32) {
+        match tls_stream.read_vectored(iovs).await {
+            Ok(bytes_read) => (0, bytes_read as u32),
+            Err(err) => (Status::from(err) as u32, 0),
+        }
+    }
+
+    fn close_tls_stream(&mut self, id: u32) {
+        self.tls_streams.remove(id);
+    }
+
+    fn tls_stream_serialize(&self, tls_stream: TlsStream) -> u32 {
+        self.channel_state.serialize_host_resource(tls_stream) as u32
+    }
+
+    fn tls_stream_deserialize(&self, index: u32) -> TlsStreamResult {
+        match self.channel_state.deserialize_host_resource(index as usize) {
+            Some(tls_stream) => TlsStreamResult::Ok(tls_stream),
+            None => TlsStreamResult::Err(NetworkError::new(
+                Status::Inval,
+                format!(
+                    "No TlsStream found under index: {}, while deserializing",
+                    index
+                ),
             )),
         }
     }
 }
+
+/// Host functions for Unix domain sockets, only available on Unix platforms.
+#[cfg(unix)]
+pub struct UnixState {
+    channel_state: ChannelState,
+    policy: NetworkPolicy,
+    backend: NetworkBackend,
+    pub last_error: LastError,
+    pub listeners: HashMapStore<UnixListener>,
+    pub streams: HashMapStore<UnixStream>,
+}
+
+#[cfg(unix)]
+impl UnixState {
+    /// Failures are recorded in `last_error`, which should be shared with the process' `TcpState`.
+    pub fn new(
+        channel_state: ChannelState,
+        policy: NetworkPolicy,
+        backend: NetworkBackend,
+        last_error: LastError,
+    ) -> Self {
+        Self {
+            channel_state,
+            policy,
+            backend,
+            last_error,
+            listeners: HashMapStore::new_nonzero(),
+            streams: HashMapStore::new_nonzero(),
+        }
+    }
+}
+
+// Functions returning a result code use 0 for success and a WASI status code for errors.
+#[cfg(unix)]
+#[host_functions(namespace = "lunatic")]
+impl UnixState {
+    fn unix_bind(&self, path: &str) -> (u32, UnixListenerResult) {
+        let bind = self
+            .policy
+            .check_unix_sockets(path)
+            .and_then(|()| self.backend.check_unix_sockets())
+            .and_then(|()| UnixListener::bind(path));
+        match bind {
+            Ok(listener) => (0, UnixListenerResult::Ok(listener)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, UnixListenerResult::Err(err))
+            }
+        }
+    }
+
+    async fn unix_accept(&self, unix_listener: UnixListener) -> (u32, UnixStreamResult) {
+        match unix_listener.accept().await {
+            Ok(stream) => (0, UnixStreamResult::Ok(stream)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, UnixStreamResult::Err(err))
+            }
+        }
+    }
+
+    async fn unix_connect(&self, path: &str) -> (u32, UnixStreamResult) {
+        let connect = async {
+            self.policy.check_unix_sockets(path)?;
+            self.backend.check_unix_sockets()?;
+            UnixStream::connect(path).await
+        };
+        match connect.await {
+            Ok(stream) => (0, UnixStreamResult::Ok(stream)),
+            Err(err) => {
+                let err = NetworkError::from(err);
+                (err.status as u32, UnixStreamResult::Err(err))
+            }
+        }
+    }
+
+    async fn unix_write_vectored(
+        &self,
+        mut unix_stream: UnixStream,
+        ciovs: &[IoSlice<'_>],
+    ) -> (u32, u32) {
+        match unix_stream.0.write_vectored(ciovs).await {
+            Ok(bytes_written) => (0, bytes_written as u32),
+            Err(err) => (Status::from(err) as u32, 0),
+        }
+    }
+
+    async fn unix_flush(&self, mut unix_stream: UnixStream) -> u32 {
+        status_code(unix_stream.0.flush().await)
+    }
+
+    async fn unix_read_vectored<'a>(
+        &self,
+        unix_stream: &'a mut UnixStream,
+        iovs: &'a mut [IoSliceMut<'a>],
+    ) -> (u32, u32) {
+        match unix_stream.0.read_vectored(iovs).await {
+            Ok(bytes_read) => (0, bytes_read as u32),
+            Err(err) => (Status::from(err) as u32, 0),
+        }
+    }
+
+    fn close_unix_listener(&mut self, id: u32) {
+        self.listeners.remove(id);
+    }
+
+    fn close_unix_stream(&mut self, id: u32) {
+        self.streams.remove(id);
+    }
+
+    fn unix_stream_serialize(&self, unix_stream: UnixStream) -> u32 {
+        self.channel_state.serialize_host_resource(unix_stream) as u32
+    }
+
+    fn unix_stream_deserialize(&self, index: u32) -> UnixStreamResult {
+        match self.channel_state.deserialize_host_resource(index as usize) {
+            Some(unix_stream) => UnixStreamResult::Ok(unix_stream),
+            None => UnixStreamResult::Err(NetworkError::new(
+                Status::Inval,
+                format!(
+                    "No UnixStream found under index: {}, while deserializing",
+                    index
+                ),
+            )),
+        }
+    }
+
+    fn unix_listener_serialize(&self, unix_listener: UnixListener) -> u32 {
+        self.channel_state.serialize_host_resource(unix_listener) as u32
+    }
+
+    fn unix_listener_deserialize(&self, index: u32) -> UnixListenerResult {
+        match self.channel_state.deserialize_host_resource(index as usize) {
+            Some(unix_listener) => UnixListenerResult::Ok(unix_listener),
+            None => UnixListenerResult::Err(NetworkError::new(
+                Status::Inval,
+                format!(
+                    "No UnixListener found under index: {}, while deserializing",
+                    index
+                ),
+            )),
+        }
+    }
+}
+
+// Turns the result of an operation into the code returned to the guest.
+fn status_code(result: Result<(), io::Error>) -> u32 {
+    match result {
+        Ok(()) => 0,
+        Err(err) => Status::from(err) as u32,
+    }
+}
+
+// Writes the address if the query succeeded, otherwise returns the error code.
+fn write_address_result(
+    address: Result<SocketAddr, io::Error>,
+    addr: Ptr<u8>,
+    addr_len: Ptr<u32>,
+    port: Ptr<u16>,
+    flowinfo: Ptr<u32>,
+    scope_id: Ptr<u32>,
+) -> OptionTrap {
+    match address {
+        Ok(address) => {
+            write_socket_addr(address, addr, addr_len, port, flowinfo, scope_id)?;
+            Ok(0)
+        }
+        Err(err) => Ok(Status::from(err) as u32),
+    }
+}
+
+// Writes the address in the format expected by the guest: the 4 or 16 bytes of the IP address
+// followed by its length, the port and for IPv6 addresses the flow info and scope id.
+fn write_socket_addr(
+    address: SocketAddr,
+    addr: Ptr<u8>,
+    mut addr_len: Ptr<u32>,
+    mut port: Ptr<u16>,
+    mut flowinfo: Ptr<u32>,
+    mut scope_id: Ptr<u32>,
+) -> Result<(), uptown_funk::Trap> {
+    match address {
+        SocketAddr::V4(v4) => {
+            let octets = v4.ip().octets();
+            addr.copy_slice(&octets)?;
+            addr_len.set(octets.len() as u32);
+        }
+        SocketAddr::V6(v6) => {
+            let octets = v6.ip().octets();
+            addr.copy_slice(&octets)?;
+            addr_len.set(octets.len() as u32);
+            flowinfo.set(v6.flowinfo());
+            scope_id.set(v6.scope_id());
+        }
+    }
+    port.set(address.port());
+    Ok(())
+}
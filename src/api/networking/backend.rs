@@ -0,0 +1,63 @@
+use std::io;
+
+use super::tcp::{TcpListener, TcpStream};
+use super::udp::{socket_addr, UdpSocket};
+use super::virtual_net::VirtualInterface;
+
+/// Where the sockets created by a process live.
+#[derive(Clone)]
+pub enum NetworkBackend {
+    /// Sockets of the host's operating system.
+    Host,
+    /// Sockets on an in-memory network, using the address of the interface.
+    Virtual(VirtualInterface),
+}
+
+impl Default for NetworkBackend {
+    fn default() -> Self {
+        NetworkBackend::Host
+    }
+}
+
+impl NetworkBackend {
+    pub async fn tcp_bind(&self, addr: &[u8], port: u16) -> Result<TcpListener, io::Error> {
+        match self {
+            NetworkBackend::Host => TcpListener::bind(addr, port).await,
+            NetworkBackend::Virtual(interface) => {
+                let listener = interface.tcp_bind(socket_addr(addr, port)?)?;
+                Ok(TcpListener::from_virtual(listener))
+            }
+        }
+    }
+
+    pub async fn tcp_connect(&self, addr: &[u8], port: u16) -> Result<TcpStream, io::Error> {
+        match self {
+            NetworkBackend::Host => TcpStream::connect(addr, port).await,
+            NetworkBackend::Virtual(interface) => {
+                let stream = interface.tcp_connect(socket_addr(addr, port)?).await?;
+                Ok(TcpStream::from_virtual(stream))
+            }
+        }
+    }
+
+    pub async fn udp_bind(&self, addr: &[u8], port: u16) -> Result<UdpSocket, io::Error> {
+        match self {
+            NetworkBackend::Host => UdpSocket::bind(addr, port).await,
+            NetworkBackend::Virtual(interface) => {
+                let socket = interface.udp_bind(socket_addr(addr, port)?)?;
+                Ok(UdpSocket::from_virtual(socket))
+            }
+        }
+    }
+
+    /// Unix domain sockets always live on the host, processes on a virtual network can't use them.
+    pub fn check_unix_sockets(&self) -> Result<(), io::Error> {
+        match self {
+            NetworkBackend::Host => Ok(()),
+            NetworkBackend::Virtual(_) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Unix sockets can't be used on a virtual network",
+            )),
+        }
+    }
+}
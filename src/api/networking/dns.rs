@@ -0,0 +1,128 @@
+//! Name resolution backends used by the `resolve` host functions.
+//!
+//! The backend is selected in the `ProcessConfig` of a process:
+//! * `SystemResolver` uses the resolver of the host's operating system.
+//! * `StaticResolver` only knows the hosts added to it and never touches the network, which allows
+//!   tests to use fake names.
+//! * `CachingResolver` remembers the results of another resolver for a fixed time.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub trait NameResolver: Send + Sync {
+    /// Resolves a name in the `HOST:PORT` format to socket addresses.
+    ///
+    /// Resolvers are called from the blocking thread pool, so they are allowed to block.
+    fn resolve(&self, name: &str) -> Result<Vec<SocketAddr>, io::Error>;
+}
+
+/// Forwards lookups to the host's resolver.
+pub struct SystemResolver;
+
+impl NameResolver for SystemResolver {
+    fn resolve(&self, name: &str) -> Result<Vec<SocketAddr>, io::Error> {
+        Ok(name.to_socket_addrs()?.collect())
+    }
+}
+
+/// Resolves names from a fixed map of hosts.
+///
+/// Literal socket addresses are resolved to themselves, unknown hosts fail with `NotFound`.
+#[derive(Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an address of `host`, a host can have multiple addresses.
+    pub fn host<S: Into<String>>(&mut self, host: S, ip: IpAddr) -> &mut Self {
+        self.hosts.entry(host.into()).or_default().push(ip);
+        self
+    }
+}
+
+impl NameResolver for StaticResolver {
+    fn resolve(&self, name: &str) -> Result<Vec<SocketAddr>, io::Error> {
+        if let Ok(address) = name.parse::<SocketAddr>() {
+            return Ok(vec![address]);
+        }
+        let mut parts = name.rsplitn(2, ':');
+        let port = parts.next().and_then(|port| port.parse::<u16>().ok());
+        let (host, port) = match (parts.next(), port) {
+            (Some(host), Some(port)) => (host, port),
+            _ => {
+                let message = format!("`{}` isn't in the HOST:PORT format", name);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+        };
+        match self.hosts.get(host) {
+            Some(ips) => Ok(ips.iter().map(|ip| SocketAddr::new(*ip, port)).collect()),
+            None => {
+                let message = format!("unknown host `{}`", host);
+                Err(io::Error::new(io::ErrorKind::NotFound, message))
+            }
+        }
+    }
+}
+
+/// Caches successful lookups of another resolver for `ttl`.
+///
+/// At most `max_entries` names are kept, once full the entry expiring first is evicted.
+pub struct CachingResolver {
+    inner: Arc<dyn NameResolver>,
+    ttl: Duration,
+    max_entries: usize,
+    cache: Mutex<HashMap<String, (Instant, Vec<SocketAddr>)>>,
+}
+
+impl CachingResolver {
+    pub fn new(inner: Arc<dyn NameResolver>, ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            inner,
+            ttl,
+            max_entries,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl NameResolver for CachingResolver {
+    fn resolve(&self, name: &str) -> Result<Vec<SocketAddr>, io::Error> {
+        let now = Instant::now();
+        if let Some((expires, addresses)) = self.cache.lock().unwrap().get(name) {
+            if *expires > now {
+                return Ok(addresses.clone());
+            }
+        }
+
+        // The lock isn't held during the lookup, so that slow lookups don't block other names.
+        let addresses = self.inner.resolve(name)?;
+
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, (expires, _)| *expires > now);
+        if cache.len() >= self.max_entries && !cache.contains_key(name) {
+            let first_expiring = cache
+                .iter()
+                .min_by_key(|(_, (expires, _))| *expires)
+                .map(|(name, _)| name.clone());
+            match first_expiring {
+                Some(first_expiring) => {
+                    cache.remove(&first_expiring);
+                }
+                // Caching is disabled with `max_entries` of 0.
+                None => return Ok(addresses),
+            }
+        }
+        cache.insert(name.to_string(), (now + self.ttl, addresses.clone()));
+        Ok(addresses)
+    }
+}
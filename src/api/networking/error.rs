@@ -0,0 +1,39 @@
+use std::{cell::RefCell, io, rc::Rc};
+
+use crate::api::wasi::types::Status;
+
+/// Why a networking call or a spawn failed to create a resource.
+#[derive(Clone)]
+pub struct NetworkError {
+    pub status: Status,
+    pub message: String,
+}
+
+impl NetworkError {
+    pub fn new(status: Status, message: String) -> Self {
+        Self { status, message }
+    }
+}
+
+impl From<io::Error> for NetworkError {
+    fn from(err: io::Error) -> Self {
+        let message = err.to_string();
+        Self::new(Status::from(err), message)
+    }
+}
+
+/// The most recent error of a process, shared by its networking and process states.
+///
+/// Like `errno` it's only overwritten by the next failure, successful calls don't clear it.
+#[derive(Clone, Default)]
+pub struct LastError(Rc<RefCell<Option<NetworkError>>>);
+
+impl LastError {
+    pub fn set(&self, err: NetworkError) {
+        *self.0.borrow_mut() = Some(err);
+    }
+
+    pub fn get(&self) -> Option<NetworkError> {
+        self.0.borrow().clone()
+    }
+}
@@ -1,5 +1,20 @@
 pub mod api;
+pub mod backend;
+pub mod dns;
+pub mod error;
+pub mod policy;
 pub mod resolver;
 pub mod tcp;
+pub mod timeout;
+pub mod tls;
+pub mod udp;
+#[cfg(unix)]
+pub mod unix;
+pub mod virtual_net;
 
 pub use api::TcpState;
+#[cfg(unix)]
+pub use api::UnixState;
+pub use backend::NetworkBackend;
+pub use policy::{AddressRule, NetworkPolicy};
+pub use virtual_net::VirtualNetwork;
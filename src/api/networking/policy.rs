@@ -0,0 +1,231 @@
+use anyhow::{anyhow, Error};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+use super::udp::socket_addr;
+
+/// Network access a process is allowed to, checked before any socket is created.
+///
+/// By default everything is allowed. Once a bind or connect rule is added only addresses matching
+/// one of the rules are allowed.
+#[derive(Clone)]
+pub struct NetworkPolicy {
+    /// Addresses listeners and UDP sockets can be bound to, `None` allows all of them.
+    pub bind: Option<Vec<AddressRule>>,
+    /// Addresses TCP streams can connect and UDP datagrams can be sent to, `None` allows all of
+    /// them.
+    pub connect: Option<Vec<AddressRule>>,
+    /// Can host names be resolved? Literal socket addresses are always accepted by `resolve`.
+    pub dns: bool,
+    /// Can Unix domain sockets be bound or connected to?
+    pub unix_sockets: bool,
+}
+
+impl Default for NetworkPolicy {
+    fn default() -> Self {
+        Self {
+            bind: None,
+            connect: None,
+            dns: true,
+            unix_sockets: true,
+        }
+    }
+}
+
+impl NetworkPolicy {
+    /// Denies all network access, rules can be added afterwards to allow parts of it again.
+    pub fn deny_all() -> Self {
+        Self {
+            bind: Some(Vec::new()),
+            connect: Some(Vec::new()),
+            dns: false,
+            unix_sockets: false,
+        }
+    }
+
+    /// Allows binding to addresses matching `rule`.
+    pub fn allow_bind(&mut self, rule: AddressRule) -> &mut Self {
+        self.bind.get_or_insert_with(Vec::new).push(rule);
+        self
+    }
+
+    /// Allows connecting and sending to addresses matching `rule`.
+    pub fn allow_connect(&mut self, rule: AddressRule) -> &mut Self {
+        self.connect.get_or_insert_with(Vec::new).push(rule);
+        self
+    }
+
+    pub fn check_bind(&self, addr: &[u8], port: u16) -> Result<(), io::Error> {
+        let address = socket_addr(addr, port)?;
+        if allows(&self.bind, &address) {
+            Ok(())
+        } else {
+            Err(denied(format!("binding to {}", address)))
+        }
+    }
+
+    pub fn check_connect(&self, addr: &[u8], port: u16) -> Result<(), io::Error> {
+        let address = socket_addr(addr, port)?;
+        if allows(&self.connect, &address) {
+            Ok(())
+        } else {
+            Err(denied(format!("connecting to {}", address)))
+        }
+    }
+
+    pub fn check_dns(&self, name: &str) -> Result<(), io::Error> {
+        if self.dns || name.parse::<SocketAddr>().is_ok() {
+            Ok(())
+        } else {
+            Err(denied(format!("resolving {}", name)))
+        }
+    }
+
+    pub fn check_unix_sockets(&self, path: &str) -> Result<(), io::Error> {
+        if self.unix_sockets {
+            Ok(())
+        } else {
+            Err(denied(format!("using the Unix socket {}", path)))
+        }
+    }
+}
+
+fn allows(rules: &Option<Vec<AddressRule>>, address: &SocketAddr) -> bool {
+    match rules {
+        Some(rules) => rules.iter().any(|rule| rule.matches(address)),
+        None => true,
+    }
+}
+
+fn denied(action: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{} is not allowed by the network policy", action),
+    )
+}
+
+/// A network in CIDR notation and a range of ports.
+///
+/// Parsed from `IP[/PREFIX][:PORT[-PORT]]`, IPv6 networks need to be put in brackets if followed by
+/// ports, e.g. `10.0.0.0/8`, `127.0.0.1:8080`, `0.0.0.0/0:1024-65535` or `[fd00::/8]:443`. Without
+/// a prefix length only the address itself matches and without ports all of them match.
+#[derive(Clone)]
+pub struct AddressRule {
+    network: IpAddr,
+    prefix_len: u8,
+    ports: RangeInclusive<u16>,
+}
+
+impl AddressRule {
+    /// Fails if `prefix_len` is longer than the address of `network`.
+    pub fn new(network: IpAddr, prefix_len: u8, ports: RangeInclusive<u16>) -> Result<Self, Error> {
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_prefix_len {
+            return Err(anyhow!("prefix length {} is too long", prefix_len));
+        }
+        Ok(Self {
+            network,
+            prefix_len,
+            ports,
+        })
+    }
+
+    /// IPv4 addresses mapped into IPv6 are matched as IPv4 addresses, so that they can't be used to
+    /// get around IPv4 rules.
+    pub fn matches(&self, address: &SocketAddr) -> bool {
+        if !self.ports.contains(&address.port()) {
+            return false;
+        }
+        match (self.network, unmap(address.ip())) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(network), u32::from(ip), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(network), u128::from(ip), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn unmap(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, hi, lo] => {
+                let [a, b] = hi.to_be_bytes();
+                let [c, d] = lo.to_be_bytes();
+                IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+            }
+            _ => ip,
+        },
+        ip => ip,
+    }
+}
+
+fn prefix_matches<N>(network: N, ip: N, prefix_len: u8) -> bool
+where
+    N: Into<u128>,
+{
+    let bits = std::mem::size_of::<N>() as u32 * 8;
+    let (network, ip) = (network.into(), ip.into());
+    // A shift by the full width would overflow, a prefix of length 0 matches everything.
+    match bits.checked_sub(prefix_len as u32) {
+        Some(shift) => shift >= bits || network >> shift == ip >> shift,
+        None => false,
+    }
+}
+
+impl FromStr for AddressRule {
+    type Err = Error;
+
+    fn from_str(rule: &str) -> Result<Self, Error> {
+        // Split off the ports, IPv6 networks contain colons themselves and need brackets for it.
+        let (network, ports) = if let Some(rest) = rule.strip_prefix('[') {
+            let end = rest.find(']').ok_or_else(|| anyhow!("missing `]`"))?;
+            match &rest[end + 1..] {
+                "" => (&rest[..end], None),
+                ports => match ports.strip_prefix(':') {
+                    Some(ports) => (&rest[..end], Some(ports)),
+                    None => return Err(anyhow!("expected `:` after `]`")),
+                },
+            }
+        } else if rule.matches(':').count() == 1 {
+            let mut parts = rule.splitn(2, ':');
+            (parts.next().unwrap(), parts.next())
+        } else {
+            (rule, None)
+        };
+
+        let ports = match ports {
+            None => 0..=u16::MAX,
+            Some(ports) => {
+                let mut range = ports.splitn(2, '-');
+                let start: u16 = range.next().unwrap().parse()?;
+                let end: u16 = match range.next() {
+                    Some(end) => end.parse()?,
+                    None => start,
+                };
+                if start > end {
+                    return Err(anyhow!("empty port range `{}`", ports));
+                }
+                start..=end
+            }
+        };
+
+        let mut parts = network.splitn(2, '/');
+        let network: IpAddr = match parts.next().unwrap() {
+            ip if ip.contains(':') => IpAddr::V6(ip.parse::<Ipv6Addr>()?),
+            ip => IpAddr::V4(ip.parse::<Ipv4Addr>()?),
+        };
+        let prefix_len = match parts.next() {
+            Some(prefix_len) => prefix_len.parse()?,
+            None if network.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(network, prefix_len, ports)
+    }
+}
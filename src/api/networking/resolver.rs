@@ -1,7 +1,9 @@
-use std::{cell::RefCell, io, rc::Rc, vec::IntoIter};
+use std::{cell::RefCell, io, rc::Rc, sync::Arc, vec::IntoIter};
 use uptown_funk::{Executor, FromWasm, ToWasm};
 
 use super::api::TcpState;
+use super::dns::NameResolver;
+use super::error::NetworkError;
 
 #[derive(Clone)]
 pub struct Resolver {
@@ -9,8 +11,9 @@ pub struct Resolver {
 }
 
 impl Resolver {
-    pub async fn resolve(name: &str) -> Result<Self, io::Error> {
-        let resolved = smol::net::resolve(name).await?;
+    pub async fn resolve(resolver: Arc<dyn NameResolver>, name: &str) -> Result<Self, io::Error> {
+        let name = name.to_string();
+        let resolved = smol::unblock(move || resolver.resolve(&name)).await?;
         Ok(Resolver {
             iter: Rc::new(RefCell::new(resolved.into_iter())),
         })
@@ -41,7 +44,7 @@ impl FromWasm<&mut TcpState> for Resolver {
 
 pub enum ResolverResult {
     Ok(Resolver),
-    Err(String),
+    Err(NetworkError),
 }
 
 impl ToWasm<&mut TcpState> for ResolverResult {
@@ -50,7 +53,10 @@ impl ToWasm<&mut TcpState> for ResolverResult {
     fn to(state: &mut TcpState, _: &impl Executor, result: Self) -> Result<u32, uptown_funk::Trap> {
         match result {
             ResolverResult::Ok(resolver) => Ok(state.resolvers.add(resolver)),
-            ResolverResult::Err(_err) => Ok(0),
+            ResolverResult::Err(err) => {
+                state.last_error.set(err);
+                Ok(0)
+            }
         }
     }
 }
@@ -1,10 +1,31 @@
-use std::{convert::TryInto, io};
+use smol::future::poll_fn;
+use smol::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use socket2::{SockRef, TcpKeepalive};
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{self, IoSlice, IoSliceMut},
+    net::{Shutdown, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
 use uptown_funk::{Executor, FromWasm, ToWasm};
 
 use super::api::TcpState;
+use super::error::NetworkError;
+use super::timeout::with_timeout;
+use super::virtual_net::{VirtualTcpListener, VirtualTcpStream};
 
+/// A listener of the host or of a virtual network.
 #[derive(Clone)]
-pub struct TcpListener(smol::net::TcpListener);
+pub struct TcpListener(Listener);
+
+#[derive(Clone)]
+enum Listener {
+    Host(smol::net::TcpListener),
+    Virtual(VirtualTcpListener),
+}
 
 impl TcpListener {
     pub async fn bind(addr: &[u8], port: u16) -> Result<Self, io::Error> {
@@ -13,7 +34,7 @@ impl TcpListener {
                 let addr: [u8; 4] = addr.try_into().unwrap();
                 let addr = smol::net::Ipv4Addr::from(addr);
                 match smol::net::TcpListener::bind((addr, port)).await {
-                    Ok(tcp_listener) => Ok(Self(tcp_listener)),
+                    Ok(tcp_listener) => Ok(Self(Listener::Host(tcp_listener))),
                     Err(err) => Err(err),
                 }
             }
@@ -21,7 +42,7 @@ impl TcpListener {
                 let addr: [u8; 16] = addr.try_into().unwrap();
                 let addr = smol::net::Ipv6Addr::from(addr);
                 match smol::net::TcpListener::bind((addr, port)).await {
-                    Ok(tcp_listener) => Ok(Self(tcp_listener)),
+                    Ok(tcp_listener) => Ok(Self(Listener::Host(tcp_listener))),
                     Err(err) => Err(err),
                 }
             }
@@ -29,9 +50,33 @@ impl TcpListener {
         }
     }
 
+    /// Wraps an already bound listener of the host.
+    pub fn from_std(listener: std::net::TcpListener) -> Result<Self, io::Error> {
+        let listener = smol::net::TcpListener::try_from(listener)?;
+        Ok(Self(Listener::Host(listener)))
+    }
+
+    pub fn from_virtual(listener: VirtualTcpListener) -> Self {
+        Self(Listener::Virtual(listener))
+    }
+
     pub async fn accept(&self) -> Result<TcpStream, io::Error> {
-        let (stream, _address) = self.0.accept().await?;
-        Ok(TcpStream(stream))
+        match &self.0 {
+            Listener::Host(listener) => {
+                let (stream, _address) = listener.accept().await?;
+                Ok(TcpStream::new(stream))
+            }
+            Listener::Virtual(listener) => Ok(TcpStream::from_virtual(listener.accept().await?)),
+        }
+    }
+
+    /// Returns the address the listener is bound to, including the port picked by the OS when
+    /// binding to port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        match &self.0 {
+            Listener::Host(listener) => listener.local_addr(),
+            Listener::Virtual(listener) => Ok(listener.local_addr()),
+        }
     }
 }
 
@@ -55,7 +100,7 @@ impl FromWasm<&mut TcpState> for TcpListener {
 
 pub enum TcpListenerResult {
     Ok(TcpListener),
-    Err(String),
+    Err(NetworkError),
 }
 
 impl ToWasm<&mut TcpState> for TcpListenerResult {
@@ -64,22 +109,53 @@ impl ToWasm<&mut TcpState> for TcpListenerResult {
     fn to(state: &mut TcpState, _: &impl Executor, result: Self) -> Result<u32, uptown_funk::Trap> {
         match result {
             TcpListenerResult::Ok(listener) => Ok(state.listeners.add(listener)),
-            TcpListenerResult::Err(_err) => Ok(0),
+            TcpListenerResult::Err(err) => {
+                state.last_error.set(err);
+                Ok(0)
+            }
         }
     }
 }
 
+/// Read and write timeouts of a stream, `None` waits forever.
+#[derive(Clone, Copy, Default)]
+struct Timeouts {
+    read: Option<Duration>,
+    write: Option<Duration>,
+}
+
+/// A TCP stream of the host or of a virtual network, the timeouts are shared by all clones of it.
+#[derive(Clone)]
+pub struct TcpStream(Stream, Arc<Mutex<Timeouts>>);
+
 #[derive(Clone)]
-pub struct TcpStream(pub smol::net::TcpStream);
+enum Stream {
+    Host(smol::net::TcpStream),
+    Virtual(VirtualTcpStream),
+}
 
 impl TcpStream {
+    pub fn new(stream: smol::net::TcpStream) -> Self {
+        Self(
+            Stream::Host(stream),
+            Arc::new(Mutex::new(Timeouts::default())),
+        )
+    }
+
+    pub fn from_virtual(stream: VirtualTcpStream) -> Self {
+        Self(
+            Stream::Virtual(stream),
+            Arc::new(Mutex::new(Timeouts::default())),
+        )
+    }
+
     pub async fn connect(addr: &[u8], port: u16) -> Result<Self, io::Error> {
         match addr.len() {
             4 => {
                 let addr: [u8; 4] = addr.try_into().unwrap();
                 let addr = smol::net::Ipv4Addr::from(addr);
                 match smol::net::TcpStream::connect((addr, port)).await {
-                    Ok(tcp_stream) => Ok(Self(tcp_stream)),
+                    Ok(tcp_stream) => Ok(Self::new(tcp_stream)),
                     Err(err) => Err(err),
                 }
             }
@@ -87,13 +163,175 @@ impl TcpStream {
                 let addr: [u8; 16] = addr.try_into().unwrap();
                 let addr = smol::net::Ipv6Addr::from(addr);
                 match smol::net::TcpStream::connect((addr, port)).await {
-                    Ok(tcp_stream) => Ok(Self(tcp_stream)),
+                    Ok(tcp_stream) => Ok(Self::new(tcp_stream)),
                     Err(err) => Err(err),
                 }
             }
             _ => Err(io::Error::from_raw_os_error(22)), // Wrong argument error code.
         }
     }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        self.1.lock().unwrap().read = timeout;
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) {
+        self.1.lock().unwrap().write = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.1.lock().unwrap().read
+    }
+
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.1.lock().unwrap().write
+    }
+
+    // The guest memory behind `iovs` only lives as long as the call, so the read is polled directly
+    // instead of going through `AsyncReadExt`, which would tie it to the lifetime of the stream.
+    pub async fn read_vectored(&self, iovs: &mut [IoSliceMut<'_>]) -> Result<usize, io::Error> {
+        let timeout = self.1.lock().unwrap().read;
+        let mut stream = self.clone();
+        with_timeout(
+            timeout,
+            poll_fn(|cx| Pin::new(&mut stream).poll_read_vectored(cx, iovs)),
+        )
+        .await
+    }
+
+    /// Reads into `buf` without removing the data from the socket.
+    pub async fn peek(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let timeout = self.1.lock().unwrap().read;
+        match &self.0 {
+            Stream::Host(stream) => with_timeout(timeout, stream.peek(buf)).await,
+            Stream::Virtual(stream) => with_timeout(timeout, stream.peek(buf)).await,
+        }
+    }
+
+    pub async fn write_vectored(&self, ciovs: &[IoSlice<'_>]) -> Result<usize, io::Error> {
+        let timeout = self.1.lock().unwrap().write;
+        let mut stream = self.clone();
+        with_timeout(timeout, AsyncWriteExt::write_vectored(&mut stream, ciovs)).await
+    }
+
+    pub async fn flush(&self) -> Result<(), io::Error> {
+        let mut stream = self.clone();
+        AsyncWriteExt::flush(&mut stream).await
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, io::Error> {
+        match &self.0 {
+            Stream::Host(stream) => stream.peer_addr(),
+            Stream::Virtual(stream) => Ok(stream.peer_addr()),
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        match &self.0 {
+            Stream::Host(stream) => stream.local_addr(),
+            Stream::Virtual(stream) => Ok(stream.local_addr()),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<(), io::Error> {
+        match &self.0 {
+            Stream::Host(stream) => stream.shutdown(how),
+            Stream::Virtual(stream) => {
+                stream.shutdown(how);
+                Ok(())
+            }
+        }
+    }
+
+    // Socket options don't have any effect on streams of a virtual network.
+
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<(), io::Error> {
+        match &self.0 {
+            Stream::Host(stream) => stream.set_nodelay(nodelay),
+            Stream::Virtual(_) => Ok(()),
+        }
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> Result<(), io::Error> {
+        match &self.0 {
+            Stream::Host(stream) => stream.set_ttl(ttl),
+            Stream::Virtual(_) => Ok(()),
+        }
+    }
+
+    /// Enables keepalive probes after the connection was idle for `idle`, or disables them if
+    /// `None`.
+    pub fn set_keepalive(&self, idle: Option<Duration>) -> Result<(), io::Error> {
+        let socket = match &self.0 {
+            Stream::Host(stream) => SockRef::from(stream),
+            Stream::Virtual(_) => return Ok(()),
+        };
+        match idle {
+            Some(idle) => socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle)),
+            None => socket.set_keepalive(false),
+        }
+    }
+
+    /// Sets how long closing the stream waits for unsent data to be transmitted. With a linger
+    /// of zero the connection is reset on close.
+    pub fn set_linger(&self, linger: Option<Duration>) -> Result<(), io::Error> {
+        match &self.0 {
+            Stream::Host(stream) => SockRef::from(stream).set_linger(linger),
+            Stream::Virtual(_) => Ok(()),
+        }
+    }
+}
+
+// Allows using the stream as transport of a TLS session, `TlsStream` applies the timeouts itself.
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        match &mut self.get_mut().0 {
+            Stream::Host(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Virtual(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        match &mut self.get_mut().0 {
+            Stream::Host(stream) => Pin::new(stream).poll_read_vectored(cx, bufs),
+            Stream::Virtual(stream) => Pin::new(stream).poll_read_vectored(cx, bufs),
+        }
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        match &mut self.get_mut().0 {
+            Stream::Host(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Virtual(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        match &mut self.get_mut().0 {
+            Stream::Host(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Virtual(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        match &mut self.get_mut().0 {
+            Stream::Host(stream) => Pin::new(stream).poll_close(cx),
+            Stream::Virtual(stream) => Pin::new(stream).poll_close(cx),
+        }
+    }
 }
 
 impl FromWasm<&mut TcpState> for TcpStream {
@@ -113,9 +351,32 @@ impl FromWasm<&mut TcpState> for TcpStream {
         }
     }
 }
+
+/// A TCP stream that is taken out of the resource table when passed to a host function, so that
+/// its id can't be used anymore afterwards.
+pub struct TakenTcpStream(pub TcpStream);
+
+impl FromWasm<&mut TcpState> for TakenTcpStream {
+    type From = u32;
+
+    fn from(
+        state: &mut TcpState,
+        _: &impl Executor,
+        tcp_stream_id: u32,
+    ) -> Result<Self, uptown_funk::Trap>
+    where
+        Self: Sized,
+    {
+        match state.streams.remove(tcp_stream_id) {
+            Some(tcp_stream) => Ok(TakenTcpStream(tcp_stream)),
+            None => Err(uptown_funk::Trap::new("TcpStream not found")),
+        }
+    }
+}
+
 pub enum TcpStreamResult {
     Ok(TcpStream),
-    Err(String),
+    Err(NetworkError),
 }
 
 impl ToWasm<&mut TcpState> for TcpStreamResult {
@@ -124,7 +385,10 @@ impl ToWasm<&mut TcpState> for TcpStreamResult {
     fn to(state: &mut TcpState, _: &impl Executor, result: Self) -> Result<u32, uptown_funk::Trap> {
         match result {
             TcpStreamResult::Ok(stream) => Ok(state.streams.add(stream)),
-            TcpStreamResult::Err(_err) => Ok(0),
+            TcpStreamResult::Err(err) => {
+                state.last_error.set(err);
+                Ok(0)
+            }
         }
     }
 }
@@ -0,0 +1,31 @@
+use smol::future::FutureExt;
+use smol::Timer;
+use std::{future::Future, io, time::Duration};
+
+/// Races `future` against a timer, failing with `TimedOut` if the timer fires first.
+///
+/// Without a timeout the future is awaited until it completes.
+pub async fn with_timeout<T, F>(timeout: Option<Duration>, future: F) -> Result<T, io::Error>
+where
+    F: Future<Output = Result<T, io::Error>>,
+{
+    match timeout {
+        Some(timeout) => {
+            future
+                .or(async {
+                    Timer::after(timeout).await;
+                    Err(io::ErrorKind::TimedOut.into())
+                })
+                .await
+        }
+        None => future.await,
+    }
+}
+
+/// Turns a timeout in milliseconds passed by the guest into a duration, 0 means no timeout.
+pub fn from_millis(timeout_ms: u64) -> Option<Duration> {
+    match timeout_ms {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
@@ -0,0 +1,236 @@
+use futures_rustls::rustls::{internal::pemfile, ClientConfig, NoClientAuth, ServerConfig};
+use futures_rustls::webpki::DNSNameRef;
+use futures_rustls::{TlsAcceptor, TlsConnector};
+use smol::future::poll_fn;
+use smol::io::{AsyncRead, AsyncWrite};
+use std::{
+    io::{self, IoSlice, IoSliceMut},
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+use uptown_funk::{Executor, FromWasm, ToWasm};
+
+use super::api::TcpState;
+use super::error::NetworkError;
+use super::tcp::TcpStream;
+use super::timeout::with_timeout;
+
+/// Certificates trusted when connecting to a TLS server.
+#[derive(Clone)]
+pub struct TlsClientConfig(Arc<ClientConfig>);
+
+impl TlsClientConfig {
+    /// Trusts the PEM encoded certificates in `ca_pem` or the Mozilla root certificates if `None`.
+    pub fn new(ca_pem: Option<&[u8]>) -> Result<Self, io::Error> {
+        let mut config = ClientConfig::new();
+        match ca_pem {
+            Some(mut ca_pem) => match config.root_store.add_pem_file(&mut ca_pem) {
+                Ok((valid, _invalid)) if valid > 0 => (),
+                _ => return Err(invalid_pem("no valid CA certificate found")),
+            },
+            None => config
+                .root_store
+                .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS),
+        }
+        Ok(Self(Arc::new(config)))
+    }
+}
+
+/// Certificate chain and private key presented by a TLS server.
+#[derive(Clone)]
+pub struct TlsServerConfig(Arc<ServerConfig>);
+
+impl TlsServerConfig {
+    /// Uses the PEM encoded certificate chain and the PKCS #8 or RSA private key.
+    pub fn new(mut cert_pem: &[u8], key_pem: &[u8]) -> Result<Self, io::Error> {
+        let certs =
+            pemfile::certs(&mut cert_pem).map_err(|_| invalid_pem("invalid certificate"))?;
+        let mut keys = pemfile::pkcs8_private_keys(&mut &key_pem[..])
+            .map_err(|_| invalid_pem("invalid private key"))?;
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut &key_pem[..])
+                .map_err(|_| invalid_pem("invalid private key"))?;
+        }
+        let key = keys
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid_pem("no private key found"))?;
+
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config
+            .set_single_cert(certs, key)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        Ok(Self(Arc::new(config)))
+    }
+}
+
+fn invalid_pem(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// An encrypted stream on top of a `TcpStream`.
+///
+/// Reads and writes need exclusive access to the TLS session, so it's shared by all clones of the
+/// stream behind a lock. The lock is only held while a read or write is polled, never while it
+/// waits for the socket, so one clone can wait for data while another one writes.
+///
+/// The read and write timeouts of the `TcpStream` also apply to the TLS stream.
+#[derive(Clone)]
+pub struct TlsStream(Arc<Mutex<futures_rustls::TlsStream<TcpStream>>>, TcpStream);
+
+impl TlsStream {
+    /// Performs the client handshake, verifying that the server's certificate is valid for `domain`.
+    ///
+    /// Fails with `TimedOut` if the server doesn't answer within the read timeout of `tcp_stream`.
+    pub async fn connect(
+        config: &TlsClientConfig,
+        tcp_stream: TcpStream,
+        domain: &str,
+    ) -> Result<Self, io::Error> {
+        let domain = DNSNameRef::try_from_ascii_str(domain)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let handshake = TlsConnector::from(config.0.clone()).connect(domain, tcp_stream.clone());
+        let stream = with_timeout(tcp_stream.read_timeout(), handshake).await?;
+        Ok(Self(Arc::new(Mutex::new(stream.into())), tcp_stream))
+    }
+
+    /// Performs the server handshake.
+    ///
+    /// Fails with `TimedOut` if the client doesn't answer within the read timeout of `tcp_stream`.
+    pub async fn accept(
+        config: &TlsServerConfig,
+        tcp_stream: TcpStream,
+    ) -> Result<Self, io::Error> {
+        let handshake = TlsAcceptor::from(config.0.clone()).accept(tcp_stream.clone());
+        let stream = with_timeout(tcp_stream.read_timeout(), handshake).await?;
+        Ok(Self(Arc::new(Mutex::new(stream.into())), tcp_stream))
+    }
+
+    // Reads and writes are polled directly for the same reason as in `TcpStream::read_vectored`.
+    pub async fn read_vectored(&self, iovs: &mut [IoSliceMut<'_>]) -> Result<usize, io::Error> {
+        let read = poll_fn(|cx| {
+            let mut stream = self.0.lock().unwrap();
+            Pin::new(&mut *stream).poll_read_vectored(cx, iovs)
+        });
+        with_timeout(self.1.read_timeout(), read).await
+    }
+
+    pub async fn write_vectored(&self, ciovs: &[IoSlice<'_>]) -> Result<usize, io::Error> {
+        let write = poll_fn(|cx| {
+            let mut stream = self.0.lock().unwrap();
+            Pin::new(&mut *stream).poll_write_vectored(cx, ciovs)
+        });
+        with_timeout(self.1.write_timeout(), write).await
+    }
+
+    pub async fn flush(&self) -> Result<(), io::Error> {
+        let flush = poll_fn(|cx| {
+            let mut stream = self.0.lock().unwrap();
+            Pin::new(&mut *stream).poll_flush(cx)
+        });
+        with_timeout(self.1.write_timeout(), flush).await
+    }
+}
+
+impl FromWasm<&mut TcpState> for TlsClientConfig {
+    type From = u32;
+
+    fn from(state: &mut TcpState, _: &impl Executor, id: u32) -> Result<Self, uptown_funk::Trap>
+    where
+        Self: Sized,
+    {
+        match state.tls_client_configs.get(id) {
+            Some(config) => Ok(config.clone()),
+            None => Err(uptown_funk::Trap::new("TlsClientConfig not found")),
+        }
+    }
+}
+
+pub enum TlsClientConfigResult {
+    Ok(TlsClientConfig),
+    Err(NetworkError),
+}
+
+impl ToWasm<&mut TcpState> for TlsClientConfigResult {
+    type To = u32;
+
+    fn to(state: &mut TcpState, _: &impl Executor, result: Self) -> Result<u32, uptown_funk::Trap> {
+        match result {
+            TlsClientConfigResult::Ok(config) => Ok(state.tls_client_configs.add(config)),
+            TlsClientConfigResult::Err(err) => {
+                state.last_error.set(err);
+                Ok(0)
+            }
+        }
+    }
+}
+
+impl FromWasm<&mut TcpState> for TlsServerConfig {
+    type From = u32;
+
+    fn from(state: &mut TcpState, _: &impl Executor, id: u32) -> Result<Self, uptown_funk::Trap>
+    where
+        Self: Sized,
+    {
+        match state.tls_server_configs.get(id) {
+            Some(config) => Ok(config.clone()),
+            None => Err(uptown_funk::Trap::new("TlsServerConfig not found")),
+        }
+    }
+}
+
+pub enum TlsServerConfigResult {
+    Ok(TlsServerConfig),
+    Err(NetworkError),
+}
+
+impl ToWasm<&mut TcpState> for TlsServerConfigResult {
+    type To = u32;
+
+    fn to(state: &mut TcpState, _: &impl Executor, result: Self) -> Result<u32, uptown_funk::Trap> {
+        match result {
+            TlsServerConfigResult::Ok(config) => Ok(state.tls_server_configs.add(config)),
+            TlsServerConfigResult::Err(err) => {
+                state.last_error.set(err);
+                Ok(0)
+            }
+        }
+    }
+}
+
+impl FromWasm<&mut TcpState> for TlsStream {
+    type From = u32;
+
+    fn from(
+        state: &mut TcpState,
+        _: &impl Executor,
+        tls_stream_id: u32,
+    ) -> Result<Self, uptown_funk::Trap>
+    where
+        Self: Sized,
+    {
+        match state.tls_streams.get(tls_stream_id) {
+            Some(tls_stream) => Ok(tls_stream.clone()),
+            None => Err(uptown_funk::Trap::new("TlsStream not found")),
+        }
+    }
+}
+
+pub enum TlsStreamResult {
+    Ok(TlsStream),
+    Err(NetworkError),
+}
+
+impl ToWasm<&mut TcpState> for TlsStreamResult {
+    type To = u32;
+
+    fn to(state: &mut TcpState, _: &impl Executor, result: Self) -> Result<u32, uptown_funk::Trap> {
+        match result {
+            TlsStreamResult::Ok(stream) => Ok(state.tls_streams.add(stream)),
+            TlsStreamResult::Err(err) => {
+                state.last_error.set(err);
+                Ok(0)
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    convert::TryInto,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use uptown_funk::{Executor, FromWasm, ToWasm};
+
+use super::api::TcpState;
+use super::error::NetworkError;
+use super::virtual_net::VirtualUdpSocket;
+
+/// A UDP socket of the host or of a virtual network.
+#[derive(Clone)]
+pub struct UdpSocket(Socket);
+
+#[derive(Clone)]
+enum Socket {
+    Host(smol::net::UdpSocket),
+    Virtual(VirtualUdpSocket),
+}
+
+impl UdpSocket {
+    pub async fn bind(addr: &[u8], port: u16) -> Result<Self, io::Error> {
+        let addr = socket_addr(addr, port)?;
+        let socket = smol::net::UdpSocket::bind(addr).await?;
+        Ok(Self(Socket::Host(socket)))
+    }
+
+    pub fn from_virtual(socket: VirtualUdpSocket) -> Self {
+        Self(Socket::Virtual(socket))
+    }
+
+    /// Sets the default destination of `send` and only accepts datagrams from it in `recv`.
+    pub async fn connect(&self, addr: &[u8], port: u16) -> Result<(), io::Error> {
+        let addr = socket_addr(addr, port)?;
+        match &self.0 {
+            Socket::Host(socket) => socket.connect(addr).await,
+            Socket::Virtual(socket) => {
+                socket.connect(addr);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        match &self.0 {
+            Socket::Host(socket) => socket.local_addr(),
+            Socket::Virtual(socket) => Ok(socket.local_addr()),
+        }
+    }
+
+    pub async fn send(&self, buf: &[u8]) -> Result<usize, io::Error> {
+        match &self.0 {
+            Socket::Host(socket) => socket.send(buf).await,
+            Socket::Virtual(socket) => socket.send(buf),
+        }
+    }
+
+    pub async fn send_to(&self, buf: &[u8], addr: &[u8], port: u16) -> Result<usize, io::Error> {
+        let addr = socket_addr(addr, port)?;
+        match &self.0 {
+            Socket::Host(socket) => socket.send_to(buf, addr).await,
+            Socket::Virtual(socket) => socket.send_to(buf, addr),
+        }
+    }
+
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        match &self.0 {
+            Socket::Host(socket) => socket.recv(buf).await,
+            Socket::Virtual(socket) => Ok(socket.recv_from(buf).await?.0),
+        }
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), io::Error> {
+        match &self.0 {
+            Socket::Host(socket) => socket.recv_from(buf).await,
+            Socket::Virtual(socket) => socket.recv_from(buf).await,
+        }
+    }
+
+    // Socket options don't have any effect on sockets of a virtual network.
+
+    pub fn set_broadcast(&self, broadcast: bool) -> Result<(), io::Error> {
+        match &self.0 {
+            Socket::Host(socket) => socket.set_broadcast(broadcast),
+            Socket::Virtual(_) => Ok(()),
+        }
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> Result<(), io::Error> {
+        match &self.0 {
+            Socket::Host(socket) => socket.set_ttl(ttl),
+            Socket::Virtual(_) => Ok(()),
+        }
+    }
+
+    pub fn join_multicast_v4(&self, multiaddr: &[u8], interface: &[u8]) -> Result<(), io::Error> {
+        let (multiaddr, interface) = (ipv4_addr(multiaddr)?, ipv4_addr(interface)?);
+        match &self.0 {
+            Socket::Host(socket) => socket.join_multicast_v4(multiaddr, interface),
+            Socket::Virtual(_) => Ok(()),
+        }
+    }
+
+    pub fn leave_multicast_v4(&self, multiaddr: &[u8], interface: &[u8]) -> Result<(), io::Error> {
+        let (multiaddr, interface) = (ipv4_addr(multiaddr)?, ipv4_addr(interface)?);
+        match &self.0 {
+            Socket::Host(socket) => socket.leave_multicast_v4(multiaddr, interface),
+            Socket::Virtual(_) => Ok(()),
+        }
+    }
+
+    pub fn join_multicast_v6(&self, multiaddr: &[u8], interface: u32) -> Result<(), io::Error> {
+        let multiaddr = ipv6_addr(multiaddr)?;
+        match &self.0 {
+            Socket::Host(socket) => socket.join_multicast_v6(&multiaddr, interface),
+            Socket::Virtual(_) => Ok(()),
+        }
+    }
+
+    pub fn leave_multicast_v6(&self, multiaddr: &[u8], interface: u32) -> Result<(), io::Error> {
+        let multiaddr = ipv6_addr(multiaddr)?;
+        match &self.0 {
+            Socket::Host(socket) => socket.leave_multicast_v6(&multiaddr, interface),
+            Socket::Virtual(_) => Ok(()),
+        }
+    }
+
+    pub fn set_multicast_loop_v4(&self, multicast_loop: bool) -> Result<(), io::Error> {
+        match &self.0 {
+            Socket::Host(socket) => socket.set_multicast_loop_v4(multicast_loop),
+            Socket::Virtual(_) => Ok(()),
+        }
+    }
+
+    pub fn set_multicast_loop_v6(&self, multicast_loop: bool) -> Result<(), io::Error> {
+        match &self.0 {
+            Socket::Host(socket) => socket.set_multicast_loop_v6(multicast_loop),
+            Socket::Virtual(_) => Ok(()),
+        }
+    }
+
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> Result<(), io::Error> {
+        match &self.0 {
+            Socket::Host(socket) => socket.set_multicast_ttl_v4(ttl),
+            Socket::Virtual(_) => Ok(()),
+        }
+    }
+}
+
+fn ip_addr(addr: &[u8]) -> Result<IpAddr, io::Error> {
+    match addr.len() {
+        4 => {
+            let addr: [u8; 4] = addr.try_into().unwrap();
+            Ok(Ipv4Addr::from(addr).into())
+        }
+        16 => {
+            let addr: [u8; 16] = addr.try_into().unwrap();
+            Ok(Ipv6Addr::from(addr).into())
+        }
+        _ => Err(io::ErrorKind::InvalidInput.into()),
+    }
+}
+
+pub(super) fn socket_addr(addr: &[u8], port: u16) -> Result<SocketAddr, io::Error> {
+    Ok(SocketAddr::new(ip_addr(addr)?, port))
+}
+
+fn ipv4_addr(addr: &[u8]) -> Result<Ipv4Addr, io::Error> {
+    match ip_addr(addr)? {
+        IpAddr::V4(addr) => Ok(addr),
+        IpAddr::V6(_) => Err(io::ErrorKind::InvalidInput.into()),
+    }
+}
+
+fn ipv6_addr(addr: &[u8]) -> Result<Ipv6Addr, io::Error> {
+    match ip_addr(addr)? {
+        IpAddr::V6(addr) => Ok(addr),
+        IpAddr::V4(_) => Err(io::ErrorKind::InvalidInput.into()),
+    }
+}
+
+impl FromWasm<&mut TcpState> for UdpSocket {
+    type From = u32;
+
+    fn from(
+        state: &mut TcpState,
+        _: &impl Executor,
+        udp_socket_id: u32,
+    ) -> Result<Self, uptown_funk::Trap>
+    where
+        Self: Sized,
+    {
+        match state.udp_sockets.get(udp_socket_id) {
+            Some(udp_socket) => Ok(udp_socket.clone()),
+            None => Err(uptown_funk::Trap::new("UdpSocket not found")),
+        }
+    }
+}
+
+pub enum UdpSocketResult {
+    Ok(UdpSocket),
+    Err(NetworkError),
+}
+
+impl ToWasm<&mut TcpState> for UdpSocketResult {
+    type To = u32;
+
+    fn to(state: &mut TcpState, _: &impl Executor, result: Self) -> Result<u32, uptown_funk::Trap> {
+        match result {
+            UdpSocketResult::Ok(socket) => Ok(state.udp_sockets.add(socket)),
+            UdpSocketResult::Err(err) => {
+                state.last_error.set(err);
+                Ok(0)
+            }
+        }
+    }
+}
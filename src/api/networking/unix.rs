@@ -0,0 +1,110 @@
+use std::io;
+use uptown_funk::{Executor, FromWasm, ToWasm};
+
+use super::api::UnixState;
+use super::error::NetworkError;
+
+#[derive(Clone)]
+pub struct UnixListener(smol::net::unix::UnixListener);
+
+impl UnixListener {
+    pub fn bind(path: &str) -> Result<Self, io::Error> {
+        Ok(Self(smol::net::unix::UnixListener::bind(path)?))
+    }
+
+    pub async fn accept(&self) -> Result<UnixStream, io::Error> {
+        let (stream, _address) = self.0.accept().await?;
+        Ok(UnixStream(stream))
+    }
+}
+
+impl FromWasm<&mut UnixState> for UnixListener {
+    type From = u32;
+
+    fn from(
+        state: &mut UnixState,
+        _: &impl Executor,
+        unix_listener_id: u32,
+    ) -> Result<Self, uptown_funk::Trap>
+    where
+        Self: Sized,
+    {
+        match state.listeners.get(unix_listener_id) {
+            Some(unix_listener) => Ok(unix_listener.clone()),
+            None => Err(uptown_funk::Trap::new("UnixListener not found")),
+        }
+    }
+}
+
+pub enum UnixListenerResult {
+    Ok(UnixListener),
+    Err(NetworkError),
+}
+
+impl ToWasm<&mut UnixState> for UnixListenerResult {
+    type To = u32;
+
+    fn to(
+        state: &mut UnixState,
+        _: &impl Executor,
+        result: Self,
+    ) -> Result<u32, uptown_funk::Trap> {
+        match result {
+            UnixListenerResult::Ok(listener) => Ok(state.listeners.add(listener)),
+            UnixListenerResult::Err(err) => {
+                state.last_error.set(err);
+                Ok(0)
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct UnixStream(pub smol::net::unix::UnixStream);
+
+impl UnixStream {
+    pub async fn connect(path: &str) -> Result<Self, io::Error> {
+        Ok(Self(smol::net::unix::UnixStream::connect(path).await?))
+    }
+}
+
+impl FromWasm<&mut UnixState> for UnixStream {
+    type From = u32;
+
+    fn from(
+        state: &mut UnixState,
+        _: &impl Executor,
+        unix_stream_id: u32,
+    ) -> Result<Self, uptown_funk::Trap>
+    where
+        Self: Sized,
+    {
+        match state.streams.get(unix_stream_id) {
+            Some(unix_stream) => Ok(unix_stream.clone()),
+            None => Err(uptown_funk::Trap::new("UnixStream not found")),
+        }
+    }
+}
+
+pub enum UnixStreamResult {
+    Ok(UnixStream),
+    Err(NetworkError),
+}
+
+impl ToWasm<&mut UnixState> for UnixStreamResult {
+    type To = u32;
+
+    fn to(
+        state: &mut UnixState,
+        _: &impl Executor,
+        result: Self,
+    ) -> Result<u32, uptown_funk::Trap> {
+        match result {
+            UnixStreamResult::Ok(stream) => Ok(state.streams.add(stream)),
+            UnixStreamResult::Err(err) => {
+                state.last_error.set(err);
+                Ok(0)
+            }
+        }
+    }
+}
@@ -0,0 +1,577 @@
+//! An in-memory network for testing distributed guest code on a single machine.
+//!
+//! Processes using `NetworkBackend::Virtual` never touch the host's sockets. Each of them is
+//! attached to a `VirtualNetwork` through an interface with its own IP address, and connections
+//! and datagrams between the addresses are delivered in memory:
+//! * Every TCP write, UDP datagram and connection attempt is delayed by the network's latency.
+//! * Each direction of a TCP stream holds a limited number of writes, further writes wait until
+//!   the peer reads.
+//! * UDP datagrams are dropped with the probability set by `set_packet_loss`. The losses are drawn
+//!   from a generator with a fixed seed, so that runs are reproducible.
+//! * Partitions separate groups of addresses. Connection attempts across a partition time out,
+//!   writes to a stream crossing it fail with `ConnectionReset` and datagrams are dropped.
+//!
+//! Unspecified and loopback addresses refer to the address of the interface itself. Socket options
+//! are accepted, but don't have any effect.
+
+use smol::channel::{bounded, unbounded, Receiver, SendError, Sender, TrySendError};
+use smol::future::{poll_fn, Future};
+use smol::io::{AsyncRead, AsyncWrite};
+use smol::stream::Stream;
+use smol::Timer;
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, IoSliceMut},
+    net::{IpAddr, Shutdown, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+// Ports handed out when binding to port 0 and to the local end of connections.
+const EPHEMERAL_PORTS: std::ops::RangeInclusive<u16> = 49152..=65535;
+
+// Chunks in flight in each direction of a stream, further writes wait until the peer reads.
+const STREAM_CAPACITY: usize = 16;
+
+/// A network shared by all interfaces created from it, clones refer to the same network.
+#[derive(Clone)]
+pub struct VirtualNetwork(Arc<Mutex<Network>>);
+
+struct Network {
+    latency: Duration,
+    packet_loss: f64,
+    rng: SplitMix64,
+    // Pairs of addresses that can't reach each other, both directions are included.
+    partitioned: HashSet<(IpAddr, IpAddr)>,
+    listeners: HashMap<SocketAddr, Sender<VirtualTcpStream>>,
+    udp_sockets: HashMap<SocketAddr, Sender<Datagram>>,
+    next_port: u16,
+}
+
+impl Default for VirtualNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualNetwork {
+    /// Creates a network without latency, packet loss and partitions.
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Network {
+            latency: Duration::from_secs(0),
+            packet_loss: 0.0,
+            rng: SplitMix64(0),
+            partitioned: HashSet::new(),
+            listeners: HashMap::new(),
+            udp_sockets: HashMap::new(),
+            next_port: *EPHEMERAL_PORTS.start(),
+        })))
+    }
+
+    /// Returns the interface of a host with the address `ip` on this network.
+    pub fn interface(&self, ip: IpAddr) -> VirtualInterface {
+        VirtualInterface {
+            network: self.clone(),
+            ip,
+        }
+    }
+
+    pub fn set_latency(&self, latency: Duration) {
+        self.0.lock().unwrap().latency = latency;
+    }
+
+    /// Sets the probability between 0 and 1 that a datagram gets lost.
+    pub fn set_packet_loss(&self, packet_loss: f64) {
+        self.0.lock().unwrap().packet_loss = packet_loss;
+    }
+
+    /// Seeds the generator deciding which datagrams get lost.
+    pub fn set_seed(&self, seed: u64) {
+        self.0.lock().unwrap().rng = SplitMix64(seed);
+    }
+
+    /// Prevents all addresses in `a` from reaching the ones in `b` and the other way around.
+    pub fn partition(&self, a: &[IpAddr], b: &[IpAddr]) {
+        let mut network = self.0.lock().unwrap();
+        for a in a {
+            for b in b {
+                network.partitioned.insert((*a, *b));
+                network.partitioned.insert((*b, *a));
+            }
+        }
+    }
+
+    /// Removes all partitions.
+    pub fn heal(&self) {
+        self.0.lock().unwrap().partitioned.clear();
+    }
+
+    // Returns the latency of the link, or `None` if a partition separates the addresses.
+    fn link(&self, from: IpAddr, to: IpAddr) -> Option<Duration> {
+        let network = self.0.lock().unwrap();
+        if network.partitioned.contains(&(from, to)) {
+            None
+        } else {
+            Some(network.latency)
+        }
+    }
+}
+
+impl Network {
+    // Fails with `AddrInUse` if all ephemeral ports of `ip` are taken.
+    fn allocate_port(
+        &mut self,
+        ip: IpAddr,
+        in_use: impl Fn(&Self, SocketAddr) -> bool,
+    ) -> Result<u16, io::Error> {
+        for _ in EPHEMERAL_PORTS {
+            let port = self.next_port;
+            self.next_port = match port {
+                port if port == *EPHEMERAL_PORTS.end() => *EPHEMERAL_PORTS.start(),
+                port => port + 1,
+            };
+            if !in_use(self, SocketAddr::new(ip, port)) {
+                return Ok(port);
+            }
+        }
+        Err(io::ErrorKind::AddrInUse.into())
+    }
+
+    fn listener_in_use(&self, address: SocketAddr) -> bool {
+        match self.listeners.get(&address) {
+            Some(sender) => !sender.is_closed(),
+            None => false,
+        }
+    }
+
+    fn udp_socket_in_use(&self, address: SocketAddr) -> bool {
+        match self.udp_sockets.get(&address) {
+            Some(sender) => !sender.is_closed(),
+            None => false,
+        }
+    }
+}
+
+/// Generates the random numbers deciding about packet loss.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    // Returns a number in the range [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// The connection of a host with the address `ip` to a `VirtualNetwork`.
+#[derive(Clone)]
+pub struct VirtualInterface {
+    network: VirtualNetwork,
+    ip: IpAddr,
+}
+
+impl VirtualInterface {
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    // Addresses of the interface itself, only those can be bound to.
+    fn local(&self, address: SocketAddr) -> Result<SocketAddr, io::Error> {
+        let ip = address.ip();
+        if ip.is_unspecified() || ip.is_loopback() || ip == self.ip {
+            Ok(SocketAddr::new(self.ip, address.port()))
+        } else {
+            Err(io::ErrorKind::AddrNotAvailable.into())
+        }
+    }
+
+    fn remote(&self, address: SocketAddr) -> SocketAddr {
+        let ip = address.ip();
+        if ip.is_unspecified() || ip.is_loopback() {
+            SocketAddr::new(self.ip, address.port())
+        } else {
+            address
+        }
+    }
+
+    pub fn tcp_bind(&self, address: SocketAddr) -> Result<VirtualTcpListener, io::Error> {
+        let mut address = self.local(address)?;
+        let mut network = self.network.0.lock().unwrap();
+        if address.port() == 0 {
+            let port = network.allocate_port(self.ip, Network::listener_in_use)?;
+            address.set_port(port);
+        } else if network.listener_in_use(address) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        let (sender, incoming) = unbounded();
+        network.listeners.insert(address, sender);
+        Ok(VirtualTcpListener { address, incoming })
+    }
+
+    pub async fn tcp_connect(&self, address: SocketAddr) -> Result<VirtualTcpStream, io::Error> {
+        let peer = self.remote(address);
+        match self.network.link(self.ip, peer.ip()) {
+            Some(latency) => Timer::after(latency).await,
+            None => return Err(io::ErrorKind::TimedOut.into()),
+        };
+        let mut network = self.network.0.lock().unwrap();
+        let listener = match network.listeners.get(&peer) {
+            Some(listener) if !listener.is_closed() => listener.clone(),
+            _ => return Err(io::ErrorKind::ConnectionRefused.into()),
+        };
+        let port = network.allocate_port(self.ip, |_, _| false)?;
+        let local = SocketAddr::new(self.ip, port);
+        drop(network);
+
+        let (client, server) = VirtualTcpStream::pair(self.network.clone(), local, peer);
+        match listener.try_send(server) {
+            Ok(()) => Ok(client),
+            Err(_) => Err(io::ErrorKind::ConnectionRefused.into()),
+        }
+    }
+
+    pub fn udp_bind(&self, address: SocketAddr) -> Result<VirtualUdpSocket, io::Error> {
+        let mut address = self.local(address)?;
+        let mut network = self.network.0.lock().unwrap();
+        if address.port() == 0 {
+            let port = network.allocate_port(self.ip, Network::udp_socket_in_use)?;
+            address.set_port(port);
+        } else if network.udp_socket_in_use(address) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        let (sender, receiver) = unbounded();
+        network.udp_sockets.insert(address, sender);
+        Ok(VirtualUdpSocket {
+            interface: self.clone(),
+            address,
+            receiver,
+            peer: Arc::new(Mutex::new(None)),
+        })
+    }
+}
+
+/// A listener on a `VirtualNetwork`, it's closed once all clones of it are dropped.
+#[derive(Clone)]
+pub struct VirtualTcpListener {
+    address: SocketAddr,
+    incoming: Receiver<VirtualTcpStream>,
+}
+
+impl VirtualTcpListener {
+    pub async fn accept(&self) -> Result<VirtualTcpStream, io::Error> {
+        match self.incoming.recv().await {
+            Ok(stream) => Ok(stream),
+            Err(_) => Err(io::ErrorKind::NotConnected.into()),
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+// Data written to a stream, it becomes readable at `deliver_at`.
+struct Chunk {
+    deliver_at: Instant,
+    data: Vec<u8>,
+}
+
+/// One end of a connection on a `VirtualNetwork`, clones refer to the same end.
+#[derive(Clone)]
+pub struct VirtualTcpStream {
+    network: VirtualNetwork,
+    local: SocketAddr,
+    peer: SocketAddr,
+    sender: Sender<Chunk>,
+    // A written chunk that didn't fit into the channel anymore.
+    sending: Arc<Mutex<Option<Sending>>>,
+    reader: Arc<Mutex<Reader>>,
+}
+
+type Sending = Pin<Box<dyn Future<Output = Result<(), SendError<Chunk>>> + Send>>;
+
+struct Reader {
+    receiver: Receiver<Chunk>,
+    // Data of a received chunk that wasn't read yet.
+    buffer: Vec<u8>,
+    position: usize,
+    // A received chunk waiting for its delivery time.
+    delayed: Option<(Timer, Vec<u8>)>,
+    shutdown: bool,
+}
+
+impl VirtualTcpStream {
+    fn pair(network: VirtualNetwork, client: SocketAddr, server: SocketAddr) -> (Self, Self) {
+        let (to_server, from_client) = bounded(STREAM_CAPACITY);
+        let (to_client, from_server) = bounded(STREAM_CAPACITY);
+        let end = |local, peer, sender, receiver| Self {
+            network: network.clone(),
+            local,
+            peer,
+            sender,
+            sending: Arc::new(Mutex::new(None)),
+            reader: Arc::new(Mutex::new(Reader {
+                receiver,
+                buffer: Vec::new(),
+                position: 0,
+                delayed: None,
+                shutdown: false,
+            })),
+        };
+        (
+            end(client, server, to_server, from_server),
+            end(server, client, to_client, from_client),
+        )
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Closing the writing half signals the end of the stream to the peer.
+    pub fn shutdown(&self, how: Shutdown) {
+        if how != Shutdown::Read {
+            self.sender.close();
+        }
+        if how != Shutdown::Write {
+            let mut reader = self.reader.lock().unwrap();
+            reader.shutdown = true;
+            reader.receiver.close();
+        }
+    }
+
+    /// Fills the buffers in order with the data of the chunk received next.
+    pub fn poll_read_vectored(
+        &self,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        let mut reader = self.reader.lock().unwrap();
+        if reader.poll_fill(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let mut read = 0;
+        for buf in bufs {
+            let available = &reader.buffer[reader.position..];
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            reader.position += n;
+            read += n;
+        }
+        Poll::Ready(Ok(read))
+    }
+
+    /// Reads into `buf` without removing the data from the stream.
+    pub async fn peek(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        poll_fn(|cx| {
+            let mut reader = self.reader.lock().unwrap();
+            if reader.poll_fill(cx).is_pending() {
+                return Poll::Pending;
+            }
+            let available = &reader.buffer[reader.position..];
+            let n = available.len().min(buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            Poll::Ready(Ok(n))
+        })
+        .await
+    }
+
+    /// Waits while a previously written chunk doesn't fit into the channel to the peer, then
+    /// sends `buf`.
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, io::Error>> {
+        let mut sending = self.sending.lock().unwrap();
+        smol::ready!(poll_sent(&mut sending, cx))?;
+        let latency = match self.network.link(self.local.ip(), self.peer.ip()) {
+            Some(latency) => latency,
+            None => return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into())),
+        };
+        let chunk = Chunk {
+            deliver_at: Instant::now() + latency,
+            data: buf.to_vec(),
+        };
+        match self.sender.try_send(chunk) {
+            Ok(()) => {}
+            Err(TrySendError::Full(chunk)) => {
+                let sender = self.sender.clone();
+                *sending = Some(Box::pin(async move { sender.send(chunk).await }));
+            }
+            Err(TrySendError::Closed(_)) => {
+                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+            }
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    /// Waits until all written chunks are in the channel to the peer.
+    pub fn poll_flush(&self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        poll_sent(&mut self.sending.lock().unwrap(), cx)
+    }
+}
+
+fn poll_sent(sending: &mut Option<Sending>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+    let result = match sending {
+        Some(send) => smol::ready!(send.as_mut().poll(cx)),
+        None => return Poll::Ready(Ok(())),
+    };
+    *sending = None;
+    Poll::Ready(result.map_err(|_| io::ErrorKind::BrokenPipe.into()))
+}
+
+impl Reader {
+    // Waits until unread data is in the buffer or the end of the stream is reached, in which case
+    // the buffer stays empty.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            if self.shutdown || self.position < self.buffer.len() {
+                return Poll::Ready(());
+            }
+            if let Some((timer, _)) = &mut self.delayed {
+                if Pin::new(timer).poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                let (_, data) = self.delayed.take().unwrap();
+                self.buffer = data;
+                self.position = 0;
+                continue;
+            }
+            match Pin::new(&mut self.receiver).poll_next(cx) {
+                Poll::Ready(Some(chunk)) if chunk.deliver_at > Instant::now() => {
+                    self.delayed = Some((Timer::at(chunk.deliver_at), chunk.data));
+                }
+                Poll::Ready(Some(chunk)) => {
+                    self.buffer = chunk.data;
+                    self.position = 0;
+                }
+                // All writers are gone.
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncRead for VirtualTcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        VirtualTcpStream::poll_read_vectored(&self, cx, &mut [IoSliceMut::new(buf)])
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        VirtualTcpStream::poll_read_vectored(&self, cx, bufs)
+    }
+}
+
+impl AsyncWrite for VirtualTcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        VirtualTcpStream::poll_write(&self, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        VirtualTcpStream::poll_flush(&self, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        smol::ready!(VirtualTcpStream::poll_flush(&self, cx))?;
+        self.shutdown(Shutdown::Write);
+        Poll::Ready(Ok(()))
+    }
+}
+
+// A datagram sent from `from`, it can be received at `deliver_at`.
+struct Datagram {
+    from: SocketAddr,
+    deliver_at: Instant,
+    data: Vec<u8>,
+}
+
+/// A UDP socket on a `VirtualNetwork`, it's closed once all clones of it are dropped.
+#[derive(Clone)]
+pub struct VirtualUdpSocket {
+    interface: VirtualInterface,
+    address: SocketAddr,
+    receiver: Receiver<Datagram>,
+    // The only address datagrams are received from after connecting.
+    peer: Arc<Mutex<Option<SocketAddr>>>,
+}
+
+impl VirtualUdpSocket {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn connect(&self, address: SocketAddr) {
+        *self.peer.lock().unwrap() = Some(self.interface.remote(address));
+    }
+
+    /// Datagrams to addresses without a socket, across a partition or lost on the way are dropped
+    /// silently.
+    pub fn send_to(&self, buf: &[u8], address: SocketAddr) -> Result<usize, io::Error> {
+        let address = self.interface.remote(address);
+        let latency = match self.interface.network.link(self.address.ip(), address.ip()) {
+            Some(latency) => latency,
+            None => return Ok(buf.len()),
+        };
+        let mut network = self.interface.network.0.lock().unwrap();
+        let packet_loss = network.packet_loss;
+        if packet_loss > 0.0 && network.rng.next_f64() < packet_loss {
+            return Ok(buf.len());
+        }
+        if let Some(socket) = network.udp_sockets.get(&address) {
+            let _ = socket.try_send(Datagram {
+                from: self.address,
+                deliver_at: Instant::now() + latency,
+                data: buf.to_vec(),
+            });
+        }
+        Ok(buf.len())
+    }
+
+    pub fn send(&self, buf: &[u8]) -> Result<usize, io::Error> {
+        let peer = *self.peer.lock().unwrap();
+        match peer {
+            Some(peer) => self.send_to(buf, peer),
+            None => Err(io::ErrorKind::NotConnected.into()),
+        }
+    }
+
+    /// Bytes of the datagram not fitting into `buf` are discarded.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), io::Error> {
+        loop {
+            let datagram = match self.receiver.recv().await {
+                Ok(datagram) => datagram,
+                Err(_) => return Err(io::ErrorKind::NotConnected.into()),
+            };
+            let peer = *self.peer.lock().unwrap();
+            if matches!(peer, Some(peer) if peer != datagram.from) {
+                continue;
+            }
+            if datagram.deliver_at > Instant::now() {
+                Timer::at(datagram.deliver_at).await;
+            }
+            let n = datagram.data.len().min(buf.len());
+            buf[..n].copy_from_slice(&datagram.data[..n]);
+            return Ok((n, datagram.from));
+        }
+    }
+}
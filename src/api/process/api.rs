@@ -1,11 +1,15 @@
 use crate::{
     api::channel::{api::ChannelState, ChannelReceiver, Message},
-    module::LunaticModule,
+    api::networking::error::LastError,
+    api::wasi::fs::read_file,
+    module::{LunaticModule, ModuleCache, Runtime},
 };
 
-use super::{FunctionLookup, MemoryChoice, Process};
+use super::{
+    check_params, FunctionLookup, MemoryChoice, Param, Process, ProcessConfig, SpawnResult,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use smol::{channel::bounded, future::yield_now, Timer};
 use uptown_funk::{host_functions, state::HashMapStore};
 
@@ -17,15 +21,26 @@ use std::{
 pub struct ProcessState {
     module: LunaticModule,
     channel_state: ChannelState,
+    config: ProcessConfig,
     pub processes: HashMapStore<Process>,
+    pub last_error: LastError,
 }
 
 impl ProcessState {
-    pub fn new(module: LunaticModule, channel_state: ChannelState) -> Self {
+    /// Failed spawns are recorded in `last_error`, which should be shared with the process'
+    /// `TcpState`.
+    pub fn new(
+        module: LunaticModule,
+        channel_state: ChannelState,
+        config: ProcessConfig,
+        last_error: LastError,
+    ) -> Self {
         Self {
             module,
             channel_state,
-            processes: HashMapStore::new(),
+            config,
+            processes: HashMapStore::new_nonzero(),
+            last_error,
         }
     }
 }
@@ -47,26 +62,148 @@ impl ProcessState {
     // Spawn a new process with a context and call a function from the function table by `index`.
     //
     // Once the process is created the context will be passed through a Channel::Receiver to it.
+    // The new process inherits the configuration (arguments, environment variables, ...) of this one.
     async fn spawn_with_context(&self, index: u32, context: &[u8]) -> Process {
-        let (sender, receiver) = bounded(1);
-        let host_resources = &mut self
-            .channel_state
-            .inner
-            .borrow_mut()
-            .next_message_host_resources;
-        let host_resources = replace(host_resources, Vec::new());
-        let message = Message::new(context.as_ptr(), context.len(), host_resources);
-        let _ignore = sender.send(message).await;
-
         let future = Process::create(
-            Some(ChannelReceiver::from(receiver)),
+            Some(context_receiver(&self.channel_state, context)),
             self.module.clone(),
             FunctionLookup::TableIndex(index),
             MemoryChoice::New(None),
+            self.config.clone(),
         );
         Process::spawn(future)
     }
 
+    // Compile `wasm` and register it as `name`. Registered modules are shared with all processes
+    // spawned from this one.
+    //
+    // If a module loaded by a process is already registered as `name`, this loads a new version of
+    // it. Processes spawned from the name afterwards use the new version, running processes keep
+    // the old one.
+    //
+    // Returns 0 if successful, otherwise 1 (loading modules isn't allowed by the configuration,
+    // `name` is taken by a module the host registered or `wasm` doesn't compile).
+    async fn load_module(&self, name: &str, wasm: &[u8]) -> u32 {
+        if !self.config.load_modules {
+            return 1;
+        }
+        load(self, name, wasm.to_vec()).await
+    }
+
+    // Like `load_module`, but reads the module from `path` inside of the process' filesystem.
+    //
+    // Returns 0 if successful, otherwise 1
+    async fn load_module_from_path(&self, name: &str, path: &str) -> u32 {
+        if !self.config.load_modules {
+            return 1;
+        }
+        match read_file(self.config.wasi.filesystem.clone(), path).await {
+            Ok(wasm) => load(self, name, wasm).await,
+            Err(_) => 1,
+        }
+    }
+
+    // Returns 1 if a module is registered as `name`, otherwise 0
+    fn module_exists(&self, name: &str) -> u32 {
+        self.config.modules.get(name).is_some() as u32
+    }
+
+    // Returns the version of the module registered as `name`, starting at 1, or 0 if there is none.
+    fn module_version(&self, name: &str) -> u64 {
+        self.config.modules.version(name).unwrap_or(0)
+    }
+
+    // Subscribe the channel sender `sender` to new versions of the module registered as `name`.
+    //
+    // Every time a new version is loaded, an upgrade message is sent to the channel. It contains
+    // the new version as a little-endian u64 followed by the name. A long-lived process receiving
+    // it can spawn a process from the new version and hand its state over to it. Messages that
+    // don't fit into the channel unsubscribe it, so an unbounded channel should be used.
+    //
+    // Returns 0 if successful, otherwise 1 (the sender doesn't exist).
+    fn subscribe_to_upgrades(&self, name: &str, sender: u32) -> u32 {
+        match self.channel_state.inner.borrow().senders.get(sender) {
+            Some(sender) => {
+                self.config.modules.subscribe(name, sender.clone());
+                0
+            }
+            None => 1,
+        }
+    }
+
+    // Spawn a new process calling the function at `index` in the table of the module registered as
+    // `module`.
+    //
+    // The context is passed like in `spawn_with_context`. If the module doesn't exist, joining the
+    // process returns 1.
+    fn spawn_from_module(&self, module: &str, index: u32, context: &[u8]) -> Process {
+        let module = match self.config.modules.get(module) {
+            Some(module) => module,
+            None => {
+                let error = anyhow!("No module `{}`", module);
+                return Process::spawn(async move { Err(error.into()) });
+            }
+        };
+        let future = Process::create(
+            Some(context_receiver(&self.channel_state, context)),
+            module,
+            FunctionLookup::TableIndex(index),
+            MemoryChoice::New(None),
+            self.config.clone(),
+        );
+        Process::spawn(future)
+    }
+
+    // Spawn a new process calling the exported function `function` with `params`. The function is
+    // looked up in the module registered as `module`, or in the module of this process if `module`
+    // is empty. See `Param::decode` for the encoding of `params`.
+    //
+    // The context is passed like in `spawn_with_context`.
+    //
+    // Returns 0 if successful, otherwise 1 (the module or function don't exist, or the parameters
+    // don't match the function's signature). A failed spawn returns the process id 0, which no
+    // process ever uses, the reason can be retrieved with the `last_error_*` functions. Its status
+    // is `inval`.
+    //
+    // The context is only taken if the process is spawned, host resources prepared for it stay
+    // around for the next message otherwise.
+    fn spawn(
+        &self,
+        module: &str,
+        function: &str,
+        params: &[u8],
+        context: &[u8],
+    ) -> (u32, SpawnResult) {
+        let result = Param::decode(params)
+            .and_then(|params| spawn_export(self, module, function, params, context));
+        match result {
+            Ok(process) => (0, SpawnResult::Ok(process)),
+            Err(error) => (1, SpawnResult::Err(error.to_string())),
+        }
+    }
+
+    // Spawn a process on the connected node `node`, calling the function at `index` in the table of
+    // the module registered there as `module`.
+    //
+    // The context is passed like in `spawn_with_context`, but it can only contain channel senders as
+    // host resources. If the process can't be spawned, joining it returns 1.
+    fn spawn_on_node(&self, node: &str, module: &str, index: u32, context: &[u8]) -> Process {
+        let host_resources = &mut self
+            .channel_state
+            .inner
+            .borrow_mut()
+            .next_message_host_resources;
+        let host_resources = replace(host_resources, Vec::new());
+        let result = match &self.config.node {
+            Some(local) => local.spawn(node, module, index, context, host_resources),
+            None => Err(anyhow!("Process isn't running on a node")),
+        };
+        match result {
+            Ok(process) => process,
+            Err(error) => Process::spawn(async move { Err(error.into()) }),
+        }
+    }
+
     // Wait on child process to finish.
     // Returns 0 if process didn't trap, otherwise 1
     async fn join(&self, process: Process) -> u32 {
@@ -92,3 +229,62 @@ impl ProcessState {
         process.task().detach()
     }
 }
+
+// Moves the context and the host resources prepared for the next message into a new channel.
+fn context_receiver(channel_state: &ChannelState, context: &[u8]) -> ChannelReceiver {
+    let host_resources = &mut channel_state.inner.borrow_mut().next_message_host_resources;
+    let host_resources = replace(host_resources, Vec::new());
+    let message = Message::new(context.as_ptr(), context.len(), host_resources);
+    let (sender, receiver) = bounded(1);
+    let _ignore = sender.try_send(message);
+    ChannelReceiver::from(receiver)
+}
+
+// Compiles `wasm` and registers it as `name`, returns 0 if successful, otherwise 1.
+async fn load(state: &ProcessState, name: &str, wasm: Vec<u8>) -> u32 {
+    let cache = state.config.module_cache.clone();
+    match compile(state.module.runtime(), wasm, cache).await {
+        Ok(module) => state.config.modules.insert_loaded(name, module).is_none() as u32,
+        Err(_) => 1,
+    }
+}
+
+// Spawns a process calling an exported function, after checking that it exists and takes `params`.
+fn spawn_export(
+    state: &ProcessState,
+    module: &str,
+    function: &str,
+    params: Vec<Param>,
+    context: &[u8],
+) -> Result<Process> {
+    let module = if module.is_empty() {
+        state.module.clone()
+    } else {
+        match state.config.modules.get(module) {
+            Some(module) => module,
+            None => return Err(anyhow!("No module `{}`", module)),
+        }
+    };
+    check_params(function, &module.function_params(function)?, &params)?;
+    let future = Process::create(
+        Some(context_receiver(&state.channel_state, context)),
+        module,
+        FunctionLookup::Name(function.to_string(), params),
+        MemoryChoice::New(None),
+        state.config.clone(),
+    );
+    Ok(Process::spawn(future))
+}
+
+// Compiles a module on the blocking thread pool, unless it's in the cache.
+async fn compile(
+    runtime: Runtime,
+    wasm: Vec<u8>,
+    cache: Option<ModuleCache>,
+) -> Result<LunaticModule> {
+    smol::unblock(move || match cache {
+        Some(cache) => LunaticModule::new_cached(&wasm, runtime, &cache),
+        None => LunaticModule::new(&wasm, runtime),
+    })
+    .await
+}
@@ -0,0 +1,54 @@
+use crate::api::networking::dns::{NameResolver, SystemResolver};
+use crate::api::networking::{NetworkBackend, NetworkPolicy};
+use crate::api::wasi::WasiConfig;
+use crate::module::{ModuleCache, ModuleRegistry};
+use crate::node::Node;
+
+use std::sync::Arc;
+
+/// Configuration a process is spawned with.
+///
+/// Processes spawned from inside a guest inherit the configuration of their parent.
+#[derive(Clone)]
+pub struct ProcessConfig {
+    pub wasi: WasiConfig,
+    pub network: NetworkPolicy,
+    /// Used by the `resolve` host functions, shared with spawned processes.
+    pub resolver: Arc<dyn NameResolver>,
+    /// Spawned processes use the same backend, on a virtual network they share the address.
+    pub network_backend: NetworkBackend,
+    /// Modules processes can be spawned from by name, shared with spawned processes.
+    pub modules: ModuleRegistry,
+    /// Allows processes to load modules into the registry, off by default.
+    ///
+    /// Loaded modules can be spawned from by all processes sharing the registry, but can't replace
+    /// modules registered by the host.
+    pub load_modules: bool,
+    /// Used when processes load modules, compiling them only if they aren't cached.
+    pub module_cache: Option<ModuleCache>,
+    /// Node the process runs on, needed for spawning processes on other nodes.
+    pub node: Option<Node>,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        Self {
+            wasi: WasiConfig::default(),
+            network: NetworkPolicy::default(),
+            resolver: Arc::new(SystemResolver),
+            network_backend: NetworkBackend::default(),
+            modules: ModuleRegistry::new(),
+            load_modules: false,
+            module_cache: None,
+            node: None,
+        }
+    }
+}
+
+impl ProcessConfig {
+    /// Replaces the name resolver.
+    pub fn resolver<R: NameResolver + 'static>(&mut self, resolver: R) -> &mut Self {
+        self.resolver = Arc::new(resolver);
+        self
+    }
+}
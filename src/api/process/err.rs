@@ -1,3 +1,5 @@
+use std::fmt;
+
 pub struct Error<T> {
     pub error: anyhow::Error,
     pub value: Option<T>,
@@ -11,3 +13,45 @@ impl<T, E: Into<anyhow::Error>> From<E> for Error<T> {
         }
     }
 }
+
+impl<T> Error<T> {
+    /// Returns the exit code if the process stopped by calling `proc_exit`.
+    pub fn exit_code(&self) -> Option<u32> {
+        self.error.downcast_ref::<ProcExit>().map(|exit| exit.0)
+    }
+}
+
+/// The error of a process that stopped by calling `proc_exit` with the exit code.
+#[derive(Debug)]
+pub struct ProcExit(pub u32);
+
+impl fmt::Display for ProcExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "proc_exit({}) called", self.0)
+    }
+}
+
+impl std::error::Error for ProcExit {}
+
+/// Turns the error returned by a call into a wasmtime instance into `ProcExit` if the instance
+/// exited.
+#[cfg(feature = "vm-wasmtime")]
+pub(crate) fn wasmtime_exit(error: anyhow::Error) -> anyhow::Error {
+    match error
+        .downcast_ref::<wasmtime::Trap>()
+        .and_then(wasmtime::Trap::i32_exit_status)
+    {
+        Some(status) => ProcExit(status as u32).into(),
+        None => error,
+    }
+}
+
+/// Turns the error returned by a call into a wasmer instance into `ProcExit` if the instance
+/// exited.
+#[cfg(feature = "vm-wasmer")]
+pub(crate) fn wasmer_exit(error: wasmer::RuntimeError) -> anyhow::Error {
+    match error.downcast::<uptown_funk::I32Exit>() {
+        Ok(exit) => ProcExit(exit.0 as u32).into(),
+        Err(error) => error.into(),
+    }
+}
@@ -1,8 +1,13 @@
 pub mod api;
+mod config;
 mod env;
 mod err;
+mod param;
 mod process;
 mod tls;
 
+pub use config::*;
 pub use env::*;
+pub use err::{Error, ProcExit};
+pub use param::*;
 pub use process::*;
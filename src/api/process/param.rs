@@ -0,0 +1,117 @@
+use anyhow::{anyhow, bail, Result};
+
+use std::convert::TryInto;
+
+/// Argument passed to an exported function when spawning a process.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Param {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl Param {
+    pub fn ty(&self) -> ParamType {
+        match self {
+            Param::I32(_) => ParamType::I32,
+            Param::I64(_) => ParamType::I64,
+            Param::F32(_) => ParamType::F32,
+            Param::F64(_) => ParamType::F64,
+        }
+    }
+
+    /// Decodes parameters passed by a guest.
+    ///
+    /// Every parameter takes 16 bytes, a `u32` type (0 = i32, 1 = i64, 2 = f32, 3 = f64) followed by
+    /// 4 bytes of padding and the little-endian value. 32 bit values only use the first 4 of the
+    /// remaining 8 bytes.
+    pub fn decode(bytes: &[u8]) -> Result<Vec<Param>> {
+        if bytes.len() % 16 != 0 {
+            bail!(
+                "Parameters take {} bytes, not a multiple of 16",
+                bytes.len()
+            );
+        }
+        bytes
+            .chunks(16)
+            .map(|param| {
+                let value32 = param[8..12].try_into().unwrap();
+                let value64 = param[8..16].try_into().unwrap();
+                match u32::from_le_bytes(param[..4].try_into().unwrap()) {
+                    0 => Ok(Param::I32(i32::from_le_bytes(value32))),
+                    1 => Ok(Param::I64(i64::from_le_bytes(value64))),
+                    2 => Ok(Param::F32(f32::from_le_bytes(value32))),
+                    3 => Ok(Param::F64(f64::from_le_bytes(value64))),
+                    ty => Err(anyhow!("Unknown parameter type {}", ty)),
+                }
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "vm-wasmtime")]
+    pub fn to_wasmtime(self) -> wasmtime::Val {
+        match self {
+            Param::I32(value) => wasmtime::Val::I32(value),
+            Param::I64(value) => wasmtime::Val::I64(value),
+            Param::F32(value) => wasmtime::Val::F32(value.to_bits()),
+            Param::F64(value) => wasmtime::Val::F64(value.to_bits()),
+        }
+    }
+
+    #[cfg(feature = "vm-wasmer")]
+    pub fn to_wasmer(self) -> wasmer::Val {
+        match self {
+            Param::I32(value) => wasmer::Val::I32(value),
+            Param::I64(value) => wasmer::Val::I64(value),
+            Param::F32(value) => wasmer::Val::F32(value),
+            Param::F64(value) => wasmer::Val::F64(value),
+        }
+    }
+}
+
+impl ParamType {
+    #[cfg(feature = "vm-wasmtime")]
+    pub fn from_wasmtime(ty: &wasmtime::ValType) -> Option<Self> {
+        match ty {
+            wasmtime::ValType::I32 => Some(ParamType::I32),
+            wasmtime::ValType::I64 => Some(ParamType::I64),
+            wasmtime::ValType::F32 => Some(ParamType::F32),
+            wasmtime::ValType::F64 => Some(ParamType::F64),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "vm-wasmer")]
+    pub fn from_wasmer(ty: &wasmer::Type) -> Option<Self> {
+        match ty {
+            wasmer::Type::I32 => Some(ParamType::I32),
+            wasmer::Type::I64 => Some(ParamType::I64),
+            wasmer::Type::F32 => Some(ParamType::F32),
+            wasmer::Type::F64 => Some(ParamType::F64),
+            _ => None,
+        }
+    }
+}
+
+/// Checks that `params` match the parameter types of the exported function `name`.
+pub fn check_params(name: &str, expected: &[ParamType], params: &[Param]) -> Result<()> {
+    let types: Vec<ParamType> = params.iter().map(Param::ty).collect();
+    if types != expected {
+        bail!(
+            "Function `{}` takes parameters {:?}, but got {:?}",
+            name,
+            expected,
+            types
+        );
+    }
+    Ok(())
+}
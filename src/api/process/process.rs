@@ -7,25 +7,36 @@ use lazy_static::lazy_static;
 use smol::{Executor as TaskExecutor, Task};
 use uptown_funk::{Executor, FromWasm, HostFunctions, ToWasm};
 
+use crate::api::{networking::error::NetworkError, wasi::types::Status};
 use crate::module::{LunaticModule, Runtime};
 use crate::{api::channel::ChannelReceiver, linker::*};
 
 use log::info;
 use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::api::DefaultApi;
 
 use super::api::ProcessState;
 use super::err::*;
+use super::param::{check_params, Param};
+use super::ProcessConfig;
 
 lazy_static! {
     pub static ref EXECUTOR: TaskExecutor<'static> = TaskExecutor::new();
 }
 
+/// Returns a new id that is unique across all processes.
+pub fn next_process_id() -> u64 {
+    static NEXT_PROCESS_ID: AtomicU64 = AtomicU64::new(1);
+    NEXT_PROCESS_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 /// Used to look up a function by name or table index inside of an Instance.
 pub enum FunctionLookup {
     TableIndex(u32),
-    Name(&'static str),
+    /// An exported function, called with the parameters.
+    Name(String, Vec<Param>),
 }
 
 /// For now we always create a new memory per instance, but eventually we will want to support
@@ -69,6 +80,10 @@ impl Process {
         let created_at = std::time::Instant::now();
         let runtime = module.runtime();
 
+        if let FunctionLookup::Name(name, params) = &function {
+            check_params(name, &module.function_params(name)?, params)?;
+        }
+
         let stack = OneMbStack::new()?;
         let mut process = AsyncWormhole::new(stack, move |yielder| {
             let yielder_ptr =
@@ -82,8 +97,8 @@ impl Process {
                     let instance = linker.instance()?;
 
                     match function {
-                        FunctionLookup::Name(name) => {
-                            let func = instance.get_func(name).ok_or_else(|| {
+                        FunctionLookup::Name(name, params) => {
+                            let func = instance.get_func(&name).ok_or_else(|| {
                                 anyhow::Error::msg(format!(
                                     "No function {} in wasmtime instance",
                                     name
@@ -92,8 +107,10 @@ impl Process {
 
                             // Measure how long the function takes for named functions.
                             let performance_timer = std::time::Instant::now();
-                            func.call(&[]).map_err(|error| Error {
-                                error: error.into(),
+                            let params: Vec<_> =
+                                params.into_iter().map(Param::to_wasmtime).collect();
+                            func.call(&params).map_err(|error| Error {
+                                error: wasmtime_exit(error),
                                 value: Some(ret.clone()),
                             })?;
                             info!(target: "performance", "Process {} finished in {:.5} ms.", name, performance_timer.elapsed().as_secs_f64() * 1000.0);
@@ -106,7 +123,7 @@ impl Process {
                                     )
                                 })?;
 
-                            func.call(&[(index as i32).into()])?;
+                            func.call(&[(index as i32).into()]).map_err(wasmtime_exit)?;
                         }
                     }
 
@@ -119,20 +136,21 @@ impl Process {
                     let instance = linker.instance()?;
 
                     match function {
-                        FunctionLookup::Name(name) => {
-                            let func = instance.exports.get_function(name)?;
+                        FunctionLookup::Name(name, params) => {
+                            let func = instance.exports.get_function(&name)?;
 
                             // Measure how long the function takes for named functions.
                             let performance_timer = std::time::Instant::now();
-                            func.call(&[]).map_err(|error| Error {
-                                error: error.into(),
+                            let params: Vec<_> = params.into_iter().map(Param::to_wasmer).collect();
+                            func.call(&params).map_err(|error| Error {
+                                error: wasmer_exit(error),
                                 value: Some(ret.clone()),
                             })?;
                             info!(target: "performance", "Process {} finished in {:.5} ms.", name, performance_timer.elapsed().as_secs_f64() * 1000.0);
                         }
                         FunctionLookup::TableIndex(index) => {
                             let func = instance.exports.get_function("lunatic_spawn_by_index")?;
-                            func.call(&[(index as i32).into()])?;
+                            func.call(&[(index as i32).into()]).map_err(wasmer_exit)?;
                         }
                     }
 
@@ -163,8 +181,9 @@ impl Process {
         module: LunaticModule,
         function: FunctionLookup,
         memory: MemoryChoice,
+        config: ProcessConfig,
     ) -> Result<(), Error<()>> {
-        let api = DefaultApi::new(context_receiver, module.clone());
+        let api = DefaultApi::new(context_receiver, module.clone(), config);
         Process::create_with_api(module, function, memory, api).await
     }
 
@@ -190,6 +209,31 @@ impl ToWasm<&mut ProcessState> for Process {
     }
 }
 
+/// Result of spawning a process, a failed spawn is returned to the guest as process 0 and its error
+/// is recorded as the last error of the process.
+pub enum SpawnResult {
+    Ok(Process),
+    Err(String),
+}
+
+impl ToWasm<&mut ProcessState> for SpawnResult {
+    type To = u32;
+
+    fn to(
+        state: &mut ProcessState,
+        _: &impl Executor,
+        result: Self,
+    ) -> Result<u32, uptown_funk::Trap> {
+        match result {
+            SpawnResult::Ok(process) => Ok(state.processes.add(process)),
+            SpawnResult::Err(error) => {
+                state.last_error.set(NetworkError::new(Status::Inval, error));
+                Ok(0)
+            }
+        }
+    }
+}
+
 impl FromWasm<&mut ProcessState> for Process {
     type From = u32;
 
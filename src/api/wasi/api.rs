@@ -1,14 +1,11 @@
+use super::fs::SetTime;
 use super::types::*;
 
 use log::debug;
 use uptown_funk::{host_functions, types, Trap};
 
-use std::io::{self, IoSlice, IoSliceMut, Read, SeekFrom, Write};
-
-lazy_static::lazy_static! {
-    static ref ENV : WasiEnv = WasiEnv::env_vars(std::env::vars());
-    static ref ARG : WasiEnv = WasiEnv::args(std::env::args().skip(1));
-}
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use std::net::Shutdown;
 
 pub use super::state::WasiState;
 
@@ -30,13 +27,13 @@ impl WasiState {
     // Command line arguments and environment variables
 
     fn args_sizes_get(&self, mut var_count: Ptr<Size>, mut total_bytes: Ptr<Size>) -> Status {
-        var_count.set(ARG.len());
-        total_bytes.set(ARG.total_bytes());
+        var_count.set(self.args.len());
+        total_bytes.set(self.args.total_bytes());
         Status::Success
     }
 
     fn args_get(&self, mut args: Ptr<Ptr<u8>>, mut args_buf: Ptr<u8>) -> StatusTrapResult {
-        for kv in ARG.iter() {
+        for kv in self.args.iter() {
             args.copy(&args_buf);
             args_buf = args_buf
                 .copy_slice(&kv)?
@@ -54,13 +51,13 @@ impl WasiState {
         mut var_count: Ptr<Size>,
         mut total_bytes: Ptr<Size>,
     ) -> StatusTrapResult {
-        var_count.set(ENV.len());
-        total_bytes.set(ENV.total_bytes());
+        var_count.set(self.envs.len());
+        total_bytes.set(self.envs.total_bytes());
         Ok(())
     }
 
     fn environ_get(&self, mut environ: Ptr<Ptr<u8>>, mut environ_buf: Ptr<u8>) -> StatusTrapResult {
-        for kv in ENV.iter() {
+        for kv in self.envs.iter() {
             environ.copy(&environ_buf);
             environ_buf = environ_buf
                 .copy_slice(&kv)?
@@ -105,7 +102,7 @@ impl WasiState {
     }
 
     fn proc_exit(&self, exit_code: u32) -> Trap {
-        Trap::new(format!("proc_exit({}) called", exit_code))
+        Trap::i32_exit(exit_code as i32)
     }
 
     fn proc_raise(&self, _signal: Signal) -> Status {
@@ -114,33 +111,36 @@ impl WasiState {
 
     // Filesystem fd functions
 
-    fn fd_advise(&self, _fd: u32, _offset: u64, _len: u64, _advice: u32) -> Status {
-        // Ignore for now
-        Status::Success
+    async fn fd_advise(
+        &self,
+        fd: Fd,
+        offset: Filesize,
+        len: Filesize,
+        advice: u32,
+    ) -> StatusResult {
+        self.advise(fd, offset, len, advice as Advice).await
     }
 
-    fn fd_allocate(&self, _fd: Fd, _offset: Filesize, _len: Filesize) -> Status {
-        // Ignore for now
-        Status::Success
+    async fn fd_allocate(&self, fd: Fd, offset: Filesize, len: Filesize) -> StatusResult {
+        self.allocate(fd, offset, len).await
     }
 
-    fn fd_close(&mut self, fd: Fd) -> Status {
-        self.close(fd);
-        Status::Success
+    fn fd_close(&mut self, fd: Fd) -> StatusResult {
+        self.close(fd)
     }
 
-    fn fd_datasync(&self, _fd: Fd) -> Status {
-        // Ignore for now
-        Status::Success
+    async fn fd_datasync(&self, fd: Fd) -> StatusResult {
+        self.sync_data(fd).await
     }
 
-    fn fd_fdstat_get(&self, fd: Fd, mut stat: Ptr<Fdstat>) -> StatusResult {
-        let metadata = self.filestat(fd);
+    async fn fd_fdstat_get(&self, fd: Fd, mut stat: Ptr<Fdstat>) -> StatusResult {
+        let metadata = self.filestat(fd).await;
         stat.set(Fdstat {
             fs_filetype: metadata?.filetype,
             fs_flags: Fdflags::new(),
             fs_rights_base: 0x600,
-            fs_rights_inheriting: 0x600,
+            // wasi-libc only requests rights on new descriptors that are inherited.
+            fs_rights_inheriting: RIGHTS_ALL,
         });
         Ok(())
     }
@@ -155,48 +155,55 @@ impl WasiState {
         Status::Success
     }
 
-    fn fd_filestat_get(&self, fd: Fd, mut filestat: Ptr<Filestat>) -> StatusResult {
-        Ok(filestat.set(self.filestat(fd)?))
+    async fn fd_filestat_get(&self, fd: Fd, mut filestat: Ptr<Filestat>) -> StatusResult {
+        Ok(filestat.set(self.filestat(fd).await?))
     }
 
-    fn fd_filestat_set_size(&mut self, fd: Fd, size: Filesize) -> StatusResult {
-        self.set_size(fd, size)
+    async fn fd_filestat_set_size(&mut self, fd: Fd, size: Filesize) -> StatusResult {
+        self.set_size(fd, size).await
     }
 
-    fn fd_filestat_set_times(&self, _fd: u32, _atim: u64, _mtim: u64, _fst_flags: u32) -> Status {
-        // Ignore for now
-        Status::Success
+    async fn fd_filestat_set_times(
+        &self,
+        fd: Fd,
+        atim: Timestamp,
+        mtim: Timestamp,
+        fst_flags: u32,
+    ) -> StatusResult {
+        let (atim, mtim) = SetTime::from_fstflags(atim, mtim, fst_flags as Fstflags)?;
+        self.set_times(fd, atim, mtim).await
     }
 
-    fn fd_pread(
+    async fn fd_pread(
         &mut self,
         fd: u32,
         iovs: &mut [IoSliceMut<'_>],
         offset: Filesize,
         read_len: Ptr<Size>,
     ) -> StatusResult {
-        let tell = self.tell(fd)?;
-        self.seek(fd, SeekFrom::Start(offset))?;
-        self.fd_read(fd, iovs, read_len)?;
-        self.seek(fd, SeekFrom::Start(tell))?;
+        let tell = self.tell(fd).await?;
+        self.seek(fd, SeekFrom::Start(offset)).await?;
+        self.fd_read(fd, iovs, read_len).await?;
+        self.seek(fd, SeekFrom::Start(tell)).await?;
         Ok(())
     }
 
-    fn fd_prestat_dir_name(&self, _fd: u32, path: Ptr<u8>, _path_len: u32) -> Status {
-        path.copy_slice("/".as_bytes()).ok();
-        Status::Success
+    fn fd_prestat_dir_name(&self, fd: Fd, path: Ptr<u8>, path_len: Size) -> StatusTrapResult {
+        let name = self.preopen_name(fd)?;
+        if name.len() > path_len as usize {
+            return Status::NameTooLong.into();
+        }
+        path.copy_slice(name.as_bytes())?;
+        Ok(())
     }
 
-    fn fd_prestat_get(&self, fd: u32, mut prestat: Ptr<Prestat>) -> Status {
-        if fd == 3 {
-            prestat.set(Prestat::directory(1));
-            return Status::Success;
-        } else {
-            return Status::Badf;
-        }
+    fn fd_prestat_get(&self, fd: Fd, mut prestat: Ptr<Prestat>) -> StatusResult {
+        let name = self.preopen_name(fd)?;
+        prestat.set(Prestat::directory(name.len() as u32));
+        Ok(())
     }
 
-    fn fd_pwrite(
+    async fn fd_pwrite(
         &mut self,
         fd: u32,
         ciovs: &[IoSlice<'_>],
@@ -204,35 +211,34 @@ impl WasiState {
         write_len: Ptr<Size>,
     ) -> StatusResult {
         debug!("fd_pwrite fd={}, offset={}", fd, offset);
-        let tell = self.tell(fd)?;
-        self.seek(fd, SeekFrom::Start(offset))?;
-        self.fd_write(fd, ciovs, write_len)?;
-        self.seek(fd, SeekFrom::Start(tell))?;
+        let tell = self.tell(fd).await?;
+        self.seek(fd, SeekFrom::Start(offset)).await?;
+        self.fd_write(fd, ciovs, write_len).await?;
+        self.seek(fd, SeekFrom::Start(tell)).await?;
         Ok(())
     }
 
-    fn fd_read(
+    async fn fd_read(
         &mut self,
         fd: u32,
         iovs: &mut [IoSliceMut<'_>],
         mut read_len: Ptr<Size>,
     ) -> StatusResult {
         debug!("fd_read fd={}", fd);
-        let read = match fd {
-            // Stdout & stderr not supported as read destination
-            1 | 2 => return Status::Inval.into(),
-            0 => io::stdin().read_vectored(iovs)?,
-            fd => self.read(fd, iovs)?,
-        };
+        let read = self.read(fd, iovs).await?;
         read_len.set(read as u32);
         Ok(())
     }
 
-    #[allow(unused_assignments)]
-    fn fd_readdir(
-        &self,
+    /// Fills `buf` with as many directory entries as fit, starting at `cookie`.
+    ///
+    /// If the last entry doesn't fit, only the part of it that fits is written. The guest knows that
+    /// there are more entries if the whole buffer was filled, and continues reading from the cookie
+    /// of the last complete entry.
+    async fn fd_readdir(
+        &mut self,
         fd: Fd,
-        mut buf: Ptr<u8>,
+        buf: Ptr<u8>,
         buf_len: Size,
         cookie: Dircookie,
         mut written_ptr: Ptr<Size>,
@@ -241,103 +247,71 @@ impl WasiState {
             "fd_readdir fd={}, buf={:?}, buf_len={}, cookie={}",
             fd, buf, buf_len, cookie
         );
-        let path = self.get_path(fd)?;
-        let paths = std::fs::read_dir(path)?;
-        let dirent_size = std::mem::size_of::<Dirent>() as u32;
-        let mut written = 0;
-        // TODO something is broken when multiple entries are sent
-        // check how Rust implements reading from this syscall
-        for (i, dent) in paths
-            .filter(|e| e.is_ok())
-            .map(|e| e.unwrap())
-            .enumerate()
-            .skip(cookie as usize)
-        {
-            let dpath = dent.file_name();
-            let dpath_str = dpath.to_str();
-            if dpath_str.is_none() {
-                continue;
-            }
-            let dpath_bytes = dpath_str.unwrap().as_bytes();
-            if written + dpath_bytes.len() as u32 + dirent_size > buf_len {
+        let buf_len = buf_len as usize;
+        let mut bytes = Vec::with_capacity(buf_len);
+        let entries = self.read_dir(fd, cookie).await?;
+        for (i, entry) in entries.iter().skip(cookie as usize).enumerate() {
+            if bytes.len() >= buf_len {
                 break;
             }
-
-            let d = Dirent {
-                d_next: (i + 1) as u64,
-                d_ino: 0,
-                d_namlen: dpath_bytes.len() as u32,
-                d_type: dent.file_type()?.into(),
+            let dirent = Dirent {
+                d_next: cookie + i as u64 + 1,
+                d_ino: entry.ino,
+                d_namlen: entry.name.len() as u32,
+                d_type: entry.filetype,
             };
-            //debug!("Write {} direntzie to {:?}", dirent_size, buf); // WTF
-            buf = buf
-                .set_cast(d)?
-                .ok_or_else(|| Trap::new("Reached end of the readdir buffer"))?;
-            //debug!("Copy {} bytes to {:?}", dpath_bytes.len(), buf);
-
-            buf = buf
-                .copy_slice(&dpath_bytes)?
-                .ok_or_else(|| Trap::new("Reached end of the readdir buffer"))?;
-            //debug!("Final buf {:?}", buf);
-            written += dirent_size + dpath_bytes.len() as u32;
-            //debug!("Name: {} {:?}", i, dent.path());
-            //debug!("Written {}", written);
-            break;
+            bytes.extend_from_slice(&dirent.to_le_bytes());
+            bytes.extend_from_slice(entry.name.as_bytes());
         }
+        bytes.truncate(buf_len);
 
-        written_ptr.set(written);
+        buf.copy_slice(&bytes)?;
+        written_ptr.set(bytes.len() as u32);
         Ok(())
     }
 
-    fn fd_renumber(&self, _fd: u32, _to_fd: u32) -> Status {
-        Status::Success
+    fn fd_renumber(&mut self, fd: Fd, to_fd: Fd) -> StatusResult {
+        self.renumber(fd, to_fd)
     }
 
-    fn fd_seek(
+    async fn fd_seek(
         &mut self,
         fd: Fd,
         delta: Filedelta,
         whence: Whence,
         mut seek_res: Ptr<u64>,
     ) -> StatusResult {
-        Ok(seek_res.set(self.seek(fd, whence.into_seek_from(delta))?))
+        Ok(seek_res.set(self.seek(fd, whence.into_seek_from(delta)).await?))
     }
 
-    fn fd_sync(&self, _fd: u32) -> Status {
-        // Ignore for now
-        Status::Success
+    async fn fd_sync(&self, fd: Fd) -> StatusResult {
+        self.sync(fd).await
     }
 
-    fn fd_tell(&mut self, fd: Fd, mut tell_res: Ptr<u64>) -> StatusResult {
-        Ok(tell_res.set(self.tell(fd)?))
+    async fn fd_tell(&mut self, fd: Fd, mut tell_res: Ptr<u64>) -> StatusResult {
+        Ok(tell_res.set(self.tell(fd).await?))
     }
 
-    fn fd_write(
+    async fn fd_write(
         &mut self,
         fd: Fd,
         ciovs: &[IoSlice<'_>],
         mut write_len: Ptr<Size>,
     ) -> StatusResult {
-        let written = match fd {
-            // Stdin not supported as write destination
-            0 => return Status::Inval.into(),
-            1 => io::stdout().write_vectored(ciovs)?,
-            2 => io::stderr().write_vectored(ciovs)?,
-            fd => self.write(fd, ciovs)?,
-        };
+        let written = self.write(fd, ciovs).await?;
         write_len.set(written as u32);
         Ok(())
     }
 
     // Path
 
-    fn path_create_directory(&self, fd: Fd, path: &str) -> StatusResult {
+    async fn path_create_directory(&self, fd: Fd, path: &str) -> StatusResult {
         debug!("path_create_directory fd={}, path={}", fd, path);
         let abs_path = self.abs_path(fd, path)?;
-        self.create_directory(abs_path)
+        self.create_directory(abs_path).await
     }
 
-    fn path_filestat_get(
+    async fn path_filestat_get(
         &self,
         fd: Fd,
         flags: u32,
@@ -350,25 +324,28 @@ impl WasiState {
         );
 
         let abs_path = self.abs_path(fd, path)?;
-        filestat.set(self.filestat_path(&abs_path)?);
+        filestat.set(self.filestat_path(abs_path).await?);
 
         Ok(())
     }
 
-    fn path_filestat_set_times(
+    async fn path_filestat_set_times(
         &self,
-        _fd: Fd,
-        _flags: u32,
-        _path: &str,
-        _atim: u64,
-        _mtim: u64,
-        _fst_flags: u32,
-    ) -> Status {
-        // Ignore for now
-        Status::Success
+        fd: Fd,
+        flags: Lookupflags,
+        path: &str,
+        atim: Timestamp,
+        mtim: Timestamp,
+        fst_flags: u32,
+    ) -> StatusResult {
+        let (atim, mtim) = SetTime::from_fstflags(atim, mtim, fst_flags as Fstflags)?;
+        let abs_path = self.abs_path(fd, path)?;
+        let follow_symlinks = flags & LOOKUPFLAGS_SYMLINK_FOLLOW != 0;
+        self.set_times_path(abs_path, follow_symlinks, atim, mtim)
+            .await
     }
 
-    fn path_link(
+    async fn path_link(
         &self,
         fd: Fd,
         _old_flags: u32,
@@ -379,18 +356,18 @@ impl WasiState {
         // TODO handle flags
         let old = self.abs_path(fd, path)?;
         let new = self.abs_path(new_fd, new_path)?;
-        std::fs::hard_link(old, new)?;
+        self.hard_link(old, new).await?;
         Status::Success.into()
     }
 
     /// Open a file or directory.
-    fn path_open(
+    async fn path_open(
         &mut self,
         fd: Fd,
         dirflags: Lookupflags,
         path: &str,
         oflags: OpenFlags,
-        _fs_rights_base: Rights,
+        fs_rights_base: Rights,
         _fs_rights_inheriting: Rights,
         _fdflags: Fdflags,
         mut fd_res: Ptr<Fd>,
@@ -403,12 +380,15 @@ impl WasiState {
             path
         );
         let abs_path = self.abs_path(fd, path)?;
-        let fd = self.open(abs_path, oflags)?;
+        let write = fs_rights_base
+            & (RIGHTS_FD_WRITE | RIGHTS_FD_ALLOCATE | RIGHTS_FD_FILESTAT_SET_SIZE)
+            != 0;
+        let fd = self.open(abs_path, oflags, write).await?;
         fd_res.set(fd);
         Ok(())
     }
 
-    fn path_readlink(
+    async fn path_readlink(
         &self,
         fd: Fd,
         path: &str,
@@ -416,7 +396,7 @@ impl WasiState {
         mut buf_len: Ptr<Size>,
     ) -> StatusTrapResult {
         let file = self.abs_path(fd, path)?;
-        let path_buf = std::fs::read_link(file)?;
+        let path_buf = self.read_link(file).await?;
         let bytes = path_buf.to_str().unwrap().as_bytes();
         if bytes.len() >= buf_len.value() as usize {
             return Status::Overflow.into();
@@ -427,57 +407,78 @@ impl WasiState {
         Status::Success.into()
     }
 
-    fn path_remove_directory(&self, fd: Fd, path: &str) -> StatusResult {
+    async fn path_remove_directory(&self, fd: Fd, path: &str) -> StatusResult {
         let abs_path = self.abs_path(fd, path)?;
-        self.remove_directory(abs_path)
+        self.remove_directory(abs_path).await
     }
 
-    fn path_rename(&self, fd: Fd, path: &str, new_fd: Fd, new_path: &str) -> StatusResult {
+    async fn path_rename(&self, fd: Fd, path: &str, new_fd: Fd, new_path: &str) -> StatusResult {
         let from = self.abs_path(fd, path)?;
         let to = self.abs_path(new_fd, new_path)?;
-        self.rename(from, to)
+        self.rename(from, to).await
     }
 
-    fn path_symlink(&self, old_path: &str, fd: Fd, new_path: &str) -> StatusResult {
+    async fn path_symlink(&self, old_path: &str, fd: Fd, new_path: &str) -> StatusResult {
         let old = self.abs_path(fd, old_path)?;
         let new = self.abs_path(fd, new_path)?;
-        platform_symlink(old, new)?;
+        self.symlink(old, new).await?;
         Status::Success.into()
     }
 
-    fn path_unlink_file(&self, fd: Fd, path: &str) -> StatusResult {
+    async fn path_unlink_file(&self, fd: Fd, path: &str) -> StatusResult {
         let file = self.abs_path(fd, path)?;
-        std::fs::remove_file(file)?;
+        self.remove_file(file).await?;
         Status::Success.into()
     }
 
     // Socket
 
-    fn sock_recv(
-        &self,
-        _fd: Fd,
-        _ciovs: &[IoSlice<'_>],
-        _ri_flags: u32,
-        _ro_datalen_ptr: u32,
-        _ro_flags_ptr: u32,
-    ) -> Status {
-        // Ignore for now
-        Status::Success
+    async fn sock_recv(
+        &mut self,
+        fd: Fd,
+        iovs: &mut [IoSliceMut<'_>],
+        ri_flags: u32,
+        mut ro_datalen: Ptr<Size>,
+        mut ro_flags: Ptr<Roflags>,
+    ) -> StatusResult {
+        debug!("sock_recv fd={}, ri_flags={:X}", fd, ri_flags);
+        let ri_flags = ri_flags as Riflags;
+        let peek = ri_flags & RIFLAGS_RECV_PEEK != 0;
+        let wait_all = ri_flags & RIFLAGS_RECV_WAITALL != 0;
+        let received = self.recv(fd, iovs, peek, wait_all).await?;
+        ro_datalen.set(received as u32);
+        // Stream sockets never truncate data.
+        ro_flags.set(0);
+        Ok(())
     }
 
-    fn sock_send(
-        &self,
-        _fd: Fd,
-        _si_data: &[IoSlice<'_>],
+    async fn sock_send(
+        &mut self,
+        fd: Fd,
+        ciovs: &[IoSlice<'_>],
         _si_flags: u32,
-        _ro_datalen_ptr: u32,
-    ) -> Status {
-        // Ignore for now
-        Status::Success
+        mut so_datalen: Ptr<Size>,
+    ) -> StatusResult {
+        debug!("sock_send fd={}", fd);
+        let sent = self.send(fd, ciovs).await?;
+        so_datalen.set(sent as u32);
+        Ok(())
     }
 
-    fn sock_shutdown(&self, _fd: Fd, _how: u32) -> Status {
-        // Ignore for now
-        Status::Inval
+    fn sock_shutdown(&mut self, fd: Fd, how: u32) -> StatusResult {
+        let how = match how as Sdflags {
+            SDFLAGS_RD => Shutdown::Read,
+            SDFLAGS_WR => Shutdown::Write,
+            flags if flags == SDFLAGS_RD | SDFLAGS_WR => Shutdown::Both,
+            _ => return Err(Status::Inval),
+        };
+        self.shutdown(fd, how)
+    }
+
+    /// Accepts a new connection on a listening socket, for example one passed with `--tcplisten`.
+    async fn sock_accept(&mut self, fd: Fd, _flags: u32, mut ro_fd: Ptr<Fd>) -> StatusResult {
+        debug!("sock_accept fd={}", fd);
+        ro_fd.set(self.accept(fd).await?);
+        Ok(())
     }
 }
@@ -0,0 +1,82 @@
+use super::fs::{FileSystem, HostFileSystem};
+use super::stdio::{Input, Output};
+use crate::api::networking::tcp::TcpListener;
+
+use std::sync::Arc;
+
+/// Command line arguments, environment variables, standard streams and the filesystem visible to a
+/// process through WASI.
+///
+/// By default a process doesn't see any arguments or environment variables of the host and inherits
+/// the host's standard streams and filesystem.
+#[derive(Clone)]
+pub struct WasiConfig {
+    pub args: Vec<String>,
+    pub envs: Vec<(String, String)>,
+    pub stdin: Input,
+    pub stdout: Output,
+    pub stderr: Output,
+    pub filesystem: Arc<dyn FileSystem>,
+    /// Maximum number of open file descriptors, including the standard streams.
+    pub fd_limit: usize,
+    /// Listening sockets passed to the process as file descriptors, following the preopened
+    /// directory.
+    ///
+    /// Like the rest of the configuration, they are inherited by every process spawned from the
+    /// process. All of them accept connections from the same sockets.
+    pub tcp_listeners: Vec<TcpListener>,
+}
+
+impl Default for WasiConfig {
+    fn default() -> Self {
+        Self {
+            args: Vec::new(),
+            envs: Vec::new(),
+            stdin: Input::default(),
+            stdout: Output::default(),
+            stderr: Output::default(),
+            filesystem: Arc::new(HostFileSystem),
+            fd_limit: 1024,
+            tcp_listeners: Vec::new(),
+        }
+    }
+}
+
+impl WasiConfig {
+    /// Appends an argument.
+    pub fn arg<S: Into<String>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Sets an environment variable, replacing the previous value if it already exists.
+    pub fn env<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> &mut Self {
+        let key = key.into();
+        let value = value.into();
+        match self.envs.iter_mut().find(|(k, _)| *k == key) {
+            Some(env) => env.1 = value,
+            None => self.envs.push((key, value)),
+        }
+        self
+    }
+
+    /// Replaces the filesystem.
+    pub fn filesystem<F: FileSystem + 'static>(&mut self, filesystem: F) -> &mut Self {
+        self.filesystem = Arc::new(filesystem);
+        self
+    }
+
+    /// Passes a listening socket to the process and all processes spawned from it.
+    pub fn tcp_listener(&mut self, listener: TcpListener) -> &mut Self {
+        self.tcp_listeners.push(listener);
+        self
+    }
+
+    /// Copies all environment variables of the host process.
+    pub fn inherit_host_env(&mut self) -> &mut Self {
+        for (key, value) in std::env::vars() {
+            self.env(key, value);
+        }
+        self
+    }
+}
@@ -0,0 +1,152 @@
+use super::types::{Fd, Status, StatusResult};
+
+/// Maps file descriptors of a process to open resources.
+///
+/// New resources always get the lowest free descriptor, so descriptors of closed resources are
+/// reused. The number of open descriptors is limited, once the limit is reached opening new ones
+/// fails with `Status::Mfile`.
+pub struct FdTable<T> {
+    slots: Vec<Option<T>>,
+    open: usize,
+    limit: usize,
+}
+
+impl<T> FdTable<T> {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            slots: Vec::new(),
+            open: 0,
+            limit,
+        }
+    }
+
+    /// Inserts a resource at the lowest free descriptor and returns it.
+    pub fn insert(&mut self, value: T) -> Result<Fd, Status> {
+        if self.open >= self.limit {
+            return Err(Status::Mfile);
+        }
+        let fd = match self.slots.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.slots[fd] = Some(value);
+                fd
+            }
+            None => {
+                self.slots.push(Some(value));
+                self.slots.len() - 1
+            }
+        };
+        self.open += 1;
+        Ok(fd as Fd)
+    }
+
+    pub fn get(&self, fd: Fd) -> Result<&T, Status> {
+        self.slots
+            .get(fd as usize)
+            .and_then(Option::as_ref)
+            .ok_or(Status::Badf)
+    }
+
+    pub fn get_mut(&mut self, fd: Fd) -> Result<&mut T, Status> {
+        self.slots
+            .get_mut(fd as usize)
+            .and_then(Option::as_mut)
+            .ok_or(Status::Badf)
+    }
+
+    /// Removes the resource from the table and returns it.
+    pub fn remove(&mut self, fd: Fd) -> Result<T, Status> {
+        let value = self
+            .slots
+            .get_mut(fd as usize)
+            .and_then(Option::take)
+            .ok_or(Status::Badf)?;
+        self.open -= 1;
+        // Keep the table from growing if the highest descriptors are closed.
+        while let Some(None) = self.slots.last() {
+            self.slots.pop();
+        }
+        Ok(value)
+    }
+
+    /// Moves the resource at `from` to `to`, closing the resource previously at `to`.
+    ///
+    /// Both descriptors need to be open. Nothing changes if one of them isn't.
+    pub fn renumber(&mut self, from: Fd, to: Fd) -> StatusResult {
+        self.get(from)?;
+        self.get(to)?;
+        if from != to {
+            let value = self.remove(from)?;
+            self.slots[to as usize] = Some(value);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of open descriptors.
+    pub fn len(&self) -> usize {
+        self.open
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.open == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse_lowest_free_descriptor() {
+        let mut table = FdTable::new(8);
+        assert_eq!(table.insert("a").ok(), Some(0));
+        assert_eq!(table.insert("b").ok(), Some(1));
+        assert_eq!(table.insert("c").ok(), Some(2));
+
+        assert_eq!(table.remove(1).ok(), Some("b"));
+        assert!(matches!(table.get(1), Err(Status::Badf)));
+        assert!(matches!(table.remove(1), Err(Status::Badf)));
+        assert_eq!(table.insert("d").ok(), Some(1));
+
+        // Closing the highest descriptors shrinks the table.
+        assert_eq!(table.remove(2).ok(), Some("c"));
+        assert_eq!(table.remove(1).ok(), Some("d"));
+        assert_eq!(table.slots.len(), 1);
+        assert_eq!(table.insert("e").ok(), Some(1));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn renumber() {
+        let mut table = FdTable::new(8);
+        table.insert("a").ok();
+        table.insert("b").ok();
+        table.insert("c").ok();
+
+        assert!(table.renumber(0, 2).is_ok());
+        assert!(matches!(table.get(0), Err(Status::Badf)));
+        assert_eq!(table.get(2).ok(), Some(&"a"));
+        assert_eq!(table.len(), 2);
+
+        assert!(table.renumber(1, 1).is_ok());
+        assert_eq!(table.get(1).ok(), Some(&"b"));
+
+        // Nothing changes if one of the descriptors isn't open.
+        assert!(matches!(table.renumber(0, 1), Err(Status::Badf)));
+        assert!(matches!(table.renumber(1, 5), Err(Status::Badf)));
+        assert_eq!(table.get(1).ok(), Some(&"b"));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn limit_open_descriptors() {
+        let mut table = FdTable::new(2);
+        assert!(table.is_empty());
+        table.insert("a").ok();
+        table.insert("b").ok();
+        assert!(matches!(table.insert("c"), Err(Status::Mfile)));
+
+        table.remove(0).ok();
+        assert_eq!(table.insert("c").ok(), Some(0));
+        assert!(matches!(table.insert("d"), Err(Status::Mfile)));
+    }
+}
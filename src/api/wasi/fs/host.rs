@@ -0,0 +1,145 @@
+use super::{DirEntry, FileHandle, FileSystem, SetTime};
+use crate::api::wasi::types::{Advice, Filesize, Filestat, OpenFlags, Status};
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+#[cfg(any(
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos"
+))]
+use crate::api::wasi::unix::*;
+
+#[cfg(any(target_os = "windows"))]
+use crate::api::wasi::windows::*;
+
+/// Gives processes access to the host's filesystem.
+pub struct HostFileSystem;
+
+impl FileSystem for HostFileSystem {
+    fn open(
+        &self,
+        path: &Path,
+        flags: OpenFlags,
+        write: bool,
+    ) -> Result<Box<dyn FileHandle>, Status> {
+        if flags.fail_if_not_directory() && !fs::metadata(path)?.is_dir() {
+            return Err(Status::NotDir);
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(write || flags.create() || flags.truncate())
+            .create(flags.create())
+            .create_new(flags.create() && flags.fail_if_exists())
+            .truncate(flags.truncate())
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn filestat(&self, path: &Path) -> Result<Filestat, Status> {
+        Ok(platform_filestat(&fs::metadata(path)?))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, Status> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            // Guests can't refer to files with names that are not valid UTF-8.
+            if let Ok(name) = entry.file_name().into_string() {
+                entries.push(DirEntry {
+                    name,
+                    ino: platform_dir_entry_inode(&entry),
+                    filetype: entry.file_type()?.into(),
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), Status> {
+        Ok(fs::create_dir(path)?)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), Status> {
+        Ok(fs::remove_dir(path)?)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), Status> {
+        Ok(fs::remove_file(path)?)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), Status> {
+        Ok(fs::rename(from, to)?)
+    }
+
+    fn hard_link(&self, from: &Path, to: &Path) -> Result<(), Status> {
+        Ok(fs::hard_link(from, to)?)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> Result<(), Status> {
+        platform_symlink(target, link)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf, Status> {
+        Ok(fs::read_link(path)?)
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        follow_symlinks: bool,
+        atim: SetTime,
+        mtim: SetTime,
+    ) -> Result<(), Status> {
+        platform_set_times_path(path, follow_symlinks, atim, mtim)
+    }
+}
+
+// `Read`, `Write` and `Seek` are implemented for `&File`, so all operations can be performed on a
+// shared reference.
+impl FileHandle for File {
+    fn read_vectored(&self, iovs: &mut [IoSliceMut<'_>]) -> Result<usize, Status> {
+        Ok(Read::read_vectored(&mut &*self, iovs)?)
+    }
+
+    fn write_vectored(&self, ciovs: &[IoSlice<'_>]) -> Result<usize, Status> {
+        Ok(Write::write_vectored(&mut &*self, ciovs)?)
+    }
+
+    fn seek(&self, seek_from: SeekFrom) -> Result<u64, Status> {
+        Ok(Seek::seek(&mut &*self, seek_from)?)
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), Status> {
+        Ok(File::set_len(self, len)?)
+    }
+
+    fn filestat(&self) -> Result<Filestat, Status> {
+        Ok(platform_filestat(&self.metadata()?))
+    }
+
+    fn set_times(&self, atim: SetTime, mtim: SetTime) -> Result<(), Status> {
+        platform_set_times(self, atim, mtim)
+    }
+
+    fn sync_all(&self) -> Result<(), Status> {
+        Ok(File::sync_all(self)?)
+    }
+
+    fn sync_data(&self) -> Result<(), Status> {
+        Ok(File::sync_data(self)?)
+    }
+
+    fn allocate(&self, offset: Filesize, len: Filesize) -> Result<(), Status> {
+        platform_allocate(self, offset, len)
+    }
+
+    fn advise(&self, offset: Filesize, len: Filesize, advice: Advice) -> Result<(), Status> {
+        platform_advise(self, offset, len, advice)
+    }
+}
@@ -0,0 +1,630 @@
+use super::{DirEntry, FileHandle, FileSystem, SetTime};
+use crate::api::wasi::types::{
+    Advice, Filesize, Filestat, Filetype, Inode, OpenFlags, Status, Timestamp, ADVICE_NOREUSE,
+    ADVICE_NORMAL,
+};
+
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fs, io,
+    io::{IoSlice, IoSliceMut, Read, SeekFrom},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+// Maximum number of symbolic links followed while resolving a path.
+const MAX_SYMLINKS: usize = 32;
+// Maximum size of a single file, so that guests can't exhaust the host's memory.
+const MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// A filesystem that only exists in memory.
+///
+/// Symbolic links are followed anywhere inside of a path, except for the last component when
+/// operating on the link itself.
+pub struct MemoryFileSystem {
+    nodes: Mutex<BTreeMap<PathBuf, Arc<Node>>>,
+    next_ino: AtomicU64,
+}
+
+struct Node {
+    ino: Inode,
+    filetype: Filetype,
+    data: Mutex<NodeData>,
+}
+
+struct NodeData {
+    // Content of a regular file or the target of a symbolic link.
+    content: Vec<u8>,
+    nlink: u64,
+    atim: Timestamp,
+    mtim: Timestamp,
+    ctim: Timestamp,
+}
+
+impl NodeData {
+    /// Grows or shrinks the content to `len` bytes, filling new space with zeros.
+    fn resize(&mut self, len: u64) -> Result<(), Status> {
+        if len > MAX_FILE_SIZE {
+            return Err(Status::Fbig);
+        }
+        let len = len as usize;
+        if let Some(additional) = len.checked_sub(self.content.len()) {
+            self.content
+                .try_reserve_exact(additional)
+                .map_err(|_| Status::NoMem)?;
+        }
+        self.content.resize(len, 0);
+        Ok(())
+    }
+}
+
+impl Node {
+    fn set_times(&self, atim: SetTime, mtim: SetTime) {
+        let mut data = self.data.lock().unwrap();
+        let now = now();
+        let resolve = |time, current| match time {
+            SetTime::Keep => current,
+            SetTime::Now => now,
+            SetTime::At(timestamp) => timestamp,
+        };
+        data.atim = resolve(atim, data.atim);
+        data.mtim = resolve(mtim, data.mtim);
+        data.ctim = now;
+    }
+
+    fn filestat(&self) -> Filestat {
+        let data = self.data.lock().unwrap();
+        Filestat {
+            dev: 0,
+            ino: self.ino,
+            filetype: self.filetype,
+            nlink: data.nlink,
+            size: data.content.len() as u64,
+            atim: data.atim,
+            mtim: data.mtim,
+            ctim: data.ctim,
+        }
+    }
+}
+
+impl MemoryFileSystem {
+    /// Creates a filesystem containing only the root directory.
+    pub fn new() -> Self {
+        let fs = Self {
+            nodes: Mutex::new(BTreeMap::new()),
+            next_ino: AtomicU64::new(1),
+        };
+        let root = fs.node(Filetype::Directory, Vec::new());
+        fs.nodes.lock().unwrap().insert(PathBuf::from("/"), root);
+        fs
+    }
+
+    /// Creates a filesystem with the content of a tar archive.
+    pub fn from_tar<R: Read>(archive: R) -> io::Result<Self> {
+        let fs = Self::new();
+        for entry in tar::Archive::new(archive).entries()? {
+            let mut entry = entry?;
+            let path = normalize(&entry.path()?);
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_dir() {
+                fs.insert(&path, Filetype::Directory, Vec::new());
+            } else if entry_type.is_symlink() {
+                let target = entry.link_name()?.unwrap_or_default().into_owned();
+                fs.insert(&path, Filetype::SymbolicLink, path_to_bytes(&target));
+            } else if entry_type.is_hard_link() {
+                let target = normalize(&entry.link_name()?.unwrap_or_default());
+                fs.hard_link(&target, &path).map_err(status_to_io_error)?;
+            } else if entry_type.is_file() {
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content)?;
+                fs.insert(&path, Filetype::RegularFile, content);
+            }
+        }
+        Ok(fs)
+    }
+
+    /// Creates a filesystem with a copy of the content of a host directory.
+    pub fn from_directory<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        let fs = Self::new();
+        fs.copy_directory(directory.as_ref(), Path::new("/"))?;
+        Ok(fs)
+    }
+
+    fn copy_directory(&self, host_path: &Path, path: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(host_path)? {
+            let entry = entry?;
+            let host_path = entry.path();
+            let path = path.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.insert(&path, Filetype::Directory, Vec::new());
+                self.copy_directory(&host_path, &path)?;
+            } else if file_type.is_symlink() {
+                let target = fs::read_link(&host_path)?;
+                self.insert(&path, Filetype::SymbolicLink, path_to_bytes(&target));
+            } else if file_type.is_file() {
+                self.insert(&path, Filetype::RegularFile, fs::read(&host_path)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn node(&self, filetype: Filetype, content: Vec<u8>) -> Arc<Node> {
+        let now = now();
+        Arc::new(Node {
+            ino: self.next_ino.fetch_add(1, Ordering::Relaxed),
+            filetype,
+            data: Mutex::new(NodeData {
+                content,
+                nlink: 1,
+                atim: now,
+                mtim: now,
+                ctim: now,
+            }),
+        })
+    }
+
+    /// Inserts a node at an already normalized `path`, creating missing parent directories.
+    fn insert(&self, path: &Path, filetype: Filetype, content: Vec<u8>) {
+        let mut nodes = self.nodes.lock().unwrap();
+        for parent in path.ancestors().skip(1) {
+            if !nodes.contains_key(parent) {
+                nodes.insert(
+                    parent.to_owned(),
+                    self.node(Filetype::Directory, Vec::new()),
+                );
+            }
+        }
+        nodes.insert(path.to_owned(), self.node(filetype, content));
+    }
+
+    /// Resolves `path` component by component, following symbolic links in the parent directories.
+    /// The last component is only followed if `follow` is true.
+    ///
+    /// Fails with `Loop` if more than `MAX_SYMLINKS` links are followed.
+    fn resolve(
+        nodes: &BTreeMap<PathBuf, Arc<Node>>,
+        path: &Path,
+        follow: bool,
+    ) -> Result<PathBuf, Status> {
+        let mut resolved = PathBuf::from("/");
+        // Components that are still left to resolve, the next one is at the end.
+        let mut pending = components(path);
+        let mut followed = 0;
+        while let Some(name) = pending.pop() {
+            if name.as_os_str() == ".." {
+                resolved.pop();
+                continue;
+            }
+            resolved.push(name);
+            if !follow && pending.is_empty() {
+                break;
+            }
+            let node = match nodes.get(&resolved) {
+                Some(node) if matches!(node.filetype, Filetype::SymbolicLink) => node,
+                _ => continue,
+            };
+            followed += 1;
+            if followed > MAX_SYMLINKS {
+                return Err(Status::Loop);
+            }
+            let target = bytes_to_path(&node.data.lock().unwrap().content);
+            resolved.pop();
+            if target.has_root() {
+                resolved = PathBuf::from("/");
+            }
+            pending.extend(components(&target));
+        }
+        Ok(resolved)
+    }
+
+    /// Checks that the parent of `path` exists and is a directory.
+    fn check_parent(nodes: &BTreeMap<PathBuf, Arc<Node>>, path: &Path) -> Result<(), Status> {
+        let parent = path.parent().ok_or(Status::Exist)?;
+        match nodes.get(parent) {
+            Some(node) if matches!(node.filetype, Filetype::Directory) => Ok(()),
+            Some(_) => Err(Status::NotDir),
+            None => Err(Status::NoEnt),
+        }
+    }
+
+    fn has_children(nodes: &BTreeMap<PathBuf, Arc<Node>>, path: &Path) -> bool {
+        nodes.keys().any(|key| key.parent() == Some(path))
+    }
+}
+
+impl Default for MemoryFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn open(
+        &self,
+        path: &Path,
+        flags: OpenFlags,
+        write: bool,
+    ) -> Result<Box<dyn FileHandle>, Status> {
+        let write = write || flags.create() || flags.truncate();
+        let mut nodes = self.nodes.lock().unwrap();
+        let path = Self::resolve(&nodes, path, true)?;
+        let node = match nodes.get(&path) {
+            Some(_) if flags.create() && flags.fail_if_exists() => return Err(Status::Exist),
+            Some(node) => node.clone(),
+            None if flags.create() && !flags.fail_if_not_directory() => {
+                Self::check_parent(&nodes, &path)?;
+                let node = self.node(Filetype::RegularFile, Vec::new());
+                nodes.insert(path, node.clone());
+                node
+            }
+            None => return Err(Status::NoEnt),
+        };
+
+        let is_directory = matches!(node.filetype, Filetype::Directory);
+        if flags.fail_if_not_directory() && !is_directory {
+            return Err(Status::NotDir);
+        }
+        if write && is_directory {
+            return Err(Status::IsDir);
+        }
+        if flags.truncate() {
+            let mut data = node.data.lock().unwrap();
+            data.content.clear();
+            data.mtim = now();
+        }
+
+        Ok(Box::new(MemoryFile {
+            node,
+            position: Mutex::new(0),
+            writable: write,
+        }))
+    }
+
+    fn filestat(&self, path: &Path) -> Result<Filestat, Status> {
+        let nodes = self.nodes.lock().unwrap();
+        let path = Self::resolve(&nodes, path, true)?;
+        match nodes.get(&path) {
+            Some(node) => Ok(node.filestat()),
+            None => Err(Status::NoEnt),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, Status> {
+        let nodes = self.nodes.lock().unwrap();
+        let path = Self::resolve(&nodes, path, true)?;
+        match nodes.get(&path) {
+            Some(node) if matches!(node.filetype, Filetype::Directory) => {}
+            Some(_) => return Err(Status::NotDir),
+            None => return Err(Status::NoEnt),
+        }
+
+        let entries = nodes
+            .iter()
+            .filter(|(key, _)| key.parent() == Some(&path))
+            .filter_map(|(key, node)| {
+                let name = key.file_name()?.to_str()?.to_string();
+                Some(DirEntry {
+                    name,
+                    ino: node.ino,
+                    filetype: node.filetype,
+                })
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), Status> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let path = Self::resolve(&nodes, path, false)?;
+        if nodes.contains_key(&path) {
+            return Err(Status::Exist);
+        }
+        Self::check_parent(&nodes, &path)?;
+        nodes.insert(path, self.node(Filetype::Directory, Vec::new()));
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), Status> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let path = Self::resolve(&nodes, path, false)?;
+        match nodes.get(&path) {
+            Some(_) if path.parent().is_none() => return Err(Status::Busy),
+            Some(node) if matches!(node.filetype, Filetype::Directory) => {}
+            Some(_) => return Err(Status::NotDir),
+            None => return Err(Status::NoEnt),
+        }
+        if Self::has_children(&nodes, &path) {
+            return Err(Status::NotEmpty);
+        }
+        nodes.remove(&path);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), Status> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let path = Self::resolve(&nodes, path, false)?;
+        match nodes.get(&path) {
+            Some(node) if matches!(node.filetype, Filetype::Directory) => Err(Status::IsDir),
+            Some(node) => {
+                let mut data = node.data.lock().unwrap();
+                data.nlink -= 1;
+                data.ctim = now();
+                drop(data);
+                nodes.remove(&path);
+                Ok(())
+            }
+            None => Err(Status::NoEnt),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), Status> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let from = Self::resolve(&nodes, from, false)?;
+        let to = Self::resolve(&nodes, to, false)?;
+        let node = nodes.get(&from).ok_or(Status::NoEnt)?.clone();
+        if from == to {
+            return Ok(());
+        }
+        if from.parent().is_none() || to.starts_with(&from) {
+            return Err(Status::Inval);
+        }
+        Self::check_parent(&nodes, &to)?;
+
+        let is_directory = matches!(node.filetype, Filetype::Directory);
+        if let Some(existing) = nodes.get(&to) {
+            match (
+                is_directory,
+                matches!(existing.filetype, Filetype::Directory),
+            ) {
+                (true, true) if Self::has_children(&nodes, &to) => return Err(Status::NotEmpty),
+                (true, false) => return Err(Status::NotDir),
+                (false, true) => return Err(Status::IsDir),
+                _ => {}
+            }
+        }
+
+        // Move the node and, if it's a directory, everything inside of it.
+        let moved: Vec<PathBuf> = nodes
+            .keys()
+            .filter(|key| key.starts_with(&from))
+            .cloned()
+            .collect();
+        for old_path in moved {
+            let new_path = to.join(old_path.strip_prefix(&from).unwrap());
+            let node = nodes.remove(&old_path).unwrap();
+            nodes.insert(new_path, node);
+        }
+        node.data.lock().unwrap().ctim = now();
+        Ok(())
+    }
+
+    fn hard_link(&self, from: &Path, to: &Path) -> Result<(), Status> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let from = Self::resolve(&nodes, from, false)?;
+        let to = Self::resolve(&nodes, to, false)?;
+        let node = nodes.get(&from).ok_or(Status::NoEnt)?.clone();
+        if matches!(node.filetype, Filetype::Directory) {
+            return Err(Status::Perm);
+        }
+        if nodes.contains_key(&to) {
+            return Err(Status::Exist);
+        }
+        Self::check_parent(&nodes, &to)?;
+
+        let mut data = node.data.lock().unwrap();
+        data.nlink += 1;
+        data.ctim = now();
+        drop(data);
+        nodes.insert(to, node);
+        Ok(())
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> Result<(), Status> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let link = Self::resolve(&nodes, link, false)?;
+        if nodes.contains_key(&link) {
+            return Err(Status::Exist);
+        }
+        Self::check_parent(&nodes, &link)?;
+        let node = self.node(Filetype::SymbolicLink, path_to_bytes(target));
+        nodes.insert(link, node);
+        Ok(())
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf, Status> {
+        let nodes = self.nodes.lock().unwrap();
+        match nodes.get(&Self::resolve(&nodes, path, false)?) {
+            Some(node) if matches!(node.filetype, Filetype::SymbolicLink) => {
+                Ok(bytes_to_path(&node.data.lock().unwrap().content))
+            }
+            Some(_) => Err(Status::Inval),
+            None => Err(Status::NoEnt),
+        }
+    }
+
+    fn set_times(
+        &self,
+        path: &Path,
+        follow_symlinks: bool,
+        atim: SetTime,
+        mtim: SetTime,
+    ) -> Result<(), Status> {
+        let nodes = self.nodes.lock().unwrap();
+        let path = Self::resolve(&nodes, path, follow_symlinks)?;
+        let node = nodes.get(&path).ok_or(Status::NoEnt)?;
+        node.set_times(atim, mtim);
+        Ok(())
+    }
+}
+
+/// A file or directory opened in a `MemoryFileSystem`.
+///
+/// The file stays accessible through the handle even if it's removed from the filesystem.
+struct MemoryFile {
+    node: Arc<Node>,
+    position: Mutex<u64>,
+    writable: bool,
+}
+
+impl MemoryFile {
+    fn check_not_directory(&self) -> Result<(), Status> {
+        match self.node.filetype {
+            Filetype::Directory => Err(Status::IsDir),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_writable(&self) -> Result<(), Status> {
+        self.check_not_directory()?;
+        if !self.writable {
+            return Err(Status::Badf);
+        }
+        Ok(())
+    }
+}
+
+impl FileHandle for MemoryFile {
+    fn read_vectored(&self, iovs: &mut [IoSliceMut<'_>]) -> Result<usize, Status> {
+        self.check_not_directory()?;
+        let mut position = self.position.lock().unwrap();
+        let mut data = self.node.data.lock().unwrap();
+        let start = (*position as usize).min(data.content.len());
+        let read = (&data.content[start..]).read_vectored(iovs)?;
+        *position += read as u64;
+        data.atim = now();
+        Ok(read)
+    }
+
+    fn write_vectored(&self, ciovs: &[IoSlice<'_>]) -> Result<usize, Status> {
+        self.check_writable()?;
+        let mut position = self.position.lock().unwrap();
+        let mut data = self.node.data.lock().unwrap();
+        let written = ciovs
+            .iter()
+            .try_fold(0u64, |total, ciov| total.checked_add(ciov.len() as u64))
+            .ok_or(Status::Fbig)?;
+        let end = position.checked_add(written).ok_or(Status::Fbig)?;
+        if end > data.content.len() as u64 {
+            data.resize(end)?;
+        }
+        let mut offset = *position as usize;
+        for ciov in ciovs {
+            data.content[offset..offset + ciov.len()].copy_from_slice(ciov);
+            offset += ciov.len();
+        }
+        *position = end;
+        data.mtim = now();
+        Ok(written as usize)
+    }
+
+    fn seek(&self, seek_from: SeekFrom) -> Result<u64, Status> {
+        let mut position = self.position.lock().unwrap();
+        let len = self.node.data.lock().unwrap().content.len() as i64;
+        let new_position = match seek_from {
+            SeekFrom::Start(offset) => i64::try_from(offset).ok(),
+            SeekFrom::Current(delta) => (*position as i64).checked_add(delta),
+            SeekFrom::End(delta) => len.checked_add(delta),
+        };
+        match new_position {
+            Some(new_position) if new_position >= 0 => *position = new_position as u64,
+            _ => return Err(Status::Inval),
+        }
+        Ok(*position)
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), Status> {
+        self.check_writable()?;
+        let mut data = self.node.data.lock().unwrap();
+        data.resize(len)?;
+        data.mtim = now();
+        Ok(())
+    }
+
+    fn filestat(&self) -> Result<Filestat, Status> {
+        Ok(self.node.filestat())
+    }
+
+    fn set_times(&self, atim: SetTime, mtim: SetTime) -> Result<(), Status> {
+        self.node.set_times(atim, mtim);
+        Ok(())
+    }
+
+    // Everything is already "stored" once it's written.
+    fn sync_all(&self) -> Result<(), Status> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<(), Status> {
+        Ok(())
+    }
+
+    fn allocate(&self, offset: Filesize, len: Filesize) -> Result<(), Status> {
+        self.check_writable()?;
+        let end = offset.checked_add(len).ok_or(Status::Fbig)?;
+        let mut data = self.node.data.lock().unwrap();
+        if (data.content.len() as u64) < end {
+            data.resize(end)?;
+            data.mtim = now();
+        }
+        Ok(())
+    }
+
+    fn advise(&self, _offset: Filesize, _len: Filesize, advice: Advice) -> Result<(), Status> {
+        match advice {
+            ADVICE_NORMAL..=ADVICE_NOREUSE => Ok(()),
+            _ => Err(Status::Inval),
+        }
+    }
+}
+
+/// Returns an absolute path without `.` and `..` components.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    normalized
+}
+
+// Returns the names and `..` components of `path` in reverse order.
+fn components(path: &Path) -> Vec<PathBuf> {
+    path.components()
+        .rev()
+        .filter(|component| matches!(component, Component::Normal(_) | Component::ParentDir))
+        .map(|component| PathBuf::from(component.as_os_str()))
+        .collect()
+}
+
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn status_to_io_error(status: Status) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        format!("WASI error {}", status as u16),
+    )
+}
+
+fn now() -> Timestamp {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as Timestamp)
+        .unwrap_or(0)
+}
@@ -0,0 +1,166 @@
+//! Filesystem backends used by the WASI implementation.
+//!
+//! `WasiState` doesn't touch the host filesystem directly, all operations go through an
+//! implementation of the `FileSystem` trait selected in the `WasiConfig` of a process:
+//! * `HostFileSystem` forwards all operations to the host's filesystem.
+//! * `MemoryFileSystem` keeps everything in memory, optionally seeded from a tar archive or a
+//!   host directory. Processes using it don't have any access to the host's filesystem.
+//!
+//! All paths passed to a backend are absolute.
+
+mod host;
+mod memory;
+
+pub use host::HostFileSystem;
+pub use memory::MemoryFileSystem;
+
+use super::types::{
+    Advice, Filesize, Filestat, Filetype, Fstflags, Inode, OpenFlags, Status, Timestamp,
+    FSTFLAGS_ATIM, FSTFLAGS_ATIM_NOW, FSTFLAGS_MTIM, FSTFLAGS_MTIM_NOW,
+};
+
+use std::{
+    io::{IoSlice, IoSliceMut, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub trait FileSystem: Send + Sync {
+    /// Opens a file or directory, for writing if `write` is true. Files that are created or
+    /// truncated are always opened for writing.
+    fn open(
+        &self,
+        path: &Path,
+        flags: OpenFlags,
+        write: bool,
+    ) -> Result<Box<dyn FileHandle>, Status>;
+
+    /// Returns the metadata of a file or directory, following symbolic links.
+    fn filestat(&self, path: &Path) -> Result<Filestat, Status>;
+
+    /// Returns all entries of a directory, except `.` and `..`.
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, Status>;
+
+    fn create_dir(&self, path: &Path) -> Result<(), Status>;
+
+    fn remove_dir(&self, path: &Path) -> Result<(), Status>;
+
+    fn remove_file(&self, path: &Path) -> Result<(), Status>;
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), Status>;
+
+    fn hard_link(&self, from: &Path, to: &Path) -> Result<(), Status>;
+
+    /// Creates a symbolic link at `link` pointing to `target`.
+    fn symlink(&self, target: &Path, link: &Path) -> Result<(), Status>;
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf, Status>;
+
+    /// Sets the access and modification time of a file or directory.
+    fn set_times(
+        &self,
+        path: &Path,
+        follow_symlinks: bool,
+        atim: SetTime,
+        mtim: SetTime,
+    ) -> Result<(), Status>;
+}
+
+/// A file or directory opened through a `FileSystem`.
+pub trait FileHandle: Send + Sync {
+    fn read_vectored(&self, iovs: &mut [IoSliceMut<'_>]) -> Result<usize, Status>;
+
+    fn write_vectored(&self, ciovs: &[IoSlice<'_>]) -> Result<usize, Status>;
+
+    fn seek(&self, seek_from: SeekFrom) -> Result<u64, Status>;
+
+    fn set_len(&self, len: u64) -> Result<(), Status>;
+
+    fn filestat(&self) -> Result<Filestat, Status>;
+
+    fn set_times(&self, atim: SetTime, mtim: SetTime) -> Result<(), Status>;
+
+    /// Flushes the data and metadata of the file to the storage device.
+    fn sync_all(&self) -> Result<(), Status>;
+
+    /// Flushes the data of the file to the storage device.
+    fn sync_data(&self) -> Result<(), Status>;
+
+    /// Makes sure that space is allocated for the range `offset..offset + len`, growing the file
+    /// if it's shorter.
+    fn allocate(&self, offset: Filesize, len: Filesize) -> Result<(), Status>;
+
+    /// Announces how the range `offset..offset + len` is going to be accessed. Only a hint.
+    fn advise(&self, offset: Filesize, len: Filesize, advice: Advice) -> Result<(), Status>;
+}
+
+/// Reads the whole content of the file at `path`.
+pub fn read_to_end(fs: &dyn FileSystem, path: &Path) -> Result<Vec<u8>, Status> {
+    let file = fs.open(path, OpenFlags::new(), false)?;
+    let mut content = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let read = file.read_vectored(&mut [IoSliceMut::new(&mut buffer)])?;
+        if read == 0 {
+            return Ok(content);
+        }
+        content.extend_from_slice(&buffer[..read]);
+    }
+}
+
+/// Reads a file on the blocking thread pool, relative paths start at the preopened root.
+pub async fn read_file(fs: Arc<dyn FileSystem>, path: &str) -> Result<Vec<u8>, Status> {
+    let path = Path::new("/").join(path);
+    smol::unblock(move || read_to_end(fs.as_ref(), &path)).await
+}
+
+/// An entry of a directory listing.
+#[derive(Debug)]
+pub struct DirEntry {
+    pub name: String,
+    pub ino: Inode,
+    pub filetype: Filetype,
+}
+
+impl DirEntry {
+    pub fn from_filestat(name: &str, filestat: &Filestat) -> Self {
+        DirEntry {
+            name: name.to_string(),
+            ino: filestat.ino,
+            filetype: filestat.filetype,
+        }
+    }
+}
+
+/// A new value for a timestamp of a file.
+#[derive(Clone, Copy, Debug)]
+pub enum SetTime {
+    /// Leave the timestamp unchanged.
+    Keep,
+    /// Set the timestamp to the current time.
+    Now,
+    At(Timestamp),
+}
+
+impl SetTime {
+    /// Decodes the new access and modification times from WASI `Fstflags`.
+    ///
+    /// Returns `Inval` if a timestamp should be set both to a value and the current time.
+    pub fn from_fstflags(
+        atim: Timestamp,
+        mtim: Timestamp,
+        fst_flags: Fstflags,
+    ) -> Result<(SetTime, SetTime), Status> {
+        let decode =
+            |value, flag, now_flag| match (fst_flags & flag != 0, fst_flags & now_flag != 0) {
+                (true, true) => Err(Status::Inval),
+                (true, false) => Ok(SetTime::At(value)),
+                (false, true) => Ok(SetTime::Now),
+                (false, false) => Ok(SetTime::Keep),
+            };
+        Ok((
+            decode(atim, FSTFLAGS_ATIM, FSTFLAGS_ATIM_NOW)?,
+            decode(mtim, FSTFLAGS_MTIM, FSTFLAGS_MTIM_NOW)?,
+        ))
+    }
+}
@@ -1,7 +1,15 @@
 pub mod api;
+pub mod config;
+pub mod fd_table;
+pub mod fs;
 pub mod state;
+pub mod stdio;
 pub mod types;
 
+pub use config::WasiConfig;
+pub use fs::{FileSystem, HostFileSystem, MemoryFileSystem};
+pub use stdio::{Buffer, Input, Output};
+
 #[cfg(any(
     target_os = "freebsd",
     target_os = "linux",
@@ -1,214 +1,458 @@
-use super::types::{Filestat, OpenFlags, Status, StatusResult};
-use std::convert::TryInto;
+use super::config::WasiConfig;
+use super::fd_table::FdTable;
+use super::fs::{DirEntry, FileHandle, FileSystem, SetTime};
+use super::stdio::{HostStream, OutputStream, Stdin};
+use super::types::{
+    Advice, Dircookie, Fd, Filesize, Filestat, Filetype, OpenFlags, Status, StatusResult, WasiEnv,
+};
+use crate::api::networking::tcp::{TcpListener, TcpStream};
 
+use smol::unblock;
 use std::{
-    fs,
-    fs::{File, OpenOptions},
-    io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write},
+    cell::RefCell,
+    io::{IoSlice, IoSliceMut, SeekFrom},
+    net::Shutdown,
     path::{Path, PathBuf},
-    time::SystemTime,
+    rc::Rc,
+    sync::Arc,
     u32,
 };
 
-type Fd = u32;
+// Maximum size of a single read from files without a known size.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
 
 pub struct WasiState {
-    fds: Vec<Option<FileDesc>>,
+    fs: Arc<dyn FileSystem>,
+    fds: Rc<RefCell<FdTable<Descriptor>>>,
+    pub args: WasiEnv,
+    pub envs: WasiEnv,
+    pub stdin: Stdin,
+    pub stdout: OutputStream,
+    pub stderr: OutputStream,
 }
 
 // TODO: create AbsPath
 
 impl WasiState {
-    pub fn new() -> Self {
+    pub fn new(process_id: u64, config: &WasiConfig) -> Self {
+        let fs = config.filesystem.clone();
+        let mut fds = FdTable::new(config.fd_limit);
+        // Descriptors that don't fit into the limit are not opened.
+        fds.insert(Descriptor::Stdin).ok();
+        fds.insert(Descriptor::Stdout).ok();
+        fds.insert(Descriptor::Stderr).ok();
         // TODO cannot trap if open / fails
+        if let Ok(mut root) = FileDesc::open(fs.as_ref(), "/", OpenFlags::new(), false) {
+            root.preopened = true;
+            fds.insert(Descriptor::File(root)).ok();
+        }
+        for listener in config.tcp_listeners.iter() {
+            fds.insert(Descriptor::TcpListener(listener.clone())).ok();
+        }
         Self {
-            fds: vec![None, None, None, FileDesc::open("/").ok()],
+            fs,
+            fds: Rc::new(RefCell::new(fds)),
+            args: WasiEnv::args(config.args.iter().cloned()),
+            envs: WasiEnv::env_vars(config.envs.iter().cloned()),
+            stdin: Stdin::new(config.stdin.clone()),
+            stdout: OutputStream::new(config.stdout.clone(), HostStream::Stdout, process_id),
+            stderr: OutputStream::new(config.stderr.clone(), HostStream::Stderr, process_id),
+        }
+    }
+
+    /// Returns the file descriptor table of the process.
+    ///
+    /// Other APIs use it to turn their resources into file descriptors.
+    pub fn fd_table(&self) -> Rc<RefCell<FdTable<Descriptor>>> {
+        self.fds.clone()
+    }
+
+    /// Returns the filesystem of the process, its root is the preopened directory.
+    pub fn filesystem(&self) -> Arc<dyn FileSystem> {
+        self.fs.clone()
+    }
+
+    // The descriptor is cloned, so that the table isn't borrowed while waiting on it.
+    fn descriptor(&self, fd: Fd) -> Result<Descriptor, Status> {
+        self.fds.borrow().get(fd).map(Clone::clone)
+    }
+
+    fn file_desc(&self, fd: Fd) -> Result<FileDesc, Status> {
+        match self.descriptor(fd)? {
+            Descriptor::File(f) => Ok(f),
+            _ => Err(Status::Badf),
         }
     }
 
-    fn get_mut_file_desc(&mut self, fd: Fd) -> Result<&mut FileDesc, Status> {
-        self.fds
-            .get_mut(fd as usize)
-            .ok_or(Status::Badf)?
-            .as_mut()
-            .ok_or(Status::Badf)
+    fn file(&self, fd: Fd) -> Result<Arc<dyn FileHandle>, Status> {
+        Ok(self.file_desc(fd)?.file)
+    }
+
+    fn tcp_stream(&self, fd: Fd) -> Result<TcpStream, Status> {
+        match self.descriptor(fd)? {
+            Descriptor::TcpStream(stream) => Ok(stream),
+            Descriptor::TcpListener(_) => Err(Status::NotConn),
+            _ => Err(Status::NotSock),
+        }
     }
 
-    fn get_file_desc(&self, fd: Fd) -> Result<&FileDesc, Status> {
-        self.fds
-            .get(fd as usize)
-            .ok_or(Status::Badf)?
-            .as_ref()
-            .ok_or(Status::Badf)
+    /// Returns the name of the directory if `fd` is a preopened directory.
+    pub fn preopen_name(&self, fd: Fd) -> Result<&'static str, Status> {
+        match self.fds.borrow().get(fd)? {
+            Descriptor::File(f) if f.preopened => Ok("/"),
+            _ => Err(Status::Badf),
+        }
     }
 
     pub fn abs_path(&self, from: Fd, rel_path: &str) -> Result<PathBuf, Status> {
-        let f = self.get_file_desc(from)?;
-        Ok(f.path.join(rel_path).into())
+        Ok(self.get_path(from)?.join(rel_path))
     }
 
     pub fn get_path(&self, from: Fd) -> Result<PathBuf, Status> {
-        let f = self.get_file_desc(from)?;
-        Ok(f.path.clone())
-    }
-
-    pub fn open<P: AsRef<Path>>(&mut self, abs_path: P, flags: OpenFlags) -> Result<Fd, Status> {
-        self.fds
-            .push(Some(FileDesc::open_with_flags(abs_path, flags)?));
-        Ok(self.fds.len() as u32 - 1)
-    }
-
-    pub fn write(&mut self, fd: Fd, ciovs: &[IoSlice<'_>]) -> Result<usize, Status> {
-        let f = self.get_mut_file_desc(fd)?;
-        Ok(f.file.write_vectored(ciovs)?)
-    }
-
-    pub fn read(&mut self, fd: Fd, iovs: &mut [IoSliceMut<'_>]) -> Result<usize, Status> {
-        let f = self.get_mut_file_desc(fd)?;
-        Ok(f.file.read_vectored(iovs)?)
-    }
-
-    pub fn close(&mut self, fd: Fd) {
-        self.fds[fd as usize] = None;
-    }
-
-    pub fn tell(&mut self, fd: Fd) -> Result<u64, Status> {
-        let f = self.get_mut_file_desc(fd)?;
-        Ok(f.file.seek(SeekFrom::Current(0))?)
-    }
-
-    pub fn seek(&mut self, fd: Fd, seek_from: SeekFrom) -> Result<u64, Status> {
-        let f = self.get_mut_file_desc(fd)?;
-        Ok(f.file.seek(seek_from)?)
-    }
-
-    pub fn create_directory<P: AsRef<Path>>(&self, abs_path: P) -> StatusResult {
-        Ok(fs::create_dir(abs_path)?)
-    }
-
-    pub fn remove_directory<P: AsRef<Path>>(&self, abs_path: P) -> StatusResult {
-        Ok(fs::remove_dir(abs_path)?)
-    }
-
-    pub fn rename<P: AsRef<Path>>(&self, abs_from: P, abs_to: P) -> StatusResult {
-        Ok(fs::rename(abs_from, abs_to)?)
-    }
-
-    pub fn filestat(&self, fd: Fd) -> Result<Filestat, Status> {
-        if let Some(Some(f)) = self.fds.get(fd as usize) {
-            let metadata = fs::metadata(&f.path)?;
-            // TODO repeated and not sure how timestamp is actually represented
-            // NOTE try_into cast should work correctly on glibc systems as tv_nsec will be less
-            // than 10^9
-            // see https://www.gnu.org/software/libc/manual/html_node/Time-Types.html
-            let atim = metadata
-                .accessed()
-                .unwrap()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
-                .try_into()
-                .unwrap();
-            let mtim = metadata
-                .modified()
-                .unwrap()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
-                .try_into()
-                .unwrap();
-            let ctim = metadata
-                .created()
-                .unwrap()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
-                .try_into()
-                .unwrap();
-            Ok(Filestat {
-                dev: 0,
-                ino: 0,
-                filetype: metadata.file_type().into(),
-                nlink: 0,
-                size: metadata.len(),
-                atim,
-                mtim,
-                ctim,
-            })
-        } else {
-            Err(Status::Badf)
-        }
+        Ok(self.file_desc(from)?.path)
     }
 
-    pub fn filestat_path<P: AsRef<Path>>(&self, abs_path: P) -> Result<Filestat, Status> {
-        let metadata = fs::metadata(&abs_path)?;
-        // NOTE try_into cast should work correctly on glibc systems as tv_nsec will be less
-        // than 10^9
-        // see https://www.gnu.org/software/libc/manual/html_node/Time-Types.html
-        let atim = metadata
-            .accessed()
-            .unwrap()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos()
-            .try_into()
-            .unwrap();
-        let mtim = metadata
-            .modified()
-            .unwrap()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos()
-            .try_into()
-            .unwrap();
-        let ctim = metadata
-            .created()
-            .unwrap()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos()
-            .try_into()
-            .unwrap();
-        Ok(Filestat {
-            dev: 0,
-            ino: 0,
-            filetype: metadata.file_type().into(),
-            nlink: 0,
-            size: metadata.len(),
-            atim,
-            mtim,
-            ctim,
+    pub async fn open(
+        &mut self,
+        abs_path: PathBuf,
+        flags: OpenFlags,
+        write: bool,
+    ) -> Result<Fd, Status> {
+        let fs = self.fs.clone();
+        let file_desc =
+            unblock(move || FileDesc::open(fs.as_ref(), abs_path, flags, write)).await?;
+        self.fds.borrow_mut().insert(Descriptor::File(file_desc))
+    }
+
+    /// Writes all `ciovs` to the file, socket or output stream.
+    ///
+    /// File data is copied into an owned buffer first, because the guest memory can't be accessed
+    /// from the blocking thread pool.
+    pub async fn write(&mut self, fd: Fd, ciovs: &[IoSlice<'_>]) -> Result<usize, Status> {
+        let file = match self.descriptor(fd)? {
+            Descriptor::Stdin => return Err(Status::Badf),
+            Descriptor::Stdout => return self.stdout.write(ciovs).await,
+            Descriptor::Stderr => return self.stderr.write(ciovs).await,
+            Descriptor::TcpStream(stream) => return Ok(stream.write_vectored(ciovs).await?),
+            Descriptor::TcpListener(_) => return Err(Status::NotConn),
+            Descriptor::File(f) => f.file,
+        };
+        let buffer: Vec<u8> = ciovs.iter().flat_map(|c| c.iter().copied()).collect();
+        unblock(move || file.write_vectored(&[IoSlice::new(&buffer)])).await
+    }
+
+    /// Reads into `iovs` from the file, socket or input stream.
+    ///
+    /// File data is read into an owned buffer first and afterwards copied into the guest memory. The
+    /// buffer is only as large as the data left in the file, not the guest's buffers.
+    pub async fn read(&mut self, fd: Fd, iovs: &mut [IoSliceMut<'_>]) -> Result<usize, Status> {
+        let file = match self.descriptor(fd)? {
+            Descriptor::Stdin => return self.stdin.read(iovs).await,
+            Descriptor::Stdout | Descriptor::Stderr => return Err(Status::Badf),
+            Descriptor::TcpStream(stream) => return Ok(stream.read_vectored(iovs).await?),
+            Descriptor::TcpListener(_) => return Err(Status::NotConn),
+            Descriptor::File(f) => f.file,
+        };
+        let len = iovs.iter().map(|iov| iov.len()).sum();
+        let buffer = unblock(move || {
+            let mut buffer = vec![0; read_len(file.as_ref(), len)?];
+            let read = file.read_vectored(&mut [IoSliceMut::new(&mut buffer)])?;
+            buffer.truncate(read);
+            Ok::<_, Status>(buffer)
         })
+        .await?;
+
+        let mut remaining = buffer.as_slice();
+        for iov in iovs.iter_mut() {
+            if remaining.is_empty() {
+                break;
+            }
+            let n = iov.len().min(remaining.len());
+            iov[..n].copy_from_slice(&remaining[..n]);
+            remaining = &remaining[n..];
+        }
+        Ok(buffer.len())
+    }
+
+    /// Receives data from a socket.
+    ///
+    /// If `peek` is set the data is not removed from the socket and only the first non-empty buffer
+    /// is filled. If `wait_all` is set the call only returns once all buffers are full or the
+    /// connection is closed.
+    pub async fn recv(
+        &mut self,
+        fd: Fd,
+        iovs: &mut [IoSliceMut<'_>],
+        peek: bool,
+        wait_all: bool,
+    ) -> Result<usize, Status> {
+        let stream = self.tcp_stream(fd)?;
+        if peek {
+            return match iovs.iter_mut().find(|iov| !iov.is_empty()) {
+                Some(buf) => Ok(stream.peek(buf).await?),
+                None => Ok(0),
+            };
+        }
+        if !wait_all {
+            return Ok(stream.read_vectored(iovs).await?);
+        }
+
+        let mut received = 0;
+        for iov in iovs.iter_mut() {
+            let mut filled = 0;
+            while filled < iov.len() {
+                let n = stream
+                    .read_vectored(&mut [IoSliceMut::new(&mut iov[filled..])])
+                    .await?;
+                if n == 0 {
+                    return Ok(received + filled);
+                }
+                filled += n;
+            }
+            received += filled;
+        }
+        Ok(received)
+    }
+
+    pub async fn send(&mut self, fd: Fd, ciovs: &[IoSlice<'_>]) -> Result<usize, Status> {
+        let stream = self.tcp_stream(fd)?;
+        Ok(stream.write_vectored(ciovs).await?)
+    }
+
+    pub fn shutdown(&mut self, fd: Fd, how: Shutdown) -> StatusResult {
+        let stream = self.tcp_stream(fd)?;
+        Ok(stream.shutdown(how)?)
     }
 
-    pub fn set_size(&mut self, fd: Fd, len: u64) -> Result<(), Status> {
-        let f = self.get_mut_file_desc(fd)?;
-        f.file.set_len(len)?;
+    /// Accepts a new connection on a listening socket and returns the descriptor of it.
+    pub async fn accept(&mut self, fd: Fd) -> Result<Fd, Status> {
+        let listener = match self.descriptor(fd)? {
+            Descriptor::TcpListener(listener) => listener,
+            Descriptor::TcpStream(_) => return Err(Status::Inval),
+            _ => return Err(Status::NotSock),
+        };
+        let stream = listener.accept().await?;
+        self.fds.borrow_mut().insert(Descriptor::TcpStream(stream))
+    }
+
+    pub fn close(&mut self, fd: Fd) -> StatusResult {
+        self.fds.borrow_mut().remove(fd)?;
         Ok(())
     }
+
+    /// Moves the descriptor `from` to `to`, closing the descriptor previously at `to`.
+    pub fn renumber(&mut self, from: Fd, to: Fd) -> StatusResult {
+        self.fds.borrow_mut().renumber(from, to)
+    }
+
+    pub async fn tell(&mut self, fd: Fd) -> Result<u64, Status> {
+        self.seek(fd, SeekFrom::Current(0)).await
+    }
+
+    pub async fn seek(&mut self, fd: Fd, seek_from: SeekFrom) -> Result<u64, Status> {
+        let file = match self.descriptor(fd)? {
+            Descriptor::File(f) => f.file,
+            _ => return Err(Status::Spipe),
+        };
+        unblock(move || file.seek(seek_from)).await
+    }
+
+    /// Returns all entries of the directory opened as `fd`.
+    ///
+    /// The directory is read once when iteration starts (`cookie` is 0) and the listing is reused
+    /// by following calls. This keeps the cookies stable while the guest iterates over the directory.
+    pub async fn read_dir(
+        &mut self,
+        fd: Fd,
+        cookie: Dircookie,
+    ) -> Result<Arc<Vec<DirEntry>>, Status> {
+        let f = self.file_desc(fd)?;
+        match f.dir_entries {
+            Some(entries) if cookie != 0 => Ok(entries),
+            _ => {
+                let fs = self.fs.clone();
+                let path = f.path;
+                let entries = Arc::new(unblock(move || read_dir(fs.as_ref(), &path)).await?);
+                if let Ok(Descriptor::File(f)) = self.fds.borrow_mut().get_mut(fd) {
+                    f.dir_entries = Some(entries.clone());
+                }
+                Ok(entries)
+            }
+        }
+    }
+
+    pub async fn create_directory(&self, abs_path: PathBuf) -> StatusResult {
+        let fs = self.fs.clone();
+        unblock(move || fs.create_dir(&abs_path)).await
+    }
+
+    pub async fn remove_directory(&self, abs_path: PathBuf) -> StatusResult {
+        let fs = self.fs.clone();
+        unblock(move || fs.remove_dir(&abs_path)).await
+    }
+
+    pub async fn remove_file(&self, abs_path: PathBuf) -> StatusResult {
+        let fs = self.fs.clone();
+        unblock(move || fs.remove_file(&abs_path)).await
+    }
+
+    pub async fn rename(&self, abs_from: PathBuf, abs_to: PathBuf) -> StatusResult {
+        let fs = self.fs.clone();
+        unblock(move || fs.rename(&abs_from, &abs_to)).await
+    }
+
+    pub async fn hard_link(&self, abs_from: PathBuf, abs_to: PathBuf) -> StatusResult {
+        let fs = self.fs.clone();
+        unblock(move || fs.hard_link(&abs_from, &abs_to)).await
+    }
+
+    pub async fn symlink(&self, target: PathBuf, abs_link: PathBuf) -> StatusResult {
+        let fs = self.fs.clone();
+        unblock(move || fs.symlink(&target, &abs_link)).await
+    }
+
+    pub async fn read_link(&self, abs_path: PathBuf) -> Result<PathBuf, Status> {
+        let fs = self.fs.clone();
+        unblock(move || fs.read_link(&abs_path)).await
+    }
+
+    pub async fn filestat(&self, fd: Fd) -> Result<Filestat, Status> {
+        let file = match self.descriptor(fd)? {
+            Descriptor::Stdin | Descriptor::Stdout | Descriptor::Stderr => {
+                return Ok(empty_filestat(Filetype::CharacterDevice))
+            }
+            Descriptor::TcpStream(_) | Descriptor::TcpListener(_) => {
+                return Ok(empty_filestat(Filetype::SocketStream))
+            }
+            Descriptor::File(f) => f.file,
+        };
+        unblock(move || file.filestat()).await
+    }
+
+    pub async fn filestat_path(&self, abs_path: PathBuf) -> Result<Filestat, Status> {
+        let fs = self.fs.clone();
+        unblock(move || fs.filestat(&abs_path)).await
+    }
+
+    pub async fn set_size(&mut self, fd: Fd, len: u64) -> Result<(), Status> {
+        let file = self.file(fd)?;
+        unblock(move || file.set_len(len)).await
+    }
+
+    pub async fn set_times(&self, fd: Fd, atim: SetTime, mtim: SetTime) -> StatusResult {
+        let file = self.file(fd)?;
+        unblock(move || file.set_times(atim, mtim)).await
+    }
+
+    pub async fn set_times_path(
+        &self,
+        abs_path: PathBuf,
+        follow_symlinks: bool,
+        atim: SetTime,
+        mtim: SetTime,
+    ) -> StatusResult {
+        let fs = self.fs.clone();
+        unblock(move || fs.set_times(&abs_path, follow_symlinks, atim, mtim)).await
+    }
+
+    pub async fn sync(&self, fd: Fd) -> StatusResult {
+        let file = self.file(fd)?;
+        unblock(move || file.sync_all()).await
+    }
+
+    pub async fn sync_data(&self, fd: Fd) -> StatusResult {
+        let file = self.file(fd)?;
+        unblock(move || file.sync_data()).await
+    }
+
+    pub async fn allocate(&self, fd: Fd, offset: Filesize, len: Filesize) -> StatusResult {
+        let file = self.file(fd)?;
+        unblock(move || file.allocate(offset, len)).await
+    }
+
+    pub async fn advise(
+        &self,
+        fd: Fd,
+        offset: Filesize,
+        len: Filesize,
+        advice: Advice,
+    ) -> StatusResult {
+        let file = self.file(fd)?;
+        unblock(move || file.advise(offset, len, advice)).await
+    }
+}
+
+/// Limits a read of `len` bytes from `file` to the bytes left in it. Files that are not regular
+/// files, like pipes or devices on the host, are read in chunks of at most `READ_CHUNK_SIZE`.
+fn read_len(file: &dyn FileHandle, len: usize) -> Result<usize, Status> {
+    let filestat = file.filestat()?;
+    if !matches!(filestat.filetype, Filetype::RegularFile) {
+        return Ok(len.min(READ_CHUNK_SIZE));
+    }
+    let position = file.seek(SeekFrom::Current(0))?;
+    Ok(filestat.size.saturating_sub(position).min(len as u64) as usize)
 }
 
-#[derive(Debug)]
-struct FileDesc {
-    pub file: File,
-    pub path: PathBuf,
+/// Lists all entries of a directory, starting with `.` and `..`.
+fn read_dir(fs: &dyn FileSystem, path: &Path) -> Result<Vec<DirEntry>, Status> {
+    let parent = path.parent().unwrap_or(path);
+    let mut entries = vec![
+        DirEntry::from_filestat(".", &fs.filestat(path)?),
+        DirEntry::from_filestat("..", &fs.filestat(parent)?),
+    ];
+    entries.extend(fs.read_dir(path)?);
+    Ok(entries)
 }
 
-impl FileDesc {
-    fn open<P: AsRef<Path>>(path: P) -> Result<Self, Status> {
-        let file = File::open(&path)?;
-        let path = PathBuf::from(path.as_ref());
-        Ok(Self { file, path })
+// Metadata of descriptors that are not backed by a file.
+fn empty_filestat(filetype: Filetype) -> Filestat {
+    Filestat {
+        dev: 0,
+        ino: 0,
+        filetype,
+        nlink: 1,
+        size: 0,
+        atim: 0,
+        mtim: 0,
+        ctim: 0,
     }
+}
 
-    fn open_with_flags<P: AsRef<Path>>(path: P, flags: OpenFlags) -> Result<Self, Status> {
-        let file = OpenOptions::new()
-            .read(true)
-            .write(flags.create())
-            .create(flags.create())
-            .truncate(flags.truncate())
-            .open(&path)?;
+/// A resource a file descriptor of a process refers to.
+#[derive(Clone)]
+pub enum Descriptor {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(FileDesc),
+    TcpStream(TcpStream),
+    TcpListener(TcpListener),
+}
+
+#[derive(Clone)]
+pub struct FileDesc {
+    // Shared with the blocking thread pool while an operation is in progress.
+    file: Arc<dyn FileHandle>,
+    path: PathBuf,
+    // Listing of the directory cached by `WasiState::read_dir`.
+    dir_entries: Option<Arc<Vec<DirEntry>>>,
+    preopened: bool,
+}
+
+impl FileDesc {
+    fn open<P: AsRef<Path>>(
+        fs: &dyn FileSystem,
+        path: P,
+        flags: OpenFlags,
+        write: bool,
+    ) -> Result<Self, Status> {
+        let file = fs.open(path.as_ref(), flags, write)?.into();
         let path = PathBuf::from(path.as_ref());
-        Ok(Self { file, path })
+        Ok(Self {
+            file,
+            path,
+            dir_entries: None,
+            preopened: false,
+        })
     }
 }
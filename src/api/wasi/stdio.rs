@@ -0,0 +1,217 @@
+//! Standard streams of processes.
+//!
+//! Each process can have its stdin, stdout and stderr connected to the host's streams, to in-memory
+//! buffers or to channels. This makes it possible to capture the output of individual processes.
+
+use super::types::Status;
+use crate::api::channel::{ChannelReceiver, ChannelSender};
+
+use std::{
+    io::{self, IoSlice, IoSliceMut, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+/// An in-memory buffer that can be shared between the host and processes.
+///
+/// When used as an output, all data written by the process is appended to it. When used as an
+/// input, reads consume data from the front of it.
+#[derive(Clone, Default)]
+pub struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of the current content.
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Removes and returns the current content.
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    /// Appends `bytes` to the end of the buffer.
+    pub fn extend(&self, bytes: &[u8]) {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+    }
+}
+
+impl From<Vec<u8>> for Buffer {
+    fn from(bytes: Vec<u8>) -> Self {
+        Buffer(Arc::new(Mutex::new(bytes)))
+    }
+}
+
+/// Source of a process' stdin.
+#[derive(Clone)]
+pub enum Input {
+    /// Read from the host's stdin.
+    Inherit,
+    /// Reads always return end of file.
+    Null,
+    /// Read from an in-memory buffer. Reaching the end of the buffer is reported as end of file.
+    Buffer(Buffer),
+    /// Read the content of messages received on the channel. Once all senders are dropped, end of
+    /// file is reported.
+    Channel(ChannelReceiver),
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input::Inherit
+    }
+}
+
+/// Destination of a process' stdout or stderr.
+#[derive(Clone)]
+pub enum Output {
+    /// Write to the host's stdout or stderr.
+    Inherit,
+    /// Discard everything written.
+    Null,
+    /// Append to an in-memory buffer.
+    Buffer(Buffer),
+    /// Send every write as a message to the channel.
+    Channel(ChannelSender),
+    /// Prefix each line with the id of the process (e.g. `[7] `) before writing it to the inner output.
+    Prefixed(Box<Output>),
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output::Inherit
+    }
+}
+
+/// Stdin of a running process.
+pub struct Stdin {
+    input: Input,
+    // Unread part of the last message received from an `Input::Channel`.
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl Stdin {
+    pub fn new(input: Input) -> Self {
+        Self {
+            input,
+            pending: Vec::new(),
+            position: 0,
+        }
+    }
+
+    pub async fn read(&mut self, iovs: &mut [IoSliceMut<'_>]) -> Result<usize, Status> {
+        match &self.input {
+            Input::Inherit => Ok(io::stdin().read_vectored(iovs)?),
+            Input::Null => Ok(0),
+            Input::Buffer(buffer) => {
+                let mut buffer = buffer.0.lock().unwrap();
+                let read = buffer.as_slice().read_vectored(iovs)?;
+                buffer.drain(..read);
+                Ok(read)
+            }
+            Input::Channel(receiver) => {
+                while self.position == self.pending.len() {
+                    match receiver.receive().await {
+                        Ok(message) => {
+                            self.pending = message.as_slice().to_vec();
+                            self.position = 0;
+                        }
+                        // All senders are gone, nothing more can be read.
+                        Err(_) => return Ok(0),
+                    }
+                }
+                let read = (&self.pending[self.position..]).read_vectored(iovs)?;
+                self.position += read;
+                Ok(read)
+            }
+        }
+    }
+}
+
+/// Host stream used by `Output::Inherit`.
+#[derive(Clone, Copy)]
+pub enum HostStream {
+    Stdout,
+    Stderr,
+}
+
+/// Stdout or stderr of a running process.
+pub struct OutputStream {
+    output: Output,
+    host: HostStream,
+    prefix: Option<String>,
+    // Is the next written byte at the beginning of a line?
+    line_start: bool,
+}
+
+impl OutputStream {
+    pub fn new(output: Output, host: HostStream, process_id: u64) -> Self {
+        let mut prefix = None;
+        let mut output = output;
+        while let Output::Prefixed(inner) = output {
+            prefix = Some(format!("[{}] ", process_id));
+            output = *inner;
+        }
+        Self {
+            output,
+            host,
+            prefix,
+            line_start: true,
+        }
+    }
+
+    /// Writes all `ciovs` and returns the number of bytes written by the process, not counting
+    /// prefixes.
+    pub async fn write(&mut self, ciovs: &[IoSlice<'_>]) -> Result<usize, Status> {
+        let len = ciovs.iter().map(|ciov| ciov.len()).sum();
+        let bytes = match &self.prefix {
+            Some(prefix) => {
+                let mut bytes = Vec::with_capacity(len + prefix.len());
+                for byte in ciovs.iter().flat_map(|ciov| ciov.iter()) {
+                    if self.line_start {
+                        bytes.extend_from_slice(prefix.as_bytes());
+                    }
+                    bytes.push(*byte);
+                    self.line_start = *byte == b'\n';
+                }
+                bytes
+            }
+            None => match (&self.output, self.host) {
+                // Partial writes can be forwarded to the guest if we are not adding prefixes.
+                (Output::Inherit, HostStream::Stdout) => {
+                    return Ok(io::stdout().write_vectored(ciovs)?)
+                }
+                (Output::Inherit, HostStream::Stderr) => {
+                    return Ok(io::stderr().write_vectored(ciovs)?)
+                }
+                _ => ciovs.iter().flat_map(|ciov| ciov.iter().copied()).collect(),
+            },
+        };
+        self.write_all(&bytes).await?;
+        Ok(len)
+    }
+
+    async fn write_all(&self, bytes: &[u8]) -> Result<(), Status> {
+        match &self.output {
+            Output::Inherit => match self.host {
+                HostStream::Stdout => io::stdout().write_all(bytes)?,
+                HostStream::Stderr => io::stderr().write_all(bytes)?,
+            },
+            Output::Null => {}
+            Output::Buffer(buffer) => buffer.extend(bytes),
+            Output::Channel(sender) => {
+                if sender.send(bytes, Vec::new()).await.is_err() {
+                    // The receiving end was dropped.
+                    return Err(Status::Pipe);
+                }
+            }
+            // Prefixes are unwrapped when the stream is created.
+            Output::Prefixed(_) => unreachable!(),
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use std::io::SeekFrom;
 
-use super::{aliases::Oflags, Filedelta};
+use super::{
+    aliases::{Advice, Fstflags, Lookupflags, Oflags, Riflags, Rights, Sdflags},
+    Filedelta,
+};
 use uptown_funk::{types::CReprWasmType, Executor, FromWasm, ToWasm, Trap};
 
 // Create file if it does not exist.
@@ -12,11 +15,24 @@ pub const OFLAGS_EXCL: Oflags = 0x4;
 /// Truncate file to size 0.
 pub const OFLAGS_TRUNC: Oflags = 0x8;
 
+/// The right to write to a file.
+pub const RIGHTS_FD_WRITE: Rights = 1 << 6;
+/// The right to allocate space in a file.
+pub const RIGHTS_FD_ALLOCATE: Rights = 1 << 8;
+/// The right to change the size of a file.
+pub const RIGHTS_FD_FILESTAT_SET_SIZE: Rights = 1 << 22;
+/// All rights defined by WASI.
+pub const RIGHTS_ALL: Rights = (1 << 29) - 1;
+
 #[derive(Copy, Clone, Debug)]
 #[repr(transparent)]
 pub struct OpenFlags(u16);
 
 impl OpenFlags {
+    pub fn new() -> Self {
+        OpenFlags(0)
+    }
+
     pub fn create(self) -> bool {
         (self.0 & OFLAGS_CREAT) != 0
     }
@@ -38,6 +54,12 @@ impl OpenFlags {
     }
 }
 
+impl From<Oflags> for OpenFlags {
+    fn from(oflags: Oflags) -> Self {
+        OpenFlags(oflags)
+    }
+}
+
 impl<S> FromWasm<S> for OpenFlags {
     type From = u32;
 
@@ -215,3 +237,38 @@ impl<S> ToWasm<S> for Fdflags {
         Ok(v.0 as u32)
     }
 }
+
+/// Adjust the last data access timestamp to the value stored in `atim`.
+pub const FSTFLAGS_ATIM: Fstflags = 0x1;
+/// Adjust the last data access timestamp to the time of clock `Realtime`.
+pub const FSTFLAGS_ATIM_NOW: Fstflags = 0x2;
+/// Adjust the last data modification timestamp to the value stored in `mtim`.
+pub const FSTFLAGS_MTIM: Fstflags = 0x4;
+/// Adjust the last data modification timestamp to the time of clock `Realtime`.
+pub const FSTFLAGS_MTIM_NOW: Fstflags = 0x8;
+
+/// As long as the resolved path corresponds to a symbolic link, it is expanded.
+pub const LOOKUPFLAGS_SYMLINK_FOLLOW: Lookupflags = 0x1;
+
+/// The application has no advice to give on its behavior with respect to the specified data.
+pub const ADVICE_NORMAL: Advice = 0;
+/// The application expects to access the specified data sequentially from lower offsets to higher offsets.
+pub const ADVICE_SEQUENTIAL: Advice = 1;
+/// The application expects to access the specified data in a random order.
+pub const ADVICE_RANDOM: Advice = 2;
+/// The application expects to access the specified data in the near future.
+pub const ADVICE_WILLNEED: Advice = 3;
+/// The application expects that it will not access the specified data in the near future.
+pub const ADVICE_DONTNEED: Advice = 4;
+/// The application expects to access the specified data once and then not reuse it thereafter.
+pub const ADVICE_NOREUSE: Advice = 5;
+
+/// Returns the message without removing it from the socket's receive queue.
+pub const RIFLAGS_RECV_PEEK: Riflags = 0x1;
+/// On byte-stream sockets, block until the full amount of data can be returned.
+pub const RIFLAGS_RECV_WAITALL: Riflags = 0x2;
+
+/// Disables further receive operations.
+pub const SDFLAGS_RD: Sdflags = 0x1;
+/// Disables further send operations.
+pub const SDFLAGS_WR: Sdflags = 0x2;
@@ -184,6 +184,10 @@ impl<S> ToWasm<S> for Status {
 }
 
 fn convert_io_err(e: std::io::Error) -> Status {
+    // Errors coming from the OS carry the exact error code, `ErrorKind` is only a coarse grouping.
+    if let Some(status) = e.raw_os_error().and_then(from_raw_os_error) {
+        return status;
+    }
     match e.kind() {
         std::io::ErrorKind::NotFound => Status::NoEnt,
         std::io::ErrorKind::PermissionDenied => Status::Acces,
@@ -195,18 +199,109 @@ fn convert_io_err(e: std::io::Error) -> Status {
         std::io::ErrorKind::AddrNotAvailable => Status::AddrNotAvail,
         std::io::ErrorKind::BrokenPipe => Status::Pipe,
         std::io::ErrorKind::AlreadyExists => Status::Exist,
-        std::io::ErrorKind::WouldBlock => Status::Again, // ??
+        std::io::ErrorKind::WouldBlock => Status::Again,
         std::io::ErrorKind::InvalidInput => Status::Inval,
-        std::io::ErrorKind::InvalidData => Status::Inval, // ??
+        // Malformed data, like a failed TLS handshake, isn't an illegal byte sequence.
+        std::io::ErrorKind::InvalidData => Status::Io,
         std::io::ErrorKind::TimedOut => Status::TimedOut,
-        std::io::ErrorKind::WriteZero => Status::Inval, // ??{}
         std::io::ErrorKind::Interrupted => Status::Intr,
-        std::io::ErrorKind::Other => Status::Inval, // ??
-        std::io::ErrorKind::UnexpectedEof => Status::Inval, // ??
-        _ => Status::Inval,                         // ??
+        _ => Status::Io,
     }
 }
 
+/// Maps an `errno` value to the matching status.
+#[cfg(unix)]
+fn from_raw_os_error(errno: i32) -> Option<Status> {
+    let status = match errno {
+        libc::E2BIG => Status::TooBig,
+        libc::EACCES => Status::Acces,
+        libc::EADDRINUSE => Status::AddrInUse,
+        libc::EADDRNOTAVAIL => Status::AddrNotAvail,
+        libc::EAFNOSUPPORT => Status::AddrFamilyNotSupported,
+        libc::EAGAIN => Status::Again,
+        libc::EALREADY => Status::Already,
+        libc::EBADF => Status::Badf,
+        libc::EBADMSG => Status::BadMsg,
+        libc::EBUSY => Status::Busy,
+        libc::ECANCELED => Status::Canceled,
+        libc::ECHILD => Status::Child,
+        libc::ECONNABORTED => Status::ConnAborted,
+        libc::ECONNREFUSED => Status::ConnRefused,
+        libc::ECONNRESET => Status::ConnReset,
+        libc::EDEADLK => Status::Deadlk,
+        libc::EDESTADDRREQ => Status::DestAddrReq,
+        libc::EDOM => Status::Dom,
+        libc::EDQUOT => Status::Dquot,
+        libc::EEXIST => Status::Exist,
+        libc::EFAULT => Status::Fault,
+        libc::EFBIG => Status::Fbig,
+        libc::EHOSTUNREACH => Status::HostUnreach,
+        libc::EIDRM => Status::IdRemoved,
+        libc::EILSEQ => Status::IllegalSeq,
+        libc::EINPROGRESS => Status::InProgress,
+        libc::EINTR => Status::Intr,
+        libc::EINVAL => Status::Inval,
+        libc::EIO => Status::Io,
+        libc::EISCONN => Status::IsConn,
+        libc::EISDIR => Status::IsDir,
+        libc::ELOOP => Status::Loop,
+        libc::EMFILE => Status::Mfile,
+        libc::EMLINK => Status::Mlink,
+        libc::EMSGSIZE => Status::MsgSize,
+        libc::EMULTIHOP => Status::Multihop,
+        libc::ENAMETOOLONG => Status::NameTooLong,
+        libc::ENETDOWN => Status::NetDown,
+        libc::ENETRESET => Status::NetReset,
+        libc::ENETUNREACH => Status::NetUnreach,
+        libc::ENFILE => Status::Nfile,
+        libc::ENOBUFS => Status::NoBufs,
+        libc::ENODEV => Status::NoDev,
+        libc::ENOENT => Status::NoEnt,
+        libc::ENOEXEC => Status::NoExec,
+        libc::ENOLCK => Status::NoLck,
+        libc::ENOLINK => Status::NoLink,
+        libc::ENOMEM => Status::NoMem,
+        libc::ENOMSG => Status::NoMsg,
+        libc::ENOPROTOOPT => Status::NoProtoOpt,
+        libc::ENOSPC => Status::NoSpace,
+        libc::ENOSYS => Status::NoSys,
+        libc::ENOTCONN => Status::NotConn,
+        libc::ENOTDIR => Status::NotDir,
+        libc::ENOTEMPTY => Status::NotEmpty,
+        libc::ENOTRECOVERABLE => Status::NotRecoverable,
+        libc::ENOTSOCK => Status::NotSock,
+        libc::ENOTSUP => Status::NotSup,
+        libc::ENOTTY => Status::NoTty,
+        libc::ENXIO => Status::Nxio,
+        libc::EOVERFLOW => Status::Overflow,
+        libc::EOWNERDEAD => Status::OwnerDead,
+        libc::EPERM => Status::Perm,
+        libc::EPIPE => Status::Pipe,
+        libc::EPROTO => Status::Proto,
+        libc::EPROTONOSUPPORT => Status::ProtoNoSupport,
+        libc::EPROTOTYPE => Status::Prototype,
+        libc::ERANGE => Status::Range,
+        libc::EROFS => Status::Rofs,
+        libc::ESPIPE => Status::Spipe,
+        libc::ESRCH => Status::Srch,
+        libc::ESTALE => Status::Stale,
+        libc::ETIMEDOUT => Status::TimedOut,
+        libc::ETXTBSY => Status::TxtBusy,
+        libc::EXDEV => Status::Xdev,
+        // Aliases of the codes above on some platforms.
+        errno if errno == libc::EWOULDBLOCK => Status::Again,
+        errno if errno == libc::EOPNOTSUPP => Status::NotSup,
+        _ => return None,
+    };
+    Some(status)
+}
+
+// Windows error codes are already grouped into the matching `ErrorKind` by the standard library.
+#[cfg(not(unix))]
+fn from_raw_os_error(_: i32) -> Option<Status> {
+    None
+}
+
 impl From<std::io::Result<()>> for Status {
     fn from(r: std::io::Result<()>) -> Self {
         match r {
@@ -21,6 +21,21 @@ pub struct Dirent {
 
 impl CReprWasmType for Dirent {}
 
+impl Dirent {
+    /// Size of a dirent in guest memory, including the trailing padding.
+    pub const SIZE: usize = 24;
+
+    /// Returns the dirent in the layout expected by the guest.
+    pub fn to_le_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.d_next.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.d_ino.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.d_namlen.to_le_bytes());
+        bytes[20] = self.d_type as u8;
+        bytes
+    }
+}
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub struct Fdstat {
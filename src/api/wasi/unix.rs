@@ -1,27 +1,31 @@
 // NOTE: implementation borrowed from https://github.com/wasmerio/wasmer/blob/0ab8a0de096ffdf89f353dd722a15b5e6255055f/lib/wasi/src/syscalls/unix/mod.rs
 
+use super::fs::SetTime;
 use super::types::*;
 use libc::{
-    clock_getres, clock_gettime, timespec, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID,
-    CLOCK_REALTIME, CLOCK_THREAD_CPUTIME_ID,
+    c_long, clock_getres, clock_gettime, futimens, time_t, timespec, utimensat, AT_FDCWD,
+    AT_SYMLINK_NOFOLLOW, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME,
+    CLOCK_THREAD_CPUTIME_ID, UTIME_NOW, UTIME_OMIT,
+};
+use std::{
+    ffi::CString,
+    fs::{DirEntry, File, Metadata},
+    io,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{symlink, DirEntryExt, MetadataExt},
+        io::AsRawFd,
+    },
+    path::Path,
 };
-use std::{os::unix::fs::symlink, path::Path};
 
 use uptown_funk::types::Pointer;
 
-fn errno_to_status(err: i32) -> Status {
-    // TODO: map errno from clock_getres to types::Status
-    match err {
-        0 => Status::Success,
-        _ => Status::Inval,
-    }
-}
-
 pub fn platform_clock_res_get(clock_id: Clockid, mut res: Pointer<Timestamp>) -> Status {
     let unix_clock_id = match clock_id {
-        Clockid::Realtime => CLOCK_MONOTONIC,
-        Clockid::Monotonic => CLOCK_PROCESS_CPUTIME_ID,
-        Clockid::ProcessCpuTimeId => CLOCK_REALTIME,
+        Clockid::Realtime => CLOCK_REALTIME,
+        Clockid::Monotonic => CLOCK_MONOTONIC,
+        Clockid::ProcessCpuTimeId => CLOCK_PROCESS_CPUTIME_ID,
         Clockid::ThreadCpuTimeId => CLOCK_THREAD_CPUTIME_ID,
         Clockid::Unsupported => return Status::Inval,
     };
@@ -37,7 +41,10 @@ pub fn platform_clock_res_get(clock_id: Clockid, mut res: Pointer<Timestamp>) ->
     let t_out = (timespec_out.tv_sec * 1_000_000_000).wrapping_add(timespec_out.tv_nsec);
     res.set(t_out as Timestamp);
 
-    errno_to_status(output)
+    match check_result(output) {
+        Ok(()) => Status::Success,
+        Err(status) => status,
+    }
 }
 
 pub fn platform_clock_time_get(
@@ -46,9 +53,9 @@ pub fn platform_clock_time_get(
     mut time: Pointer<Timestamp>,
 ) -> StatusTrapResult {
     let unix_clock_id = match clock_id {
-        Clockid::Realtime => CLOCK_MONOTONIC,
-        Clockid::Monotonic => CLOCK_PROCESS_CPUTIME_ID,
-        Clockid::ProcessCpuTimeId => CLOCK_REALTIME,
+        Clockid::Realtime => CLOCK_REALTIME,
+        Clockid::Monotonic => CLOCK_MONOTONIC,
+        Clockid::ProcessCpuTimeId => CLOCK_PROCESS_CPUTIME_ID,
         Clockid::ThreadCpuTimeId => CLOCK_THREAD_CPUTIME_ID,
         Clockid::Unsupported => return Status::Inval.into(),
     };
@@ -67,10 +74,150 @@ pub fn platform_clock_time_get(
     let t_out = (timespec_out.tv_sec * 1_000_000_000).wrapping_add(timespec_out.tv_nsec);
     time.set(t_out as Timestamp);
 
-    errno_to_status(output).into()
+    check_result(output)?;
+    Ok(())
 }
 
 pub fn platform_symlink<P: AsRef<Path>>(old_path: P, new_path: P) -> StatusResult {
     symlink(old_path, new_path)?;
     Status::Success.into()
 }
+
+pub fn platform_filestat(metadata: &Metadata) -> Filestat {
+    Filestat {
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+        filetype: metadata.file_type().into(),
+        nlink: metadata.nlink(),
+        size: metadata.size(),
+        atim: to_timestamp(metadata.atime(), metadata.atime_nsec()),
+        mtim: to_timestamp(metadata.mtime(), metadata.mtime_nsec()),
+        ctim: to_timestamp(metadata.ctime(), metadata.ctime_nsec()),
+    }
+}
+
+// Timestamps before the UNIX epoch can't be represented and are reported as 0.
+fn to_timestamp(sec: i64, nsec: i64) -> Timestamp {
+    if sec < 0 {
+        return 0;
+    }
+    (sec as u64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(nsec as u64)
+}
+
+fn to_timespec(time: SetTime) -> timespec {
+    match time {
+        SetTime::Keep => timespec {
+            tv_sec: 0,
+            tv_nsec: UTIME_OMIT,
+        },
+        SetTime::Now => timespec {
+            tv_sec: 0,
+            tv_nsec: UTIME_NOW,
+        },
+        SetTime::At(timestamp) => timespec {
+            tv_sec: (timestamp / 1_000_000_000) as time_t,
+            tv_nsec: (timestamp % 1_000_000_000) as c_long,
+        },
+    }
+}
+
+fn check_result(result: i32) -> StatusResult {
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error().into()),
+    }
+}
+
+pub fn platform_set_times(file: &File, atim: SetTime, mtim: SetTime) -> StatusResult {
+    let times = [to_timespec(atim), to_timespec(mtim)];
+    check_result(unsafe { futimens(file.as_raw_fd(), times.as_ptr()) })
+}
+
+pub fn platform_set_times_path(
+    path: &Path,
+    follow_symlinks: bool,
+    atim: SetTime,
+    mtim: SetTime,
+) -> StatusResult {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(|_| Status::Inval)?;
+    let times = [to_timespec(atim), to_timespec(mtim)];
+    let flags = if follow_symlinks {
+        0
+    } else {
+        AT_SYMLINK_NOFOLLOW
+    };
+    check_result(unsafe { utimensat(AT_FDCWD, path.as_ptr(), times.as_ptr(), flags) })
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "linux", target_os = "android"))]
+pub fn platform_allocate(file: &File, offset: Filesize, len: Filesize) -> StatusResult {
+    let result = unsafe {
+        libc::posix_fallocate(file.as_raw_fd(), offset as libc::off_t, len as libc::off_t)
+    };
+    // posix_fallocate returns the error code instead of setting errno.
+    match result {
+        0 => Ok(()),
+        err => Err(io::Error::from_raw_os_error(err).into()),
+    }
+}
+
+// macOS doesn't have posix_fallocate, only grow the file.
+#[cfg(target_os = "macos")]
+pub fn platform_allocate(file: &File, offset: Filesize, len: Filesize) -> StatusResult {
+    let end = offset.checked_add(len).ok_or(Status::Fbig)?;
+    if file.metadata()?.len() < end {
+        file.set_len(end)?;
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "linux", target_os = "android"))]
+pub fn platform_advise(
+    file: &File,
+    offset: Filesize,
+    len: Filesize,
+    advice: Advice,
+) -> StatusResult {
+    let advice = match advice {
+        ADVICE_NORMAL => libc::POSIX_FADV_NORMAL,
+        ADVICE_SEQUENTIAL => libc::POSIX_FADV_SEQUENTIAL,
+        ADVICE_RANDOM => libc::POSIX_FADV_RANDOM,
+        ADVICE_WILLNEED => libc::POSIX_FADV_WILLNEED,
+        ADVICE_DONTNEED => libc::POSIX_FADV_DONTNEED,
+        ADVICE_NOREUSE => libc::POSIX_FADV_NOREUSE,
+        _ => return Err(Status::Inval),
+    };
+    let result = unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            offset as libc::off_t,
+            len as libc::off_t,
+            advice,
+        )
+    };
+    // posix_fadvise returns the error code instead of setting errno.
+    match result {
+        0 => Ok(()),
+        err => Err(io::Error::from_raw_os_error(err).into()),
+    }
+}
+
+// macOS doesn't have posix_fadvise, the advice is only a hint and can be ignored.
+#[cfg(target_os = "macos")]
+pub fn platform_advise(
+    _file: &File,
+    _offset: Filesize,
+    _len: Filesize,
+    advice: Advice,
+) -> StatusResult {
+    match advice {
+        ADVICE_NORMAL..=ADVICE_NOREUSE => Ok(()),
+        _ => Err(Status::Inval),
+    }
+}
+
+pub fn platform_dir_entry_inode(entry: &DirEntry) -> Inode {
+    entry.ino()
+}
@@ -1,9 +1,10 @@
 // NOTE: implementation borrowed from https://github.com/wasmerio/wasmer/blob/0ab8a0de096ffdf89f353dd722a15b5e6255055f/lib/wasi/src/syscalls/windows.rs
 
+use super::fs::SetTime;
 use super::types::*;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::{
-    fs::metadata,
+    fs::{metadata, DirEntry, File, Metadata},
     os::windows::fs::{symlink_dir, symlink_file},
     path::Path,
 };
@@ -70,3 +71,65 @@ pub fn platform_symlink<P: AsRef<Path>>(old_path: P, new_path: P) -> StatusResul
     };
     Status::Success.into()
 }
+
+// Device and inode numbers (volume serial numbers and file indexes) are not exposed by the standard
+// library on Windows.
+pub fn platform_filestat(metadata: &Metadata) -> Filestat {
+    Filestat {
+        dev: 0,
+        ino: 0,
+        filetype: metadata.file_type().into(),
+        nlink: 1,
+        size: metadata.len(),
+        atim: to_timestamp(metadata.accessed()),
+        mtim: to_timestamp(metadata.modified()),
+        ctim: to_timestamp(metadata.created()),
+    }
+}
+
+// Timestamps that are not available or before the UNIX epoch are reported as 0.
+fn to_timestamp(time: std::io::Result<SystemTime>) -> Timestamp {
+    time.ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos() as Timestamp)
+        .unwrap_or(0)
+}
+
+pub fn platform_set_times(_file: &File, _atim: SetTime, _mtim: SetTime) -> StatusResult {
+    Err(Status::NotSup)
+}
+
+pub fn platform_set_times_path(
+    _path: &Path,
+    _follow_symlinks: bool,
+    _atim: SetTime,
+    _mtim: SetTime,
+) -> StatusResult {
+    Err(Status::NotSup)
+}
+
+// Only grows the file, Windows doesn't have a way to preallocate a range without changing the size.
+pub fn platform_allocate(file: &File, offset: Filesize, len: Filesize) -> StatusResult {
+    let end = offset.checked_add(len).ok_or(Status::Fbig)?;
+    if file.metadata()?.len() < end {
+        file.set_len(end)?;
+    }
+    Ok(())
+}
+
+// The advice is only a hint and can be ignored.
+pub fn platform_advise(
+    _file: &File,
+    _offset: Filesize,
+    _len: Filesize,
+    advice: Advice,
+) -> StatusResult {
+    match advice {
+        ADVICE_NORMAL..=ADVICE_NOREUSE => Ok(()),
+        _ => Err(Status::Inval),
+    }
+}
+
+pub fn platform_dir_entry_inode(_entry: &DirEntry) -> Inode {
+    0
+}
@@ -1,3 +1,4 @@
 pub mod api;
 pub mod linker;
 pub mod module;
+pub mod node;
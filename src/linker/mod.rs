@@ -1,9 +1,15 @@
 #[cfg(feature = "vm-wasmtime")]
 mod wasmtime;
 #[cfg(feature = "vm-wasmtime")]
-pub use self::wasmtime::{engine as wasmtime_engine, LunaticLinker as WasmtimeLunaticLinker};
+pub use self::wasmtime::{
+    engine as wasmtime_engine, LunaticLinker as WasmtimeLunaticLinker,
+    ENGINE_CONFIG as WASMTIME_ENGINE_CONFIG,
+};
 
 #[cfg(feature = "vm-wasmer")]
 mod wasmer;
 #[cfg(feature = "vm-wasmer")]
-pub use self::wasmer::{engine as wasmer_engine, LunaticLinker as WasmerLunaticLinker};
+pub use self::wasmer::{
+    engine as wasmer_engine, LunaticLinker as WasmerLunaticLinker,
+    ENGINE_CONFIG as WASMER_ENGINE_CONFIG,
+};
@@ -62,6 +62,10 @@ thread_local! {
     static STORE: Store = Store::default();
 }
 
+/// Describes the configuration of `engine`, compiled modules are only compatible with engines
+/// configured the same way. Needs to be updated with every change to the configuration.
+pub const ENGINE_CONFIG: &str = "default";
+
 /// Return a configured Wasmer Store.
 pub fn engine() -> Store {
     STORE.with(|store| store.clone())
@@ -63,6 +63,10 @@ impl<T: HostFunctions> LunaticLinker<T> {
     }
 }
 
+/// Describes the configuration of `engine`, compiled modules are only compatible with engines
+/// configured the same way. Needs to be updated with every change to the configuration.
+pub const ENGINE_CONFIG: &str = "threads simd reference-types static-memory-guard-size=8MiB";
+
 /// Return a configured Wasmtime engine.
 pub fn engine() -> Engine {
     static mut ENGINE: Option<Engine> = None;
@@ -1,43 +1,282 @@
 #![feature(available_concurrency)]
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use easy_parallel::Parallel;
 
-use clap::{crate_version, Clap};
-use lunatic_runtime::module;
+use clap::{crate_version, AppSettings, Clap, FromArgMatches, IntoApp, Subcommand};
 use lunatic_runtime::{
-    api::process::{FunctionLookup, MemoryChoice, Process, EXECUTOR},
-    module::Runtime,
+    api::networking::{tcp::TcpListener, AddressRule, NetworkPolicy},
+    api::process::{FunctionLookup, MemoryChoice, Process, ProcessConfig, EXECUTOR},
+    api::wasi::MemoryFileSystem,
+    module::{is_artifact, LunaticModule, ModuleCache, Runtime},
+    node::Node,
 };
 
 use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::Path;
 use std::thread;
 
 #[cfg(all(feature = "vm-wasmer", target_family = "unix"))]
 use wasmer_vm::traphandlers::setup_unix_sigaltstack;
 
+/// Run a .wasm file or a module compiled by `lunatic compile`
+///
+/// `lunatic FILE` is the same as `lunatic run FILE`, but only runs compiled modules with
+/// --precompiled.
 #[derive(Clap)]
-#[clap(version = crate_version!())]
+#[clap(
+    version = crate_version!(),
+    setting = AppSettings::ArgsNegateSubcommands,
+    setting = AppSettings::SubcommandsNegateReqs
+)]
 struct Opts {
-    /// .wasm file
+    /// Set an environment variable for the process (KEY=VALUE)
+    #[clap(long = "env", number_of_values = 1)]
+    envs: Vec<String>,
+    /// Don't forward the host's environment variables to the process
+    #[clap(long)]
+    no_host_env: bool,
+    /// Run the process in an in-memory filesystem seeded from a .tar archive or a directory
+    #[clap(long)]
+    memfs: Option<String>,
+    /// Pass a listening socket bound to the address to the process (HOST:PORT)
+    #[clap(long = "tcplisten", number_of_values = 1)]
+    tcp_listen: Vec<String>,
+    /// Deny all network access that isn't explicitly allowed
+    #[clap(long)]
+    no_network: bool,
+    /// Only allow binding to matching addresses (IP[/PREFIX][:PORT[-PORT]])
+    #[clap(long = "allow-bind", number_of_values = 1)]
+    allow_bind: Vec<String>,
+    /// Only allow connecting to matching addresses (IP[/PREFIX][:PORT[-PORT]])
+    #[clap(long = "allow-connect", number_of_values = 1)]
+    allow_connect: Vec<String>,
+    /// Don't allow resolving host names
+    #[clap(long)]
+    no_dns: bool,
+    /// Don't allow using Unix domain sockets
+    #[clap(long)]
+    no_unix_sockets: bool,
+    /// Register a module that processes can spawn from, named by its file stem
+    #[clap(long = "module", number_of_values = 1)]
+    modules: Vec<String>,
+    /// Allow processes to load modules at runtime, they can't replace modules registered here
+    #[clap(long)]
+    allow_module_loading: bool,
+    /// Name of this node, other nodes can spawn processes from all registered modules
+    #[clap(long)]
+    node: Option<String>,
+    /// Accept connections from other nodes on the address (HOST:PORT), or on the port of 127.0.0.1
+    #[clap(long = "node-listen", requires = "node")]
+    node_listen: Option<String>,
+    /// Allow --node-listen addresses other machines can reach, connections aren't authenticated
+    #[clap(long, requires = "node-listen")]
+    node_listen_remote: bool,
+    /// Connect to the node listening on the address (HOST:PORT)
+    #[clap(long = "node-connect", number_of_values = 1, requires = "node")]
+    node_connect: Vec<String>,
+    /// Cache compiled modules on disk in the user's cache directory, so that they load faster
+    #[clap(long)]
+    module_cache: bool,
+    /// Cache compiled modules in the directory instead, implies --module-cache
+    #[clap(long = "module-cache-dir")]
+    module_cache_dir: Option<String>,
+    /// The input is a module compiled by `lunatic compile` instead of a .wasm file
+    #[clap(long)]
+    precompiled: bool,
+    /// .wasm file, or compiled module with `lunatic run` or --precompiled
     input: String,
     /// All other arguments are forwarded to the .wasm file
     #[clap(min_values(0))]
-    _args: Vec<String>,
+    args: Vec<String>,
+}
+
+#[derive(Clap)]
+enum Command {
+    /// Normalise and compile a .wasm file ahead of time, so that running it starts faster
+    ///
+    /// Run the compiled module with `lunatic run FILE`.
+    Compile(CompileOpts),
+    /// Run a .wasm file or a module compiled by `lunatic compile`
+    Run(Opts),
 }
 
-pub fn run() -> Result<()> {
-    let opts: Opts = Opts::parse();
+#[derive(Clap)]
+#[clap(version = crate_version!())]
+struct CompileOpts {
+    /// .wasm file
+    input: String,
+    /// Write the compiled module to the file [default: the input with the .lunatic extension]
+    #[clap(short, long)]
+    output: Option<String>,
+}
+
+impl Opts {
+    fn process_config(&self) -> Result<ProcessConfig> {
+        let mut config = ProcessConfig::default();
+
+        config.wasi.arg(&self.input);
+        for arg in self.args.iter() {
+            config.wasi.arg(arg);
+        }
+
+        if !self.no_host_env {
+            config.wasi.inherit_host_env();
+        }
+        for env in self.envs.iter() {
+            let mut key_value = env.splitn(2, '=');
+            match (key_value.next(), key_value.next()) {
+                (Some(key), Some(value)) if !key.is_empty() => config.wasi.env(key, value),
+                _ => return Err(anyhow!("Invalid --env value `{}`, expected KEY=VALUE", env)),
+            };
+        }
+
+        for address in self.tcp_listen.iter() {
+            let listener = std::net::TcpListener::bind(address)
+                .map_err(|e| anyhow!("Can't listen on `{}`: {}", address, e))?;
+            config.wasi.tcp_listener(TcpListener::from_std(listener)?);
+        }
+
+        config.network = self.network_policy()?;
+        config.load_modules = self.allow_module_loading;
+
+        config.module_cache = match &self.module_cache_dir {
+            Some(directory) => Some(ModuleCache::new(directory)),
+            None if self.module_cache => match ModuleCache::default_directory() {
+                Some(directory) => Some(ModuleCache::new(directory)),
+                None => bail!("No cache directory on this platform, use --module-cache-dir"),
+            },
+            None => None,
+        };
+
+        if let Some(memfs) = &self.memfs {
+            let filesystem = if memfs.ends_with(".tar") {
+                MemoryFileSystem::from_tar(fs::File::open(memfs)?)?
+            } else {
+                MemoryFileSystem::from_directory(memfs)?
+            };
+            config.wasi.filesystem(filesystem);
+        }
 
-    let wasm = fs::read(opts.input).expect("Can't open .wasm file");
+        Ok(config)
+    }
 
-    let module = module::LunaticModule::new(&wasm, Runtime::default())?;
+    fn network_policy(&self) -> Result<NetworkPolicy> {
+        let mut policy = if self.no_network {
+            NetworkPolicy::deny_all()
+        } else {
+            NetworkPolicy::default()
+        };
+        for rule in self.allow_bind.iter() {
+            policy.allow_bind(parse_rule("--allow-bind", rule)?);
+        }
+        for rule in self.allow_connect.iter() {
+            policy.allow_connect(parse_rule("--allow-connect", rule)?);
+        }
+        if self.no_dns {
+            policy.dns = false;
+        }
+        if self.no_unix_sockets {
+            policy.unix_sockets = false;
+        }
+        Ok(policy)
+    }
+
+    // Nodes only accept connections from other machines with --node-listen-remote.
+    fn node_listen_address(&self) -> Result<Option<String>> {
+        let address = match &self.node_listen {
+            Some(address) => match address.parse::<u16>() {
+                Ok(port) => format!("127.0.0.1:{}", port),
+                Err(_) => address.clone(),
+            },
+            None => return Ok(None),
+        };
+        if !self.node_listen_remote {
+            let mut addresses = address
+                .to_socket_addrs()
+                .map_err(|e| anyhow!("Invalid --node-listen value `{}`: {}", address, e))?;
+            if addresses.any(|address| !address.ip().is_loopback()) {
+                bail!(
+                    "Other machines can reach `{}`, allow it with --node-listen-remote",
+                    address
+                );
+            }
+        }
+        Ok(Some(address))
+    }
+}
+
+fn parse_rule(flag: &str, rule: &str) -> Result<AddressRule> {
+    rule.parse()
+        .map_err(|e| anyhow!("Invalid {} value `{}`: {}", flag, rule, e))
+}
+
+fn module_name(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// Loads a .wasm file. Compiled modules aren't validated, so they are only loaded after opting in
+// with `lunatic run` or --precompiled.
+fn load_module(wasm: &[u8], cache: &Option<ModuleCache>) -> Result<LunaticModule> {
+    if is_artifact(wasm) {
+        bail!("Compiled modules can only be run with `lunatic run` or --precompiled");
+    }
+    match cache {
+        Some(cache) => LunaticModule::new_cached(wasm, Runtime::default(), cache),
+        None => LunaticModule::new(wasm, Runtime::default()),
+    }
+}
+
+fn compile(opts: CompileOpts) -> Result<()> {
+    let wasm = fs::read(&opts.input).map_err(|e| anyhow!("Can't open `{}`: {}", opts.input, e))?;
+    let module = LunaticModule::new(&wasm, Runtime::default())?;
+    let output = match opts.output {
+        Some(output) => output,
+        None => Path::new(&opts.input)
+            .with_extension("lunatic")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    fs::write(&output, module.to_artifact()?)
+        .map_err(|e| anyhow!("Can't write `{}`: {}", output, e))
+}
+
+// `lunatic run` loads the input as a compiled module if it is one, this is the opt-in.
+fn run(opts: Opts, load_artifact: bool) -> Result<()> {
+    let mut config = opts.process_config()?;
+
+    let wasm = fs::read(&opts.input).expect("Can't open .wasm file");
+
+    let module = if opts.precompiled || (load_artifact && is_artifact(&wasm)) {
+        LunaticModule::from_artifact(&wasm)?
+    } else {
+        load_module(&wasm, &config.module_cache)?
+    };
+
+    // The .wasm file can also be spawned from by name.
+    config
+        .modules
+        .insert(module_name(&opts.input), module.clone());
+    for path in opts.modules.iter() {
+        let wasm = fs::read(path).map_err(|e| anyhow!("Can't open `{}`: {}", path, e))?;
+        let module = load_module(&wasm, &config.module_cache)?;
+        config.modules.insert(module_name(path), module);
+    }
+
+    let node_listen = opts.node_listen_address()?;
+    if let Some(name) = &opts.node {
+        config.node = Some(Node::new(name, config.clone()));
+    }
 
     // Set up async runtime
     let cpus = thread::available_concurrency().unwrap();
     let (signal, shutdown) = smol::channel::unbounded::<()>();
 
-    Parallel::new()
+    let result = Parallel::new()
         .each(0..cpus.into(), |_| {
             // Extend the signal stack on all execution threads
             #[cfg(all(feature = "vm-wasmer", target_family = "unix"))]
@@ -46,24 +285,52 @@ pub fn run() -> Result<()> {
         })
         .finish(|| {
             smol::future::block_on(async {
-                let result = Process::create(
-                    None,
-                    module,
-                    FunctionLookup::Name("_start"),
-                    MemoryChoice::New(None),
-                )
+                let result = async {
+                    if let Some(node) = &config.node {
+                        if let Some(address) = &node_listen {
+                            node.listen(address)
+                                .await
+                                .map_err(|e| anyhow!("Can't listen on `{}`: {}", address, e))?;
+                        }
+                        for address in opts.node_connect.iter() {
+                            node.connect(address).await.map_err(|e| {
+                                anyhow!("Can't connect to node `{}`: {}", address, e)
+                            })?;
+                        }
+                    }
+                    Process::create(
+                        None,
+                        module,
+                        FunctionLookup::Name("_start".to_string(), Vec::new()),
+                        MemoryChoice::New(None),
+                        config,
+                    )
+                    .await
+                }
                 .await;
                 drop(signal);
                 result
             })
         })
-        .1
-        .map_err(|e| e.error)?;
+        .1;
 
-    Ok(())
+    match result {
+        Ok(()) => Ok(()),
+        // Forward the exit code of processes calling `proc_exit`.
+        Err(error) => match error.exit_code() {
+            Some(code) => std::process::exit(code as i32),
+            None => Err(error.error),
+        },
+    }
 }
 
 fn main() -> Result<()> {
     env_logger::init();
-    run()
+
+    let matches = Command::augment_subcommands(Opts::into_app()).get_matches();
+    match Command::from_subcommand(matches.subcommand()) {
+        Some(Command::Compile(opts)) => compile(opts),
+        Some(Command::Run(opts)) => run(opts, true),
+        None => run(Opts::from_arg_matches(&matches), false),
+    }
 }
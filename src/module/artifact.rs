@@ -0,0 +1,88 @@
+//! Modules compiled ahead of time by `lunatic compile`.
+//!
+//! An artifact starts with `MAGIC`, followed by the bincode encoded `Artifact`. It can only be
+//! loaded by the same version of lunatic, with the runtime it was compiled for enabled and its
+//! engine configured the same way.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{normalisation, LunaticModule, Runtime};
+
+const MAGIC: &[u8] = b"\0lunatic";
+// Changes every time the layout of `Artifact` changes.
+const FORMAT: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Artifact {
+    version: String,
+    runtime: String,
+    engine_config: String,
+    normalisation: u32,
+    module: Vec<u8>,
+}
+
+/// Returns true if `bytes` are a compiled module instead of a .wasm file.
+pub fn is_artifact(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl LunaticModule {
+    /// Serializes the module together with everything needed to check if it's compatible with the
+    /// lunatic loading it.
+    pub fn to_artifact(&self) -> Result<Vec<u8>> {
+        let runtime = self.runtime();
+        let artifact = Artifact {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            runtime: runtime.name().to_string(),
+            engine_config: runtime.engine_config().to_string(),
+            normalisation: normalisation::VERSION,
+            module: self.serialize()?,
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT.to_le_bytes());
+        bytes.extend(bincode::serialize(&artifact)?);
+        Ok(bytes)
+    }
+
+    /// Loads a module serialized with `to_artifact`.
+    ///
+    /// Like `deserialize`, the compiled code isn't validated, so `bytes` need to come from a
+    /// trusted source.
+    pub fn from_artifact(bytes: &[u8]) -> Result<Self> {
+        if !is_artifact(bytes) {
+            bail!("Not a module compiled by lunatic");
+        }
+        let bytes = &bytes[MAGIC.len()..];
+        if bytes.len() < 4 || bytes[..4] != FORMAT.to_le_bytes() {
+            bail!("Module was compiled by an incompatible version of lunatic");
+        }
+        let artifact: Artifact = bincode::deserialize(&bytes[4..])?;
+
+        if artifact.version != env!("CARGO_PKG_VERSION") {
+            bail!(
+                "Module was compiled by lunatic {}, but this is lunatic {}",
+                artifact.version,
+                env!("CARGO_PKG_VERSION")
+            );
+        }
+        let runtime = Runtime::all()
+            .into_iter()
+            .find(|runtime| runtime.name() == artifact.runtime)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Module was compiled for the {} runtime, which isn't enabled",
+                    artifact.runtime
+                )
+            })?;
+        if artifact.engine_config != runtime.engine_config()
+            || artifact.normalisation != normalisation::VERSION
+        {
+            bail!(
+                "Module was compiled with a different {} configuration",
+                artifact.runtime
+            );
+        }
+        Self::deserialize(&artifact.module, runtime)
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use directories_next::ProjectDirs;
+use sha2::{Digest, Sha256};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{normalisation, Runtime};
+
+/// On-disk cache of compiled modules, so that modules don't need to be normalised and compiled
+/// again every time they are loaded.
+///
+/// Entries are content-addressed by a hash of the .wasm file, the runtime and everything else that
+/// changes the compiled code. Compiled modules are loaded without validation, so only trusted
+/// users should be able to write to the cache directory.
+#[derive(Clone, Debug)]
+pub struct ModuleCache {
+    directory: PathBuf,
+}
+
+impl ModuleCache {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// The user's cache directory for lunatic, if the platform has one.
+    pub fn default_directory() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "lunatic").map(|dirs| dirs.cache_dir().join("modules"))
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the key of `wasm` compiled for `runtime`.
+    pub fn key(wasm: &[u8], runtime: Runtime) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        hasher.update(normalisation::VERSION.to_le_bytes());
+        hasher.update(runtime.name());
+        hasher.update(runtime.engine_config());
+        hasher.update(wasm);
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Returns the compiled module stored under `key`.
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        fs::read(self.directory.join(key)).ok()
+    }
+
+    /// Stores the compiled module under `key`, replacing an existing entry.
+    pub fn insert(&self, key: &str, compiled: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        // Write to a temporary file first, so that other lunatic instances sharing the cache never
+        // see partially written entries. Its name is unique to this write, also among threads.
+        static NEXT_WRITE: AtomicUsize = AtomicUsize::new(0);
+        let write = NEXT_WRITE.fetch_add(1, Ordering::Relaxed);
+        let temporary =
+            self.directory
+                .join(format!("{}.{}.{}.tmp", key, std::process::id(), write));
+        fs::write(&temporary, compiled)?;
+        fs::rename(&temporary, self.directory.join(key))?;
+        Ok(())
+    }
+}
@@ -1,13 +1,23 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "vm-wasmer")]
 use wasmer::Module as WasmerModule;
 #[cfg(feature = "vm-wasmtime")]
 use wasmtime::Module as WasmtimeModule;
 
+use crate::api::process::ParamType;
 use crate::linker::*;
 use normalisation::patch;
 
+mod artifact;
+mod cache;
 pub mod normalisation;
+mod registry;
+
+pub use artifact::is_artifact;
+pub use cache::ModuleCache;
+pub use registry::ModuleRegistry;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Runtime {
@@ -17,6 +27,38 @@ pub enum Runtime {
     Wasmer,
 }
 
+impl Runtime {
+    /// Returns all runtimes enabled by features.
+    pub fn all() -> Vec<Runtime> {
+        vec![
+            #[cfg(feature = "vm-wasmtime")]
+            Runtime::Wasmtime,
+            #[cfg(feature = "vm-wasmer")]
+            Runtime::Wasmer,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "vm-wasmtime")]
+            Runtime::Wasmtime => "wasmtime",
+            #[cfg(feature = "vm-wasmer")]
+            Runtime::Wasmer => "wasmer",
+        }
+    }
+
+    // Modules compiled with one engine configuration can't be loaded by engines configured
+    // differently.
+    fn engine_config(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "vm-wasmtime")]
+            Runtime::Wasmtime => WASMTIME_ENGINE_CONFIG,
+            #[cfg(feature = "vm-wasmer")]
+            Runtime::Wasmer => WASMER_ENGINE_CONFIG,
+        }
+    }
+}
+
 impl Default for Runtime {
     #[cfg(feature = "vm-wasmtime")]
     fn default() -> Self {
@@ -66,6 +108,14 @@ impl Module {
     }
 }
 
+// A `LunaticModule` compiled to native code.
+#[derive(Serialize, Deserialize)]
+struct Compiled {
+    min_memory: u32,
+    max_memory: Option<u32>,
+    module: Vec<u8>,
+}
+
 #[derive(Clone)]
 pub struct LunaticModule {
     module: Module,
@@ -92,6 +142,70 @@ impl LunaticModule {
         })
     }
 
+    /// Like `new`, but loads the compiled module from `cache` if the same .wasm file was compiled
+    /// before, otherwise compiles it and stores it in the cache.
+    pub fn new_cached(wasm: &[u8], runtime: Runtime, cache: &ModuleCache) -> Result<Self> {
+        let key = ModuleCache::key(wasm, runtime);
+        if let Some(compiled) = cache.get(&key) {
+            // Entries written by incompatible hosts or corrupted ones are replaced.
+            match Self::deserialize(&compiled, runtime) {
+                Ok(module) => return Ok(module),
+                Err(error) => warn!("Ignoring cached module {}: {}", key, error),
+            }
+        }
+
+        let module = Self::new(wasm, runtime)?;
+        // A failed write only makes loading the module slower next time.
+        if let Err(error) = module
+            .serialize()
+            .and_then(|compiled| cache.insert(&key, &compiled))
+        {
+            warn!("Can't cache module {}: {}", key, error);
+        }
+        Ok(module)
+    }
+
+    /// Serializes the normalised and compiled module, it can only be deserialized by the same
+    /// version of lunatic.
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        let module = match &self.module {
+            #[cfg(feature = "vm-wasmtime")]
+            Module::Wasmtime(module) => module.serialize()?,
+            #[cfg(feature = "vm-wasmer")]
+            Module::Wasmer(module) => module.serialize()?,
+        };
+        let compiled = Compiled {
+            min_memory: self.min_memory,
+            max_memory: self.max_memory,
+            module,
+        };
+        Ok(bincode::serialize(&compiled)?)
+    }
+
+    /// Loads a module serialized with `serialize` for `runtime`.
+    ///
+    /// The compiled code isn't validated, so `compiled` needs to come from a trusted source.
+    pub fn deserialize(compiled: &[u8], runtime: Runtime) -> Result<Self> {
+        let compiled: Compiled = bincode::deserialize(compiled)?;
+        let module = match runtime {
+            #[cfg(feature = "vm-wasmtime")]
+            Runtime::Wasmtime => Module::Wasmtime(WasmtimeModule::deserialize(
+                &wasmtime_engine(),
+                &compiled.module,
+            )?),
+            #[cfg(feature = "vm-wasmer")]
+            Runtime::Wasmer => Module::Wasmer(unsafe {
+                WasmerModule::deserialize(&wasmer_engine(), &compiled.module)?
+            }),
+        };
+
+        Ok(Self {
+            module,
+            min_memory: compiled.min_memory,
+            max_memory: compiled.max_memory,
+        })
+    }
+
     pub fn runtime(&self) -> Runtime {
         self.module.runtime()
     }
@@ -107,4 +221,36 @@ impl LunaticModule {
     pub fn max_memory(&self) -> Option<u32> {
         self.max_memory
     }
+
+    /// Returns the parameter types of the exported function `name`.
+    pub fn function_params(&self, name: &str) -> Result<Vec<ParamType>> {
+        let params: Option<Vec<_>> = match &self.module {
+            #[cfg(feature = "vm-wasmtime")]
+            Module::Wasmtime(module) => match module.get_export(name) {
+                Some(wasmtime::ExternType::Func(func)) => Some(
+                    func.params()
+                        .map(|ty| ParamType::from_wasmtime(&ty))
+                        .collect(),
+                ),
+                _ => None,
+            },
+            #[cfg(feature = "vm-wasmer")]
+            Module::Wasmer(module) => module
+                .exports()
+                .find(|export| export.name() == name)
+                .and_then(|export| match export.ty() {
+                    wasmer::ExternType::Function(func) => {
+                        Some(func.params().iter().map(ParamType::from_wasmer).collect())
+                    }
+                    _ => None,
+                }),
+        };
+        let params = params.ok_or_else(|| anyhow!("No exported function `{}`", name))?;
+        params.into_iter().collect::<Option<_>>().ok_or_else(|| {
+            anyhow!(
+                "Function `{}` takes parameters of types that can't be passed",
+                name
+            )
+        })
+    }
 }
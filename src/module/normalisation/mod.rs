@@ -15,6 +15,10 @@ mod reduction_counting;
 mod shared_memory;
 mod stdlib;
 
+/// Changes every time the patches transform modules differently, so that modules compiled before
+/// aren't reused.
+pub const VERSION: u32 = 1;
+
 /// Patches:
 /// * Add reduction counters and yielding to functions and ~hot loops~.
 /// * Add low level functions required by the Lunatic stdlib.
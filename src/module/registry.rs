@@ -0,0 +1,139 @@
+use super::LunaticModule;
+use crate::api::channel::ChannelSender;
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+/// Modules processes can be spawned from, looked up by name.
+///
+/// Clones share the same modules, so a module loaded by one process can be used by all processes
+/// sharing the registry.
+///
+/// Registering a module under a name that is already taken loads a new version of it. Processes
+/// spawned from the name afterwards run the new version, while running processes keep the version
+/// they were spawned from. Channels subscribed to the name receive an upgrade message, telling
+/// long-lived processes to hand their state over to a process running the new version.
+///
+/// Processes can only replace modules that were loaded by processes, not the ones registered by
+/// the host.
+#[derive(Clone, Default)]
+pub struct ModuleRegistry(Arc<RwLock<Modules>>);
+
+#[derive(Default)]
+struct Modules {
+    modules: HashMap<String, LunaticModule>,
+    // Latest version of each name, kept after removing the module so versions are never reused.
+    versions: HashMap<String, u64>,
+    // Names of the modules loaded by processes.
+    loaded: HashSet<String>,
+    subscribers: HashMap<String, Vec<ChannelSender>>,
+}
+
+impl ModuleRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `module` as `name` and returns its version, starting at 1 for the first module
+    /// registered under the name.
+    ///
+    /// The upgrade message sent to subscribers contains the new version as a little-endian `u64`
+    /// followed by the name. Subscribers that can't receive it, because their channel is closed or
+    /// full, are dropped.
+    pub fn insert<S: Into<String>>(&self, name: S, module: LunaticModule) -> u64 {
+        let name = name.into();
+        let mut modules = self.0.write().unwrap();
+        modules.loaded.remove(&name);
+        let (version, subscribers) = modules.register(name.clone(), module);
+        drop(modules);
+        self.notify(&name, version, subscribers);
+        version
+    }
+
+    /// Registers `module` loaded by a process as `name` and returns its version.
+    ///
+    /// Returns `None` if `name` is taken by a module that wasn't loaded by a process.
+    pub fn insert_loaded<S: Into<String>>(&self, name: S, module: LunaticModule) -> Option<u64> {
+        let name = name.into();
+        let mut modules = self.0.write().unwrap();
+        if modules.modules.contains_key(&name) && !modules.loaded.contains(&name) {
+            return None;
+        }
+        modules.loaded.insert(name.clone());
+        let (version, subscribers) = modules.register(name.clone(), module);
+        drop(modules);
+        self.notify(&name, version, subscribers);
+        Some(version)
+    }
+
+    pub fn get(&self, name: &str) -> Option<LunaticModule> {
+        self.0.read().unwrap().modules.get(name).cloned()
+    }
+
+    /// Returns the version of the module registered as `name`.
+    pub fn version(&self, name: &str) -> Option<u64> {
+        let modules = self.0.read().unwrap();
+        if modules.modules.contains_key(name) {
+            modules.versions.get(name).copied()
+        } else {
+            None
+        }
+    }
+
+    pub fn remove(&self, name: &str) -> Option<LunaticModule> {
+        let mut modules = self.0.write().unwrap();
+        modules.loaded.remove(name);
+        modules.modules.remove(name)
+    }
+
+    /// Returns the names of all registered modules.
+    pub fn names(&self) -> Vec<String> {
+        self.0.read().unwrap().modules.keys().cloned().collect()
+    }
+
+    /// Sends an upgrade message to `sender` every time a new version is registered as `name`.
+    ///
+    /// A channel is only subscribed once, no matter through how many senders.
+    pub fn subscribe<S: Into<String>>(&self, name: S, sender: ChannelSender) {
+        let mut modules = self.0.write().unwrap();
+        let subscribers = modules.subscribers.entry(name.into()).or_default();
+        subscribers.retain(|subscriber| !subscriber.is_closed());
+        if !subscribers
+            .iter()
+            .any(|subscriber| subscriber.same_channel(&sender))
+        {
+            subscribers.push(sender);
+        }
+    }
+
+    // Sends the upgrade message to `subscribers` without holding the lock, then drops the ones that
+    // couldn't receive it.
+    fn notify(&self, name: &str, version: u64, subscribers: Vec<ChannelSender>) {
+        let mut message = version.to_le_bytes().to_vec();
+        message.extend_from_slice(name.as_bytes());
+        let failed: Vec<ChannelSender> = subscribers
+            .into_iter()
+            .filter(|sender| sender.try_send(&message, Vec::new()).is_err())
+            .collect();
+        if failed.is_empty() {
+            return;
+        }
+        let mut modules = self.0.write().unwrap();
+        if let Some(subscribers) = modules.subscribers.get_mut(name) {
+            subscribers.retain(|sender| !failed.iter().any(|failed| failed.same_channel(sender)));
+        }
+    }
+}
+
+impl Modules {
+    // Returns the new version and the subscribers that need to be notified about it.
+    fn register(&mut self, name: String, module: LunaticModule) -> (u64, Vec<ChannelSender>) {
+        let version = self.versions.get(&name).map_or(1, |version| version + 1);
+        self.versions.insert(name.clone(), version);
+        self.modules.insert(name.clone(), module);
+        let subscribers = self.subscribers.get(&name).cloned().unwrap_or_default();
+        (version, subscribers)
+    }
+}
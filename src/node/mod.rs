@@ -0,0 +1,473 @@
+//! Nodes connect lunatic runtimes living in different OS processes or on different machines.
+//!
+//! Nodes are connected over TCP and identified by their names. A process can spawn processes on a
+//! connected node, running a module that was registered there under a name. Messages sent to another
+//! node can only carry channel senders as host resources. The sending node exports them and the
+//! receiving node gets a `ChannelSender::Remote`, forwarding all messages over the connection. Other
+//! host resources (streams, processes, ...) are bound to the node they were created on.
+//!
+//! Frames are limited to 16 MiB. If a channel doesn't keep up with the messages sent to it from
+//! another node, further messages are dropped once `EXPORTED_QUEUE_SIZE` of them are waiting.
+//!
+//! Connections are not authenticated or encrypted. Any node that can reach a listening node can
+//! spawn processes from its registered modules, so nodes should only listen on trusted networks.
+
+pub mod protocol;
+
+use crate::api::channel::{
+    host_resources::Resource, ChannelReceiver, ChannelSender, LocalSender, Message,
+};
+use crate::api::networking::timeout::with_timeout;
+use crate::api::process::{FunctionLookup, MemoryChoice, Process, ProcessConfig, EXECUTOR};
+use crate::module::LunaticModule;
+use protocol::*;
+
+use anyhow::{anyhow, bail, Result};
+use smol::channel::{bounded, unbounded, Sender};
+use smol::net::{TcpListener, TcpStream};
+
+use std::{
+    collections::HashMap,
+    net::{Shutdown, SocketAddr},
+    sync::atomic::{AtomicU64, Ordering},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Messages from another node waiting for a full channel, further ones are dropped.
+pub const EXPORTED_QUEUE_SIZE: usize = 1024;
+
+// Connections are dropped if the other node doesn't introduce itself in time.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A lunatic runtime that other nodes can connect to.
+#[derive(Clone)]
+pub struct Node(Arc<Inner>);
+
+// A channel sender exported to another node.
+struct Exported {
+    // Name of the node it was exported to.
+    node: String,
+    sender: LocalSender,
+    // Messages from the other node are queued here and forwarded to `sender` by a separate task, so
+    // that a full channel doesn't stop the connection from reading other frames. Messages arriving
+    // while the queue is full are dropped.
+    queue: Sender<Message>,
+}
+
+// Receives the result of a process spawned on another node.
+type ExitSender = Sender<Result<(), String>>;
+
+struct Inner {
+    name: String,
+    // Used by processes that other nodes spawn here.
+    config: Mutex<ProcessConfig>,
+    // Frames waiting to be written to connected nodes, with the id of the connection.
+    peers: Mutex<HashMap<String, (u64, Sender<Frame>)>>,
+    // Channel senders used by other nodes.
+    exported: Mutex<HashMap<u64, Exported>>,
+    // Processes spawned on other nodes, waiting for the `Exited` frame.
+    spawned: Mutex<HashMap<u64, (String, ExitSender)>>,
+    next_id: AtomicU64,
+}
+
+impl Node {
+    /// Creates a node, processes spawned on it by other nodes use `config`.
+    ///
+    /// Other nodes can spawn processes from all modules in the registry of `config`.
+    pub fn new<S: Into<String>>(name: S, config: ProcessConfig) -> Self {
+        Self(Arc::new(Inner {
+            name: name.into(),
+            config: Mutex::new(config),
+            peers: Mutex::new(HashMap::new()),
+            exported: Mutex::new(HashMap::new()),
+            spawned: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Adds `module` to the registry of the node as `name`.
+    pub fn register_module<S: Into<String>>(&self, name: S, module: LunaticModule) {
+        self.0.config.lock().unwrap().modules.insert(name, module);
+    }
+
+    /// Returns the names of all connected nodes.
+    pub fn peers(&self) -> Vec<String> {
+        self.0.peers.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Accepts connections from other nodes on `address` (HOST:PORT) and returns the bound address.
+    ///
+    /// Connecting nodes are not authenticated, everyone who can reach `address` can spawn processes
+    /// on this node.
+    pub async fn listen(&self, address: &str) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(address).await?;
+        let local_addr = listener.local_addr()?;
+        let node = self.clone();
+        EXECUTOR
+            .spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let node = node.clone();
+                    EXECUTOR
+                        .spawn(async move {
+                            // A failed handshake only affects the connecting node.
+                            let _ignore = node.handshake(stream).await;
+                        })
+                        .detach();
+                }
+            })
+            .detach();
+        Ok(local_addr)
+    }
+
+    /// Connects to the node listening on `address` (HOST:PORT) and returns its name.
+    pub async fn connect(&self, address: &str) -> Result<String> {
+        let stream = TcpStream::connect(address).await?;
+        self.handshake(stream).await
+    }
+
+    /// Spawns a process on the connected node `node`, calling the function at `index` in the table
+    /// of the module registered there as `module`.
+    ///
+    /// The context is passed to the new process like in `spawn_with_context`. The returned process
+    /// finishes once the remote one does.
+    pub fn spawn(
+        &self,
+        node: &str,
+        module: &str,
+        index: u32,
+        context: &[u8],
+        host_resources: Vec<Resource>,
+    ) -> Result<Process> {
+        self.check_connected(node)?;
+        let context = self.serialize(node, context, host_resources)?;
+        let id = self.0.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = bounded(1);
+        self.0
+            .spawned
+            .lock()
+            .unwrap()
+            .insert(id, (node.to_string(), sender));
+        let frame = Frame::Spawn {
+            id,
+            module: module.to_string(),
+            index,
+            context,
+        };
+        if let Err(err) = self.send(node, frame) {
+            self.0.spawned.lock().unwrap().remove(&id);
+            return Err(err);
+        }
+
+        let node = node.to_string();
+        Ok(Process::spawn(async move {
+            match receiver.recv().await {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(error)) => Err(anyhow!(error).into()),
+                Err(_) => Err(anyhow!("Lost the connection to node `{}`", node).into()),
+            }
+        }))
+    }
+
+    fn check_connected(&self, node: &str) -> Result<()> {
+        if self.0.peers.lock().unwrap().contains_key(node) {
+            Ok(())
+        } else {
+            Err(anyhow!("Not connected to node `{}`", node))
+        }
+    }
+
+    fn send(&self, node: &str, frame: Frame) -> Result<()> {
+        match self.0.peers.lock().unwrap().get(node) {
+            Some((_, peer)) => peer
+                .try_send(frame)
+                .map_err(|_| anyhow!("Lost the connection to node `{}`", node)),
+            None => Err(anyhow!("Not connected to node `{}`", node)),
+        }
+    }
+
+    async fn handshake(&self, mut stream: TcpStream) -> Result<String> {
+        stream.set_nodelay(true)?;
+        let hello = Frame::Hello {
+            node: self.0.name.clone(),
+        };
+        let exchange = async {
+            write_frame(&mut stream, &hello).await?;
+            read_frame(&mut stream).await
+        };
+        let peer = match with_timeout(Some(HANDSHAKE_TIMEOUT), exchange).await? {
+            Frame::Hello { node } => node,
+            _ => bail!("Connected node didn't introduce itself"),
+        };
+        if peer == self.0.name {
+            bail!("Connected node is also named `{}`", peer);
+        }
+
+        // A new connection to the same node replaces the old one.
+        let connection = self.0.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = unbounded();
+        self.0
+            .peers
+            .lock()
+            .unwrap()
+            .insert(peer.clone(), (connection, sender));
+
+        let mut writer = stream.clone();
+        EXECUTOR
+            .spawn(async move {
+                while let Ok(frame) = receiver.recv().await {
+                    if write_frame(&mut writer, &frame).await.is_err() {
+                        break;
+                    }
+                }
+                // Wakes up the reading task.
+                let _ignore = writer.shutdown(Shutdown::Both);
+            })
+            .detach();
+
+        let node = self.clone();
+        let name = peer.clone();
+        EXECUTOR
+            .spawn(async move {
+                let mut reader = stream;
+                while let Ok(frame) = read_frame(&mut reader).await {
+                    node.receive(&name, frame);
+                }
+                node.disconnected(&name, connection);
+            })
+            .detach();
+        Ok(peer)
+    }
+
+    fn receive(&self, peer: &str, frame: Frame) {
+        match frame {
+            Frame::Hello { .. } => {}
+            Frame::Spawn {
+                id,
+                module,
+                index,
+                context,
+            } => {
+                if let Err(err) = self.spawn_for(peer, id, &module, index, context) {
+                    let error = Some(err.to_string());
+                    let _ignore = self.send(peer, Frame::Exited { id, error });
+                }
+            }
+            Frame::Exited { id, error } => {
+                if let Some((_, result)) = self.0.spawned.lock().unwrap().remove(&id) {
+                    let _ignore = result.try_send(error.map_or(Ok(()), Err));
+                }
+            }
+            Frame::Message { channel, message } => {
+                let queue = match self.0.exported.lock().unwrap().get(&channel) {
+                    Some(exported) => exported.queue.clone(),
+                    None => return,
+                };
+                let _ignore = queue.try_send(self.deserialize(message));
+            }
+            Frame::Release { channel } => {
+                self.0.exported.lock().unwrap().remove(&channel);
+            }
+        }
+    }
+
+    // Spawns a process requested by `peer`, the result is reported back with an `Exited` frame.
+    fn spawn_for(
+        &self,
+        peer: &str,
+        id: u64,
+        module: &str,
+        index: u32,
+        context: RemoteMessage,
+    ) -> Result<()> {
+        let mut config = self.0.config.lock().unwrap().clone();
+        let module = match config.modules.get(module) {
+            Some(module) => module,
+            None => bail!("No module `{}` on node `{}`", module, self.0.name),
+        };
+        config.node = Some(self.clone());
+        let (sender, receiver) = bounded(1);
+        let _ignore = sender.try_send(self.deserialize(context));
+
+        let node = self.clone();
+        let peer = peer.to_string();
+        EXECUTOR
+            .spawn(async move {
+                let result = Process::create(
+                    Some(ChannelReceiver::from(receiver)),
+                    module,
+                    FunctionLookup::TableIndex(index),
+                    MemoryChoice::New(None),
+                    config,
+                )
+                .await;
+                let error = result.err().map(|err| err.error.to_string());
+                let _ignore = node.send(&peer, Frame::Exited { id, error });
+            })
+            .detach();
+        Ok(())
+    }
+
+    fn disconnected(&self, peer: &str, connection: u64) {
+        {
+            let mut peers = self.0.peers.lock().unwrap();
+            match peers.get(peer) {
+                Some((current, _)) if *current == connection => peers.remove(peer),
+                // The connection was replaced by a newer one.
+                _ => return,
+            };
+        }
+        self.0
+            .exported
+            .lock()
+            .unwrap()
+            .retain(|_, exported| exported.node != peer);
+        let error = format!("Lost the connection to node `{}`", peer);
+        self.0.spawned.lock().unwrap().retain(|_, (node, result)| {
+            if node == peer {
+                let _ignore = result.try_send(Err(error.clone()));
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    // Prepares a message for `peer`, exporting all channel senders in it.
+    fn serialize(
+        &self,
+        peer: &str,
+        data: &[u8],
+        host_resources: Vec<Resource>,
+    ) -> Result<RemoteMessage> {
+        // All resources are checked first, so that nothing gets exported if the message can't be sent.
+        for resource in host_resources.iter() {
+            match resource {
+                Resource::Empty | Resource::ChannelSender(ChannelSender::Local(_)) => {}
+                Resource::ChannelSender(ChannelSender::Remote(remote)) => {
+                    if remote.node != peer {
+                        bail!(
+                            "A ChannelSender of node `{}` can only be sent back to it, not to node `{}`",
+                            remote.node,
+                            peer
+                        );
+                    }
+                }
+                resource => bail!("A {} can't be sent to another node", resource.name()),
+            }
+        }
+
+        let resources = host_resources
+            .into_iter()
+            .map(|resource| match resource {
+                Resource::ChannelSender(ChannelSender::Local(sender)) => {
+                    let channel = self.0.next_id.fetch_add(1, Ordering::Relaxed);
+                    let (queue, queued) = bounded(EXPORTED_QUEUE_SIZE);
+                    let forward = sender.clone();
+                    // Finishes once the sender is released and all queued messages are forwarded.
+                    EXECUTOR
+                        .spawn(async move {
+                            while let Ok(message) = queued.recv().await {
+                                if forward.send(message).await.is_err() {
+                                    break;
+                                }
+                            }
+                        })
+                        .detach();
+                    let exported = Exported {
+                        node: peer.to_string(),
+                        sender,
+                        queue,
+                    };
+                    self.0.exported.lock().unwrap().insert(channel, exported);
+                    RemoteResource::ChannelSender {
+                        node: self.0.name.clone(),
+                        channel,
+                    }
+                }
+                Resource::ChannelSender(ChannelSender::Remote(remote)) => {
+                    RemoteResource::ChannelSender {
+                        node: remote.node.clone(),
+                        channel: remote.channel,
+                    }
+                }
+                _ => RemoteResource::Empty,
+            })
+            .collect();
+        Ok(RemoteMessage {
+            data: data.to_vec(),
+            resources,
+        })
+    }
+
+    fn deserialize(&self, message: RemoteMessage) -> Message {
+        let host_resources = message
+            .resources
+            .into_iter()
+            .map(|resource| match resource {
+                RemoteResource::Empty => Resource::Empty,
+                // One of our own senders came back.
+                RemoteResource::ChannelSender { node, channel } if node == self.0.name => {
+                    match self.0.exported.lock().unwrap().get(&channel) {
+                        Some(exported) => ChannelSender::Local(exported.sender.clone()).into(),
+                        None => Resource::Empty,
+                    }
+                }
+                RemoteResource::ChannelSender { node, channel } => {
+                    ChannelSender::Remote(Arc::new(RemoteChannel {
+                        local: self.clone(),
+                        node,
+                        channel,
+                    }))
+                    .into()
+                }
+            })
+            .collect();
+        Message::new(message.data.as_ptr(), message.data.len(), host_resources)
+    }
+}
+
+/// A channel sender exported by another node, messages are forwarded to it over the connection.
+///
+/// Once dropped the other node is notified, so that it can drop the exported sender.
+pub struct RemoteChannel {
+    local: Node,
+    node: String,
+    channel: u64,
+}
+
+impl RemoteChannel {
+    /// Name of the node the channel lives on.
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    /// Id the other node exported the channel as.
+    pub fn channel(&self) -> u64 {
+        self.channel
+    }
+
+    /// Queues the message for sending, it doesn't wait until the message is received.
+    pub fn send(&self, data: &[u8], host_resources: Vec<Resource>) -> Result<()> {
+        self.local.check_connected(&self.node)?;
+        let message = self.local.serialize(&self.node, data, host_resources)?;
+        let frame = Frame::Message {
+            channel: self.channel,
+            message,
+        };
+        self.local.send(&self.node, frame)
+    }
+}
+
+impl Drop for RemoteChannel {
+    fn drop(&mut self) {
+        let frame = Frame::Release {
+            channel: self.channel,
+        };
+        let _ignore = self.local.send(&self.node, frame);
+    }
+}
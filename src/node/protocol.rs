@@ -0,0 +1,79 @@
+//! Frames exchanged between connected nodes.
+//!
+//! Every frame is sent as its length (a little-endian `u32`) followed by the bincode encoded `Frame`.
+
+use serde::{Deserialize, Serialize};
+use smol::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use std::io;
+
+/// Larger frames are rejected, so that a misbehaving peer can't make us allocate arbitrary amounts
+/// of memory.
+const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+pub enum Frame {
+    /// First frame sent in both directions after connecting.
+    Hello { node: String },
+    /// Spawns a process running the function at `index` in the table of `module`.
+    Spawn {
+        id: u64,
+        module: String,
+        index: u32,
+        context: RemoteMessage,
+    },
+    /// The process started by `Spawn { id, .. }` finished, `error` is set if it failed.
+    Exited { id: u64, error: Option<String> },
+    /// Message for the channel sender the receiving node exported as `channel`.
+    Message {
+        channel: u64,
+        message: RemoteMessage,
+    },
+    /// The peer doesn't use the channel sender exported as `channel` anymore.
+    Release { channel: u64 },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RemoteMessage {
+    pub data: Vec<u8>,
+    pub resources: Vec<RemoteResource>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum RemoteResource {
+    Empty,
+    /// Channel sender exported by `node` as `channel`.
+    ChannelSender {
+        node: String,
+        channel: u64,
+    },
+}
+
+pub async fn write_frame<W>(writer: &mut W, frame: &Frame) -> Result<(), io::Error>
+where
+    W: AsyncWrite + Unpin,
+{
+    let bytes =
+        bincode::serialize(frame).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    writer
+        .write_all(&(bytes.len() as u32).to_le_bytes())
+        .await?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await
+}
+
+pub async fn read_frame<R>(reader: &mut R) -> Result<Frame, io::Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut len = [0; 4];
+    reader.read_exact(&mut len).await?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        let message = format!("frame of {} bytes is too large", len);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes).await?;
+    bincode::deserialize(&bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
@@ -0,0 +1,51 @@
+//! Checks that modules compiled ahead of time can be loaded and run.
+
+mod common;
+
+use common::{exit_code, run};
+use lunatic_runtime::api::process::ProcessConfig;
+use lunatic_runtime::module::{is_artifact, LunaticModule, Runtime};
+
+const MODULE: &str = r#"
+(module
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (func (export "_start")
+        (call $proc_exit (i32.const 7)))
+)
+"#;
+
+#[test]
+fn run_compiled_modules() {
+    let wasm = wat::parse_str(MODULE).unwrap();
+    assert!(!is_artifact(&wasm));
+
+    for runtime in Runtime::all() {
+        let artifact = LunaticModule::new(&wasm, runtime)
+            .unwrap()
+            .to_artifact()
+            .unwrap();
+        assert!(is_artifact(&artifact));
+
+        let module = LunaticModule::from_artifact(&artifact).unwrap();
+        assert_eq!(module.runtime().name(), runtime.name());
+        assert_eq!(exit_code(run(module, ProcessConfig::default())), Some(7));
+    }
+}
+
+#[test]
+fn reject_incompatible_modules() {
+    assert_eq!(
+        LunaticModule::from_artifact(&wat::parse_str(MODULE).unwrap())
+            .err()
+            .unwrap()
+            .to_string(),
+        "Not a module compiled by lunatic"
+    );
+    assert_eq!(
+        LunaticModule::from_artifact(b"\0lunatic\x02\0\0\0")
+            .err()
+            .unwrap()
+            .to_string(),
+        "Module was compiled by an incompatible version of lunatic"
+    );
+}
@@ -0,0 +1,49 @@
+//! Helpers shared by the integration tests, each test only uses some of them.
+#![allow(dead_code)]
+
+use lunatic_runtime::api::process::{
+    Error, FunctionLookup, MemoryChoice, Process, ProcessConfig, EXECUTOR,
+};
+use lunatic_runtime::module::{LunaticModule, Runtime};
+
+use std::sync::Once;
+use std::thread;
+
+/// Compiles a module from its text format.
+pub fn module(wat: &str) -> LunaticModule {
+    let wasm = wat::parse_str(wat).unwrap();
+    LunaticModule::new(&wasm, Runtime::default()).unwrap()
+}
+
+/// Runs `_start` of `module` in a new process and waits until it finishes.
+pub fn run(module: LunaticModule, config: ProcessConfig) -> Result<(), Error<()>> {
+    let start = FunctionLookup::Name("_start".to_string(), Vec::new());
+    run_function(module, start, config)
+}
+
+pub fn run_function(
+    module: LunaticModule,
+    function: FunctionLookup,
+    config: ProcessConfig,
+) -> Result<(), Error<()>> {
+    smol::future::block_on(Process::create(
+        None,
+        module,
+        function,
+        MemoryChoice::New(None),
+        config,
+    ))
+}
+
+/// Returns the exit code if the process stopped by calling `proc_exit`.
+pub fn exit_code(result: Result<(), Error<()>>) -> Option<u32> {
+    result.err().and_then(|error| error.exit_code())
+}
+
+/// Starts a thread running the `EXECUTOR`, which spawned processes and nodes run on.
+pub fn start_executor() {
+    static START: Once = Once::new();
+    START.call_once(|| {
+        thread::spawn(|| smol::future::block_on(EXECUTOR.run(smol::future::pending::<()>())));
+    });
+}
@@ -0,0 +1,84 @@
+//! Checks the name resolvers that don't need network access.
+
+use lunatic_runtime::api::networking::dns::{CachingResolver, NameResolver, StaticResolver};
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn static_hosts() {
+    let mut resolver = StaticResolver::new();
+    resolver
+        .host("db.test", "10.0.0.5".parse().unwrap())
+        .host("db.test", "fd00::5".parse().unwrap());
+
+    let addresses = resolver.resolve("db.test:5432").unwrap();
+    let expected: Vec<SocketAddr> = vec![
+        "10.0.0.5:5432".parse().unwrap(),
+        "[fd00::5]:5432".parse().unwrap(),
+    ];
+    assert_eq!(addresses, expected);
+
+    assert_eq!(
+        resolver.resolve("[::1]:80").unwrap(),
+        vec!["[::1]:80".parse::<SocketAddr>().unwrap()]
+    );
+    let err = resolver.resolve("unknown.test:80").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let err = resolver.resolve("db.test").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+// Counts the lookups reaching the wrapped resolver.
+struct CountingResolver(StaticResolver, AtomicUsize);
+
+impl NameResolver for CountingResolver {
+    fn resolve(&self, name: &str) -> Result<Vec<SocketAddr>, io::Error> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        self.0.resolve(name)
+    }
+}
+
+fn counting_resolver() -> Arc<CountingResolver> {
+    let mut hosts = StaticResolver::new();
+    hosts
+        .host("a.test", "10.0.0.1".parse().unwrap())
+        .host("b.test", "10.0.0.2".parse().unwrap());
+    Arc::new(CountingResolver(hosts, AtomicUsize::new(0)))
+}
+
+#[test]
+fn caching_until_ttl_expires() {
+    let inner = counting_resolver();
+    let resolver = CachingResolver::new(inner.clone(), Duration::from_millis(100), 16);
+
+    resolver.resolve("a.test:80").unwrap();
+    resolver.resolve("a.test:80").unwrap();
+    assert_eq!(inner.1.load(Ordering::SeqCst), 1);
+
+    // Failures aren't cached.
+    assert!(resolver.resolve("c.test:80").is_err());
+    assert!(resolver.resolve("c.test:80").is_err());
+    assert_eq!(inner.1.load(Ordering::SeqCst), 3);
+
+    std::thread::sleep(Duration::from_millis(150));
+    resolver.resolve("a.test:80").unwrap();
+    assert_eq!(inner.1.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn caching_evicts_when_full() {
+    let inner = counting_resolver();
+    let resolver = CachingResolver::new(inner.clone(), Duration::from_secs(60), 1);
+
+    resolver.resolve("a.test:80").unwrap();
+    resolver.resolve("b.test:80").unwrap();
+    resolver.resolve("b.test:80").unwrap();
+    assert_eq!(inner.1.load(Ordering::SeqCst), 2);
+    // `a.test` was evicted to make room for `b.test`.
+    resolver.resolve("a.test:80").unwrap();
+    assert_eq!(inner.1.load(Ordering::SeqCst), 3);
+}
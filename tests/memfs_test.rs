@@ -0,0 +1,321 @@
+//! Checks that processes can run inside of an in-memory filesystem.
+
+mod common;
+
+use common::{module, run};
+use lunatic_runtime::api::process::ProcessConfig;
+use lunatic_runtime::api::wasi::fs::SetTime;
+use lunatic_runtime::api::wasi::types::{
+    OpenFlags, Status, ADVICE_NORMAL, ADVICE_SEQUENTIAL, OFLAGS_CREAT, OFLAGS_DIRECTORY,
+    OFLAGS_EXCL,
+};
+use lunatic_runtime::api::wasi::{Buffer, FileSystem, HostFileSystem, MemoryFileSystem, Output};
+
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use std::{env, fs, process};
+
+// Creates `/hello.txt` in the preopened root directory and writes "hello" to it.
+const CREATE_FILE: &str = r#"
+(module
+    (import "wasi_snapshot_preview1" "path_open"
+        (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (memory 1)
+    (data (i32.const 64) "hello.txt")
+    (data (i32.const 80) "hello")
+    (func (export "_start")
+        ;; open with OFLAGS_CREAT, the new fd is stored at 16
+        (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 64) (i32.const 9)
+            (i32.const 1) (i64.const 0) (i64.const 0) (i32.const 0) (i32.const 16)))
+        ;; ciovec for the write: buf = 80, len = 5
+        (i32.store (i32.const 0) (i32.const 80))
+        (i32.store (i32.const 4) (i32.const 5))
+        (drop (call $fd_write (i32.load (i32.const 16)) (i32.const 0) (i32.const 1) (i32.const 8))))
+)
+"#;
+
+#[test]
+fn create_file_in_memory() {
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    let mut config = ProcessConfig::default();
+    config.wasi.filesystem = filesystem.clone();
+
+    run(module(CREATE_FILE), config)
+        .map_err(|e| e.error)
+        .unwrap();
+
+    let file = filesystem
+        .open(Path::new("/hello.txt"), OpenFlags::new(), false)
+        .ok()
+        .unwrap();
+    let mut buf = [0; 16];
+    let read = file
+        .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+        .ok()
+        .unwrap();
+    assert_eq!(&buf[..read], b"hello");
+}
+
+// Lists the preopened root directory in two calls to `fd_readdir` and writes the raw entries to
+// stdout. The first call only has room for `.` and `..`, the second one continues from cookie 2.
+const READ_DIR: &str = r#"
+(module
+    (import "wasi_snapshot_preview1" "fd_readdir"
+        (func $fd_readdir (param i32 i32 i32 i64 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (func $read_dir (param $buf_len i32) (param $cookie i64)
+        ;; the entries are written to 1024, their size is stored at 16
+        (if (call $fd_readdir (i32.const 3) (i32.const 1024) (local.get $buf_len)
+                (local.get $cookie) (i32.const 16))
+            (then (call $proc_exit (i32.const 1))))
+        ;; ciovec for the write: buf = 1024, len = size of the entries
+        (i32.store (i32.const 0) (i32.const 1024))
+        (i32.store (i32.const 4) (i32.load (i32.const 16)))
+        (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+    (func (export "_start")
+        ;; two headers of 24 bytes and the names `.` and `..`
+        (call $read_dir (i32.const 51) (i64.const 0))
+        (call $read_dir (i32.const 1024) (i64.const 2)))
+)
+"#;
+
+#[test]
+fn read_dir_from_cookie() {
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    for name in &["a.txt", "b.txt", "c.txt"] {
+        assert!(filesystem
+            .open(
+                &Path::new("/").join(name),
+                OpenFlags::from(OFLAGS_CREAT),
+                false
+            )
+            .is_ok());
+    }
+    let stdout = Buffer::new();
+    let mut config = ProcessConfig::default();
+    config.wasi.filesystem = filesystem;
+    config.wasi.stdout = Output::Buffer(stdout.clone());
+
+    run(module(READ_DIR), config).map_err(|e| e.error).unwrap();
+
+    // Each entry is a 24 byte header, starting with the cookie of the next entry and holding the
+    // length of the name at offset 16, followed by the name.
+    let mut entries = Vec::new();
+    let mut output = stdout.contents();
+    while !output.is_empty() {
+        let mut next = [0; 8];
+        next.copy_from_slice(&output[..8]);
+        let mut name_len = [0; 4];
+        name_len.copy_from_slice(&output[16..20]);
+        let end = 24 + u32::from_le_bytes(name_len) as usize;
+        let name = String::from_utf8(output[24..end].to_vec()).unwrap();
+        entries.push((u64::from_le_bytes(next), name));
+        output.drain(..end);
+    }
+    let expected: Vec<_> = vec![".", "..", "a.txt", "b.txt", "c.txt"]
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| (i as u64 + 1, name.to_string()))
+        .collect();
+    assert_eq!(entries, expected);
+}
+
+fn status<T>(result: Result<T, Status>) -> u16 {
+    result.err().unwrap() as u16
+}
+
+// Both filesystems need to treat the flags and rights passed to `open` the same way.
+fn check_open_flags(filesystem: &dyn FileSystem, root: &Path) {
+    let file = root.join("file.txt");
+    let created = filesystem
+        .open(&file, OpenFlags::from(OFLAGS_CREAT), false)
+        .ok()
+        .unwrap();
+    assert_eq!(
+        created.write_vectored(&[IoSlice::new(b"hello")]).ok(),
+        Some(5)
+    );
+
+    let read_only = filesystem
+        .open(&file, OpenFlags::new(), false)
+        .ok()
+        .unwrap();
+    assert_eq!(
+        status(read_only.write_vectored(&[IoSlice::new(b"hello")])),
+        Status::Badf as u16
+    );
+    let writable = filesystem.open(&file, OpenFlags::new(), true).ok().unwrap();
+    assert_eq!(writable.write_vectored(&[IoSlice::new(b"H")]).ok(), Some(1));
+
+    assert_eq!(
+        status(filesystem.open(&file, OpenFlags::from(OFLAGS_CREAT | OFLAGS_EXCL), false)),
+        Status::Exist as u16
+    );
+    assert_eq!(
+        status(filesystem.open(&file, OpenFlags::from(OFLAGS_DIRECTORY), false)),
+        Status::NotDir as u16
+    );
+    assert!(filesystem
+        .open(root, OpenFlags::from(OFLAGS_DIRECTORY), false)
+        .is_ok());
+    assert_eq!(
+        status(filesystem.open(root, OpenFlags::new(), true)),
+        Status::IsDir as u16
+    );
+    assert_eq!(
+        status(filesystem.open(&root.join("missing"), OpenFlags::new(), false)),
+        Status::NoEnt as u16
+    );
+}
+
+#[test]
+fn open_flags_in_memory() {
+    check_open_flags(&MemoryFileSystem::new(), Path::new("/"));
+}
+
+#[test]
+fn open_flags_on_host() {
+    let root = env::temp_dir().join(format!("lunatic-open-flags-{}", process::id()));
+    fs::create_dir_all(&root).unwrap();
+    check_open_flags(&HostFileSystem, &root);
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn memory_file_size_is_limited() {
+    let filesystem = MemoryFileSystem::new();
+    let file = filesystem
+        .open(Path::new("/big"), OpenFlags::from(OFLAGS_CREAT), false)
+        .ok()
+        .unwrap();
+
+    assert_eq!(status(file.set_len(u64::MAX)), Status::Fbig as u16);
+    assert_eq!(file.seek(SeekFrom::Start(1 << 40)).ok(), Some(1 << 40));
+    assert_eq!(
+        status(file.write_vectored(&[IoSlice::new(b"hello")])),
+        Status::Fbig as u16
+    );
+    assert_eq!(
+        status(file.seek(SeekFrom::Start(u64::MAX))),
+        Status::Inval as u16
+    );
+    assert_eq!(file.seek(SeekFrom::End(0)).ok(), Some(0));
+    assert_eq!(
+        status(file.seek(SeekFrom::Current(i64::MIN))),
+        Status::Inval as u16
+    );
+    assert_eq!(file.filestat().ok().unwrap().size, 0);
+}
+
+#[test]
+fn memory_file_metadata() {
+    let filesystem = MemoryFileSystem::new();
+    let path = Path::new("/file.txt");
+    let file = filesystem
+        .open(path, OpenFlags::from(OFLAGS_CREAT), false)
+        .ok()
+        .unwrap();
+    let created = file.filestat().ok().unwrap();
+    assert_eq!(created.nlink, 1);
+    assert_eq!(created.size, 0);
+    assert!(created.mtim > 0);
+
+    // Allocating grows the file, but never shrinks it.
+    assert!(file.allocate(2, 6).is_ok());
+    assert_eq!(file.filestat().ok().unwrap().size, 8);
+    assert!(file.allocate(0, 4).is_ok());
+    assert_eq!(file.filestat().ok().unwrap().size, 8);
+    assert_eq!(status(file.allocate(u64::MAX, 1)), Status::Fbig as u16);
+    assert_eq!(status(file.allocate(0, u64::MAX)), Status::Fbig as u16);
+    assert_eq!(file.filestat().ok().unwrap().size, 8);
+
+    assert!(file.advise(0, 8, ADVICE_NORMAL).is_ok());
+    assert!(file.advise(0, 8, ADVICE_SEQUENTIAL).is_ok());
+    assert_eq!(status(file.advise(0, 8, 200)), Status::Inval as u16);
+    assert!(file.sync_all().is_ok());
+    assert!(file.sync_data().is_ok());
+
+    assert!(file.set_times(SetTime::At(1), SetTime::At(2)).is_ok());
+    let stat = file.filestat().ok().unwrap();
+    assert_eq!((stat.atim, stat.mtim), (1, 2));
+    assert!(stat.ctim >= created.ctim);
+
+    assert!(filesystem
+        .set_times(path, true, SetTime::Keep, SetTime::At(3))
+        .is_ok());
+    let stat = filesystem.filestat(path).ok().unwrap();
+    assert_eq!((stat.atim, stat.mtim), (1, 3));
+
+    assert!(filesystem.hard_link(path, Path::new("/link.txt")).is_ok());
+    assert_eq!(file.filestat().ok().unwrap().nlink, 2);
+    assert!(filesystem.remove_file(path).is_ok());
+    let stat = file.filestat().ok().unwrap();
+    assert_eq!((stat.nlink, stat.size), (1, 8));
+}
+
+#[test]
+fn memory_symlinks_inside_of_paths() {
+    let filesystem = MemoryFileSystem::new();
+    assert!(filesystem.create_dir(Path::new("/dir")).is_ok());
+    assert!(filesystem.create_dir(Path::new("/dir/sub")).is_ok());
+    let file = filesystem
+        .open(
+            Path::new("/dir/sub/file.txt"),
+            OpenFlags::from(OFLAGS_CREAT),
+            false,
+        )
+        .ok()
+        .unwrap();
+    assert!(file.write_vectored(&[IoSlice::new(b"hello")]).is_ok());
+
+    // Absolute and relative links to directories, also chained and followed by `..`.
+    assert!(filesystem
+        .symlink(Path::new("/dir"), Path::new("/absolute"))
+        .is_ok());
+    assert!(filesystem
+        .symlink(Path::new("sub"), Path::new("/dir/relative"))
+        .is_ok());
+    assert!(filesystem
+        .symlink(Path::new("absolute/relative"), Path::new("/chained"))
+        .is_ok());
+    for path in &[
+        "/absolute/sub/file.txt",
+        "/dir/relative/file.txt",
+        "/absolute/relative/file.txt",
+        "/chained/file.txt",
+        "/chained/../sub/file.txt",
+    ] {
+        let stat = filesystem.filestat(Path::new(path)).ok().unwrap();
+        assert_eq!(stat.size, 5, "{}", path);
+    }
+
+    // Links inside of the path are followed even when the last component isn't.
+    assert!(filesystem
+        .symlink(Path::new("file.txt"), Path::new("/chained/link"))
+        .is_ok());
+    assert_eq!(
+        filesystem.read_link(Path::new("/absolute/sub/link")).ok(),
+        Some("file.txt".into())
+    );
+    assert!(filesystem
+        .remove_file(Path::new("/absolute/relative/link"))
+        .is_ok());
+    assert_eq!(
+        status(filesystem.read_link(Path::new("/dir/sub/link"))),
+        Status::NoEnt as u16
+    );
+
+    assert!(filesystem
+        .symlink(Path::new("loop"), Path::new("/loop"))
+        .is_ok());
+    assert_eq!(
+        status(filesystem.filestat(Path::new("/loop/file.txt"))),
+        Status::Loop as u16
+    );
+}
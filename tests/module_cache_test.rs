@@ -0,0 +1,59 @@
+//! Checks that compiled modules are cached on disk and run the same as freshly compiled ones.
+
+mod common;
+
+use common::{exit_code, run};
+use lunatic_runtime::api::process::ProcessConfig;
+use lunatic_runtime::module::{LunaticModule, ModuleCache, Runtime};
+
+use std::{env, fs, process};
+
+const MODULE: &str = r#"
+(module
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (func (export "_start")
+        (call $proc_exit (i32.const 7)))
+)
+"#;
+
+#[test]
+fn cache_compiled_modules() {
+    let directory = env::temp_dir().join(format!("lunatic-module-cache-{}", process::id()));
+    let cache = ModuleCache::new(&directory);
+    let wasm = wat::parse_str(MODULE).unwrap();
+
+    for runtime in Runtime::all() {
+        let key = ModuleCache::key(&wasm, runtime);
+        assert!(cache.get(&key).is_none());
+
+        // Compiled and stored, then loaded from the cache.
+        let module = LunaticModule::new_cached(&wasm, runtime, &cache).unwrap();
+        assert_eq!(exit_code(run(module, ProcessConfig::default())), Some(7));
+        let compiled = cache.get(&key).unwrap();
+        let module = LunaticModule::new_cached(&wasm, runtime, &cache).unwrap();
+        assert_eq!(exit_code(run(module, ProcessConfig::default())), Some(7));
+        let module = LunaticModule::deserialize(&compiled, runtime).unwrap();
+        assert_eq!(exit_code(run(module, ProcessConfig::default())), Some(7));
+
+        // Hits use the entry as it is, without compiling the module.
+        let other = wat::parse_str(MODULE.replace("i32.const 7", "i32.const 8")).unwrap();
+        let other = LunaticModule::new(&other, runtime).unwrap();
+        cache.insert(&key, &other.serialize().unwrap()).unwrap();
+        let module = LunaticModule::new_cached(&wasm, runtime, &cache).unwrap();
+        assert_eq!(exit_code(run(module, ProcessConfig::default())), Some(8));
+
+        // Corrupted entries are compiled again and replaced.
+        cache.insert(&key, b"corrupted").unwrap();
+        let module = LunaticModule::new_cached(&wasm, runtime, &cache).unwrap();
+        assert_eq!(exit_code(run(module, ProcessConfig::default())), Some(7));
+        assert_ne!(cache.get(&key).unwrap(), b"corrupted");
+    }
+
+    #[cfg(all(feature = "vm-wasmtime", feature = "vm-wasmer"))]
+    assert_ne!(
+        ModuleCache::key(&wasm, Runtime::Wasmtime),
+        ModuleCache::key(&wasm, Runtime::Wasmer)
+    );
+
+    fs::remove_dir_all(directory).unwrap();
+}
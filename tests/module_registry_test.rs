@@ -0,0 +1,178 @@
+//! Checks that processes can load modules, spawn processes from them and receive upgrade messages
+//! for new versions, if the configuration allows loading modules.
+
+mod common;
+
+use common::{exit_code, module, run, start_executor};
+use lunatic_runtime::api::channel::ChannelSender;
+use lunatic_runtime::api::process::ProcessConfig;
+use lunatic_runtime::api::wasi::{Buffer, MemoryFileSystem, Output};
+use lunatic_runtime::module::ModuleRegistry;
+
+use std::sync::Arc;
+
+// Writes "plugin" to stdout, `$run` is at index 0 of the table.
+const PLUGIN: &str = r#"
+(module
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (memory 1)
+    (table 1 funcref)
+    (elem (i32.const 0) $run)
+    (data (i32.const 64) "plugin\n")
+    (func $run
+        (i32.store (i32.const 0) (i32.const 64))
+        (i32.store (i32.const 4) (i32.const 7))
+        (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+)
+"#;
+
+// Loads `/plugin.wasm` as "plugin" and runs its function at index 0, then tries to run the function
+// at index 0 of the missing module "missing". Exits with
+// `100 * load result + 10 * first join + second join`.
+const HOST: &str = r#"
+(module
+    (import "lunatic" "load_module_from_path"
+        (func $load (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "spawn_from_module"
+        (func $spawn (param i32 i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "join" (func $join (param i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 0) "plugin")
+    (data (i32.const 16) "plugin.wasm")
+    (data (i32.const 48) "missing")
+    (func (export "_start")
+        (local $code i32)
+        (local.set $code (i32.mul (i32.const 100)
+            (call $load (i32.const 0) (i32.const 6) (i32.const 16) (i32.const 11))))
+        (local.set $code (i32.add (local.get $code) (i32.mul (i32.const 10)
+            (call $join (call $spawn (i32.const 0) (i32.const 6) (i32.const 0)
+                (i32.const 0) (i32.const 0))))))
+        (local.set $code (i32.add (local.get $code)
+            (call $join (call $spawn (i32.const 48) (i32.const 7) (i32.const 0)
+                (i32.const 0) (i32.const 0)))))
+        (call $proc_exit (local.get $code)))
+)
+"#;
+
+// Loads `/plugin.wasm` as "plugin", subscribes to upgrades of it, loads a new version and waits for
+// the upgrade message. Exits with `100 * version + message size`.
+const UPGRADE: &str = r#"
+(module
+    (import "lunatic" "channel" (func $channel (param i32 i32) (result i32)))
+    (import "lunatic" "subscribe_to_upgrades"
+        (func $subscribe (param i32 i32 i32) (result i32)))
+    (import "lunatic" "load_module_from_path"
+        (func $load (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "channel_receive_prepare"
+        (func $receive_prepare (param i32 i32) (result i32)))
+    (import "lunatic" "channel_receive" (func $receive (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 32) "plugin")
+    (data (i32.const 48) "plugin.wasm")
+    (func (export "_start")
+        (local $sender i32)
+        ;; the receiver is stored at 0 and the size of the upgrade message at 4
+        (local.set $sender (call $channel (i32.const 0) (i32.const 0)))
+        (drop (call $load (i32.const 32) (i32.const 6) (i32.const 48) (i32.const 11)))
+        (drop (call $subscribe (i32.const 32) (i32.const 6) (local.get $sender)))
+        (drop (call $load (i32.const 32) (i32.const 6) (i32.const 48) (i32.const 11)))
+        (drop (call $receive_prepare (i32.load (i32.const 0)) (i32.const 4)))
+        (drop (call $receive (i32.const 64) (i32.load (i32.const 4))))
+        (call $proc_exit (i32.add
+            (i32.mul (i32.load (i32.const 64)) (i32.const 100))
+            (i32.load (i32.const 4)))))
+)
+"#;
+
+// Loads `/plugin.wasm` as "plugin" and exits with the result.
+const LOAD: &str = r#"
+(module
+    (import "lunatic" "load_module_from_path"
+        (func $load (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 0) "plugin")
+    (data (i32.const 16) "plugin.wasm")
+    (func (export "_start")
+        (call $proc_exit (call $load (i32.const 0) (i32.const 6) (i32.const 16) (i32.const 11))))
+)
+"#;
+
+fn filesystem_with_plugin() -> MemoryFileSystem {
+    let plugin = wat::parse_str(PLUGIN).unwrap();
+    let mut archive = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(plugin.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, "plugin.wasm", plugin.as_slice())
+        .unwrap();
+    let archive = archive.into_inner().unwrap();
+    MemoryFileSystem::from_tar(archive.as_slice()).unwrap()
+}
+
+#[test]
+fn spawn_from_loaded_module() {
+    start_executor();
+
+    let stdout = Buffer::new();
+    let mut config = ProcessConfig::default();
+    config.wasi.filesystem = Arc::new(filesystem_with_plugin());
+    config.wasi.stdout = Output::Buffer(stdout.clone());
+    config.load_modules = true;
+    let modules = config.modules.clone();
+
+    // Loading and the first process succeeded, the second process failed.
+    assert_eq!(exit_code(run(module(HOST), config)), Some(1));
+    assert_eq!(stdout.contents(), b"plugin\n");
+    assert_eq!(modules.names(), vec!["plugin".to_string()]);
+}
+
+#[test]
+fn upgrade_loaded_module() {
+    let mut config = ProcessConfig::default();
+    config.wasi.filesystem = Arc::new(filesystem_with_plugin());
+    config.load_modules = true;
+    let modules = config.modules.clone();
+
+    // The message announced version 2 of "plugin", 8 bytes of version and 6 of name.
+    assert_eq!(exit_code(run(module(UPGRADE), config)), Some(214));
+    assert_eq!(modules.version("plugin"), Some(2));
+    assert_eq!(modules.version("missing"), None);
+}
+
+#[test]
+fn loading_modules_is_restricted() {
+    // Not allowed by the configuration.
+    let mut config = ProcessConfig::default();
+    config.wasi.filesystem = Arc::new(filesystem_with_plugin());
+    let modules = config.modules.clone();
+    assert_eq!(exit_code(run(module(LOAD), config.clone())), Some(1));
+    assert!(modules.names().is_empty());
+
+    // Modules registered by the host can't be replaced.
+    assert_eq!(modules.insert("plugin", module(PLUGIN)), 1);
+    config.load_modules = true;
+    assert_eq!(exit_code(run(module(LOAD), config)), Some(1));
+    assert_eq!(modules.version("plugin"), Some(1));
+}
+
+#[test]
+fn channels_are_subscribed_once() {
+    let modules = ModuleRegistry::new();
+    let (sender, receiver) = smol::channel::unbounded();
+    let sender = ChannelSender::from(sender);
+    modules.subscribe("plugin", sender.clone());
+    modules.subscribe("plugin", sender);
+    let (closed, _) = smol::channel::unbounded();
+    modules.subscribe("plugin", ChannelSender::from(closed));
+
+    assert_eq!(modules.insert("plugin", module(PLUGIN)), 1);
+    assert_eq!(receiver.len(), 1);
+    assert_eq!(modules.insert("plugin", module(PLUGIN)), 2);
+    assert_eq!(receiver.len(), 2);
+}
@@ -0,0 +1,73 @@
+//! Checks that processes can find out why a networking call failed.
+
+mod common;
+
+use common::{exit_code, module, run};
+use lunatic_runtime::api::process::ProcessConfig;
+use lunatic_runtime::api::wasi::types::Status;
+use lunatic_runtime::api::wasi::{Buffer, Output};
+
+// Binds a listener to a free port, closes it and connects to the port. Writes the message of the
+// error to stdout and exits with its status, or with 100 and up if a check fails.
+const REFUSED_CONNECT: &str = r#"
+(module
+    (import "lunatic" "tcp_bind" (func $tcp_bind (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_listener_local_addr"
+        (func $listener_addr (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "close_tcp_listener" (func $close_listener (param i32)))
+    (import "lunatic" "tcp_connect" (func $tcp_connect (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "last_error_status" (func $last_error_status (result i32)))
+    (import "lunatic" "last_error_message_len" (func $last_error_message_len (result i32)))
+    (import "lunatic" "last_error_message" (func $last_error_message (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 64) "\7f\00\00\01")
+    (func $check (param $ok i32) (param $exit_code i32)
+        (if (i32.eqz (local.get $ok))
+            (then (call $proc_exit (local.get $exit_code)))))
+    (func (export "_start")
+        (call $check (i32.eqz (call $last_error_status)) (i32.const 100))
+
+        ;; the id of the listener is stored at 0, the first resource gets the id 1
+        (call $check (i32.eqz (call $tcp_bind (i32.const 64) (i32.const 4) (i32.const 0)
+            (i32.const 0))) (i32.const 101))
+        (call $check (i32.eq (i32.load (i32.const 0)) (i32.const 1)) (i32.const 102))
+        ;; the port of the listener is stored at 116
+        (call $check (i32.eqz (call $listener_addr (i32.load (i32.const 0))
+            (i32.const 96) (i32.const 112) (i32.const 116) (i32.const 120) (i32.const 124)))
+            (i32.const 103))
+        (call $close_listener (i32.load (i32.const 0)))
+
+        ;; failed calls return the id 0, the status is also returned directly
+        (i32.store (i32.const 4) (i32.const 42))
+        (call $check (i32.eq
+            (call $tcp_connect (i32.const 64) (i32.const 4) (i32.load16_u (i32.const 116))
+                (i32.const 4))
+            (call $last_error_status)) (i32.const 104))
+        (call $check (i32.eqz (i32.load (i32.const 4))) (i32.const 105))
+
+        ;; copy the message to 256 and write it to stdout
+        (call $check (i32.gt_u (call $last_error_message_len) (i32.const 0)) (i32.const 106))
+        (i32.store (i32.const 8) (i32.const 256))
+        (i32.store (i32.const 12) (call $last_error_message (i32.const 256) (i32.const 256)))
+        (call $check (i32.eq (i32.load (i32.const 12)) (call $last_error_message_len))
+            (i32.const 107))
+        (drop (call $fd_write (i32.const 1) (i32.const 8) (i32.const 1) (i32.const 16)))
+        (call $proc_exit (call $last_error_status)))
+)
+"#;
+
+#[test]
+fn refused_connect() {
+    let stdout = Buffer::new();
+    let mut config = ProcessConfig::default();
+    config.wasi.stdout = Output::Buffer(stdout.clone());
+
+    let result = run(module(REFUSED_CONNECT), config);
+
+    assert_eq!(exit_code(result), Some(Status::ConnRefused as u32));
+    let message = String::from_utf8(stdout.contents()).unwrap();
+    assert!(message.to_lowercase().contains("refused"), "{}", message);
+}
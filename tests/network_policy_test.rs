@@ -0,0 +1,124 @@
+//! Checks which addresses a network policy allows and that processes can't get around it.
+
+mod common;
+
+use common::{module, run};
+use lunatic_runtime::api::networking::{AddressRule, NetworkPolicy};
+use lunatic_runtime::api::process::ProcessConfig;
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// Tries to bind to and connect to 127.0.0.1, checking that both calls fail without creating a
+// resource. Exits with `100 * bind result + 10 * connect result + last error status`.
+const BIND_AND_CONNECT: &str = r#"
+(module
+    (import "lunatic" "tcp_bind" (func $tcp_bind (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_connect" (func $tcp_connect (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "last_error_status" (func $last_error_status (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 64) "\7f\00\00\01")
+    (func (export "_start")
+        (local $code i32)
+        ;; the ids are stored at 0 and 4, they have to stay 0
+        (i32.store (i32.const 0) (i32.const 1))
+        (i32.store (i32.const 4) (i32.const 1))
+        (local.set $code (i32.mul (i32.const 100)
+            (call $tcp_bind (i32.const 64) (i32.const 4) (i32.const 0) (i32.const 0))))
+        (local.set $code (i32.add (local.get $code) (i32.mul (i32.const 10)
+            (call $tcp_connect (i32.const 64) (i32.const 4) (i32.const 80) (i32.const 4)))))
+        (if (i32.or (i32.load (i32.const 0)) (i32.load (i32.const 4)))
+            (then (call $proc_exit (i32.const 1))))
+        (call $proc_exit (i32.add (local.get $code) (call $last_error_status))))
+)
+"#;
+
+fn rule(rule: &str) -> AddressRule {
+    rule.parse().unwrap()
+}
+
+#[test]
+fn everything_is_allowed_by_default() {
+    let policy = NetworkPolicy::default();
+    assert!(policy.check_bind(&[0, 0, 0, 0], 80).is_ok());
+    assert!(policy.check_connect(&[10, 0, 0, 1], 22).is_ok());
+    assert!(policy.check_dns("example.com:443").is_ok());
+    assert!(policy.check_unix_sockets("/tmp/socket").is_ok());
+}
+
+#[test]
+fn deny_all_with_exceptions() {
+    let mut policy = NetworkPolicy::deny_all();
+    policy
+        .allow_bind(rule("127.0.0.1:8000-8999"))
+        .allow_connect(rule("93.184.216.0/24:443"))
+        .allow_connect(rule("[2606:2800::/32]"));
+
+    assert!(policy.check_bind(&[127, 0, 0, 1], 8080).is_ok());
+    assert!(policy.check_bind(&[127, 0, 0, 1], 9000).is_err());
+    assert!(policy.check_bind(&[0, 0, 0, 0], 8080).is_err());
+
+    assert!(policy.check_connect(&[93, 184, 216, 34], 443).is_ok());
+    assert!(policy.check_connect(&[93, 184, 216, 34], 80).is_err());
+    assert!(policy.check_connect(&[10, 0, 0, 1], 443).is_err());
+    let ipv6 = "2606:2800:220:1::".parse::<std::net::Ipv6Addr>().unwrap();
+    assert!(policy.check_connect(&ipv6.octets(), 80).is_ok());
+
+    let err = policy.check_dns("example.com:443").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    // Literal addresses don't need DNS.
+    assert!(policy.check_dns("93.184.216.34:443").is_ok());
+    assert!(policy.check_unix_sockets("/tmp/socket").is_err());
+}
+
+#[test]
+fn ipv4_mapped_addresses_match_ipv4_rules() {
+    let mut policy = NetworkPolicy::default();
+    policy
+        .allow_connect(rule("0.0.0.0/0"))
+        .allow_connect(rule("::/0"));
+    let mut denied = NetworkPolicy::default();
+    denied.allow_connect(rule("::/0"));
+
+    let mapped = std::net::Ipv4Addr::new(10, 0, 0, 1).to_ipv6_mapped();
+    assert!(policy.check_connect(&mapped.octets(), 80).is_ok());
+    assert!(denied.check_connect(&mapped.octets(), 80).is_err());
+}
+
+#[test]
+fn invalid_rules() {
+    for invalid in [
+        "10.0.0.0/33",
+        "10.0.0.1:80-79",
+        "[::1",
+        "[::1]80",
+        "localhost",
+    ]
+    .iter()
+    {
+        assert!(invalid.parse::<AddressRule>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn prefix_length_is_checked() {
+    let ipv4 = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0));
+    let ipv6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+    assert!(AddressRule::new(ipv4, 32, 0..=u16::MAX).is_ok());
+    assert!(AddressRule::new(ipv4, 33, 0..=u16::MAX).is_err());
+    assert!(AddressRule::new(ipv6, 128, 0..=u16::MAX).is_ok());
+    assert!(AddressRule::new(ipv6, 129, 0..=u16::MAX).is_err());
+    assert!(AddressRule::new(ipv6, u8::MAX, 0..=u16::MAX).is_err());
+}
+
+#[test]
+fn denied_calls_fail_with_acces() {
+    let mut config = ProcessConfig::default();
+    config.network = NetworkPolicy::deny_all();
+
+    let error = run(module(BIND_AND_CONNECT), config).err().unwrap();
+
+    // Both calls and the last error report `acces` (2).
+    assert_eq!(error.exit_code(), Some(222));
+}
@@ -0,0 +1,124 @@
+//! Checks spawning processes on another node and sending messages between nodes.
+
+mod common;
+
+use common::{module, start_executor};
+use lunatic_runtime::api::channel::{host_resources::Resource, ChannelSender, Message};
+use lunatic_runtime::api::networking::tcp::TcpStream;
+use lunatic_runtime::api::process::ProcessConfig;
+use lunatic_runtime::node::Node;
+
+use std::time::{Duration, Instant};
+
+// Sends the data of its context to the channel sender in the context.
+const ECHO: &str = r#"
+(module
+    (import "lunatic" "channel_receive_prepare"
+        (func $receive_prepare (param i32 i32) (result i32)))
+    (import "lunatic" "channel_receive" (func $receive (param i32 i32) (result i32)))
+    (import "lunatic" "sender_deserialize" (func $sender_deserialize (param i32) (result i32)))
+    (import "lunatic" "channel_send" (func $send (param i32 i32 i32) (result i32)))
+    (memory 1)
+    (table 1 funcref)
+    (elem (i32.const 0) $echo)
+    (func $echo
+        ;; the context receiver has the id 0, the size of the context is stored at 0
+        (drop (call $receive_prepare (i32.const 0) (i32.const 0)))
+        (drop (call $receive (i32.const 16) (i32.load (i32.const 0))))
+        (drop (call $send
+            (call $sender_deserialize (i32.const 0))
+            (i32.const 16)
+            (i32.load (i32.const 0)))))
+)
+"#;
+
+// Returns node `a` connected to node `b`, which has the echo module registered.
+async fn connected_nodes() -> (Node, Node) {
+    start_executor();
+    let a = Node::new("a", ProcessConfig::default());
+    let b = Node::new("b", ProcessConfig::default());
+    b.register_module("echo", module(ECHO));
+
+    let address = b.listen("127.0.0.1:0").await.unwrap();
+    assert_eq!(a.connect(&address.to_string()).await.unwrap(), "b");
+    (a, b)
+}
+
+// `connect` returns once the connecting node finished the handshake, the listening one might not
+// have registered the connection yet.
+async fn wait_for_peers(node: &Node) -> Vec<String> {
+    let start = Instant::now();
+    while node.peers().is_empty() && start.elapsed() < Duration::from_secs(5) {
+        smol::Timer::after(Duration::from_millis(10)).await;
+    }
+    node.peers()
+}
+
+#[test]
+fn spawn_on_other_node() {
+    smol::future::block_on(async {
+        let (a, b) = connected_nodes().await;
+        assert_eq!(wait_for_peers(&b).await, vec!["a".to_string()]);
+
+        let (sender, receiver) = smol::channel::unbounded();
+        let resources = vec![ChannelSender::from(sender).into()];
+        let process = a.spawn("b", "echo", 0, b"ping", resources).unwrap();
+
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(message.as_slice(), b"ping");
+        assert!(process.task().await.is_ok());
+    });
+}
+
+#[test]
+fn empty_messages() {
+    smol::future::block_on(async {
+        let (a, _b) = connected_nodes().await;
+
+        let (sender, receiver) = smol::channel::unbounded();
+        let resources = vec![ChannelSender::from(sender).into()];
+        let process = a.spawn("b", "echo", 0, b"", resources).unwrap();
+
+        assert!(process.task().await.is_ok());
+        assert!(receiver.recv().await.unwrap().as_slice().is_empty());
+    });
+}
+
+#[test]
+fn full_channels_dont_block_the_connection() {
+    smol::future::block_on(async {
+        let (a, _b) = connected_nodes().await;
+
+        let (sender, receiver) = smol::channel::bounded(1);
+        let full = Message::new(b"full".as_ptr(), 4, Vec::new());
+        sender.try_send(full).ok().unwrap();
+        let resources = vec![ChannelSender::from(sender).into()];
+        let process = a.spawn("b", "echo", 0, b"ping", resources).unwrap();
+
+        // The `Exited` frame arrives after the message for the full channel.
+        assert!(process.task().await.is_ok());
+        assert_eq!(receiver.recv().await.unwrap().as_slice(), b"full");
+        assert_eq!(receiver.recv().await.unwrap().as_slice(), b"ping");
+    });
+}
+
+#[test]
+fn spawn_errors() {
+    smol::future::block_on(async {
+        let (a, _b) = connected_nodes().await;
+
+        let err = a.spawn("c", "echo", 0, b"", Vec::new()).err().unwrap();
+        assert_eq!(err.to_string(), "Not connected to node `c`");
+
+        let process = a.spawn("b", "unknown", 0, b"", Vec::new()).unwrap();
+        let err = process.task().await.err().unwrap();
+        assert_eq!(err.error.to_string(), "No module `unknown` on node `b`");
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = TcpStream::connect(&[127, 0, 0, 1], port).await.unwrap();
+        let resources = vec![Resource::from(stream)];
+        let err = a.spawn("b", "echo", 0, b"", resources).err().unwrap();
+        assert_eq!(err.to_string(), "A TcpStream can't be sent to another node");
+    });
+}
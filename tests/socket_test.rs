@@ -0,0 +1,165 @@
+//! Checks that processes can use TCP sockets, both through the lunatic API and through WASI.
+
+mod common;
+
+use common::{module, run};
+use lunatic_runtime::api::networking::tcp::TcpListener;
+use lunatic_runtime::api::process::ProcessConfig;
+
+use std::io::Read;
+use std::net::TcpStream;
+use std::thread;
+
+// Accepts a connection on fd 4 (the first listener after the preopened directory), writes "hello"
+// to it and closes it.
+const HELLO_SERVER: &str = r#"
+(module
+    (import "wasi_snapshot_preview1" "sock_accept"
+        (func $sock_accept (param i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_close"
+        (func $fd_close (param i32) (result i32)))
+    (memory 1)
+    (data (i32.const 64) "hello")
+    (func (export "_start")
+        ;; the new fd is stored at 16
+        (drop (call $sock_accept (i32.const 4) (i32.const 0) (i32.const 16)))
+        ;; ciovec for the write: buf = 64, len = 5
+        (i32.store (i32.const 0) (i32.const 64))
+        (i32.store (i32.const 4) (i32.const 5))
+        (drop (call $fd_write (i32.load (i32.const 16)) (i32.const 0) (i32.const 1) (i32.const 8)))
+        (drop (call $fd_close (i32.load (i32.const 16)))))
+)
+"#;
+
+#[test]
+fn accept_on_preopened_listener() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let mut config = ProcessConfig::default();
+    config
+        .wasi
+        .tcp_listener(TcpListener::from_std(listener).unwrap());
+
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        received
+    });
+
+    run(module(HELLO_SERVER), config)
+        .map_err(|e| e.error)
+        .unwrap();
+
+    assert_eq!(client.join().unwrap(), "hello");
+}
+
+// Binds a listener, moves it into a file descriptor and then tries to use the old listener id.
+const LISTENER_INTO_FD: &str = r#"
+(module
+    (import "lunatic" "tcp_bind" (func $tcp_bind (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_listener_into_fd"
+        (func $into_fd (param i32 i32) (result i32)))
+    (import "lunatic" "tcp_listener_local_addr"
+        (func $local_addr (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 64) "\7f\00\00\01")
+    (func (export "_start")
+        ;; bind to 127.0.0.1:0, the listener id is stored at 0
+        (if (call $tcp_bind (i32.const 64) (i32.const 4) (i32.const 0) (i32.const 0))
+            (then (call $proc_exit (i32.const 1))))
+        ;; the new fd is stored at 4
+        (if (call $into_fd (i32.load (i32.const 0)) (i32.const 4))
+            (then (call $proc_exit (i32.const 2))))
+        ;; traps, the listener isn't in the resource table anymore
+        (drop (call $local_addr (i32.load (i32.const 0))
+            (i32.const 96) (i32.const 16) (i32.const 20) (i32.const 24) (i32.const 28))))
+)
+"#;
+
+#[test]
+fn move_listener_into_fd() {
+    let error = run(module(LISTENER_INTO_FD), ProcessConfig::default())
+        .err()
+        .unwrap();
+
+    assert_eq!(error.exit_code(), None);
+    assert!(format!("{:?}", error.error).contains("TcpListener not found"));
+}
+
+// Binds a listener to port 0 of 127.0.0.1 and connects to it. Checks the addresses of both ends
+// and that socket options can be set, exiting with the number of the first failed check.
+const ADDRESSES_AND_OPTIONS: &str = r#"
+(module
+    (import "lunatic" "tcp_bind" (func $tcp_bind (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_connect" (func $tcp_connect (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_accept" (func $tcp_accept (param i32 i32) (result i32)))
+    (import "lunatic" "tcp_listener_local_addr"
+        (func $listener_addr (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_stream_local_addr"
+        (func $local_addr (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_stream_peer_addr"
+        (func $peer_addr (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_set_nodelay" (func $set_nodelay (param i32 i32) (result i32)))
+    (import "lunatic" "tcp_set_ttl" (func $set_ttl (param i32 i32) (result i32)))
+    (import "lunatic" "tcp_set_keepalive" (func $set_keepalive (param i32 i32) (result i32)))
+    (import "lunatic" "tcp_set_linger" (func $set_linger (param i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 64) "\7f\00\00\01")
+    (func $check (param $ok i32) (param $exit_code i32)
+        (if (i32.eqz (local.get $ok))
+            (then (call $proc_exit (local.get $exit_code)))))
+    (func (export "_start")
+        ;; the ids of the listener, client and server end are stored at 0, 4 and 8
+        (call $check (i32.eqz (call $tcp_bind (i32.const 64) (i32.const 4) (i32.const 0)
+            (i32.const 0))) (i32.const 1))
+        ;; the address of the listener is stored at 96, its port at 116
+        (call $check (i32.eqz (call $listener_addr (i32.load (i32.const 0))
+            (i32.const 96) (i32.const 112) (i32.const 116) (i32.const 120) (i32.const 124)))
+            (i32.const 2))
+        (call $check (i32.ne (i32.load16_u (i32.const 116)) (i32.const 0)) (i32.const 3))
+        (call $check (i32.eq (i32.load (i32.const 96)) (i32.load (i32.const 64))) (i32.const 4))
+
+        (call $check (i32.eqz (call $tcp_connect (i32.const 64) (i32.const 4)
+            (i32.load16_u (i32.const 116)) (i32.const 4))) (i32.const 5))
+        (call $check (i32.eqz (call $tcp_accept (i32.load (i32.const 0)) (i32.const 8)))
+            (i32.const 6))
+        ;; the local address of the client is stored at 128, its port at 148
+        (call $check (i32.eqz (call $local_addr (i32.load (i32.const 4))
+            (i32.const 128) (i32.const 144) (i32.const 148) (i32.const 152) (i32.const 156)))
+            (i32.const 7))
+        ;; the peer address of the server end is stored at 160, its port at 180
+        (call $check (i32.eqz (call $peer_addr (i32.load (i32.const 8))
+            (i32.const 160) (i32.const 176) (i32.const 180) (i32.const 184) (i32.const 188)))
+            (i32.const 8))
+        (call $check (i32.eq (i32.load (i32.const 176)) (i32.const 4)) (i32.const 9))
+        (call $check (i32.eq (i32.load (i32.const 160)) (i32.load (i32.const 128))) (i32.const 10))
+        (call $check (i32.eq (i32.load16_u (i32.const 180)) (i32.load16_u (i32.const 148)))
+            (i32.const 11))
+
+        (call $check (i32.eqz (call $set_nodelay (i32.load (i32.const 4)) (i32.const 1)))
+            (i32.const 12))
+        (call $check (i32.eqz (call $set_ttl (i32.load (i32.const 4)) (i32.const 32)))
+            (i32.const 13))
+        (call $check (i32.eqz (call $set_keepalive (i32.load (i32.const 4)) (i32.const 60)))
+            (i32.const 14))
+        (call $check (i32.eqz (call $set_keepalive (i32.load (i32.const 4)) (i32.const 0)))
+            (i32.const 15))
+        (call $check (i32.eqz (call $set_linger (i32.load (i32.const 4)) (i32.const 1)
+            (i32.const 1))) (i32.const 16))
+        (call $check (i32.eqz (call $set_linger (i32.load (i32.const 4)) (i32.const 0)
+            (i32.const 0))) (i32.const 17)))
+)
+"#;
+
+#[test]
+fn addresses_and_socket_options() {
+    let result = run(module(ADDRESSES_AND_OPTIONS), ProcessConfig::default());
+    if let Err(error) = result {
+        panic!("check {:?} failed: {}", error.exit_code(), error.error);
+    }
+}
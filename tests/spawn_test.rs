@@ -0,0 +1,93 @@
+//! Checks spawning processes by the name of an exported function with parameters.
+
+mod common;
+
+use common::{module, run_function, start_executor};
+use lunatic_runtime::api::process::{FunctionLookup, Param, ProcessConfig};
+
+// `check` traps if it isn't called with (1, 2, 3.0, 4.0).
+//
+// `_start` spawns `check` with the right parameters and with only the first one, then exits with
+// `100 * first status + 10 * first join + second status`. It traps if the failed spawn didn't
+// return the process 0 or its last error isn't `inval` (28) with a message starting with
+// "Function".
+const MODULE: &str = r#"
+(module
+    (import "lunatic" "spawn"
+        (func $spawn (param i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "join" (func $join (param i32) (result i32)))
+    (import "lunatic" "last_error_status" (func $last_error_status (result i32)))
+    (import "lunatic" "last_error_message"
+        (func $last_error_message (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 0) "check")
+    (data (i32.const 32) "Function")
+    (data (i32.const 64)
+        "\00\00\00\00\00\00\00\00\01\00\00\00\00\00\00\00"
+        "\01\00\00\00\00\00\00\00\02\00\00\00\00\00\00\00"
+        "\02\00\00\00\00\00\00\00\00\00\40\40\00\00\00\00"
+        "\03\00\00\00\00\00\00\00\00\00\00\00\00\00\10\40")
+    (func (export "check") (param i32 i64 f32 f64)
+        (if (i32.ne (local.get 0) (i32.const 1)) (then unreachable))
+        (if (i64.ne (local.get 1) (i64.const 2)) (then unreachable))
+        (if (f32.ne (local.get 2) (f32.const 3)) (then unreachable))
+        (if (f64.ne (local.get 3) (f64.const 4)) (then unreachable)))
+    (func (export "_start")
+        (local $code i32)
+        ;; the spawned process is stored at 16
+        (local.set $code (i32.mul (i32.const 100)
+            (call $spawn (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 5)
+                (i32.const 64) (i32.const 64) (i32.const 0) (i32.const 0) (i32.const 16))))
+        (local.set $code (i32.add (local.get $code)
+            (i32.mul (i32.const 10) (call $join (i32.load (i32.const 16))))))
+        (local.set $code (i32.add (local.get $code)
+            (call $spawn (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 5)
+                (i32.const 64) (i32.const 16) (i32.const 0) (i32.const 0) (i32.const 16))))
+        (if (i32.load (i32.const 16)) (then unreachable))
+        (if (i32.ne (call $last_error_status) (i32.const 28)) (then unreachable))
+        (if (i32.ne (call $last_error_message (i32.const 128) (i32.const 8)) (i32.const 8))
+            (then unreachable))
+        (if (i64.ne (i64.load (i32.const 128)) (i64.load (i32.const 32))) (then unreachable))
+        (call $proc_exit (local.get $code)))
+)
+"#;
+
+fn run(function: &str, params: Vec<Param>) -> Result<(), String> {
+    let function = FunctionLookup::Name(function.to_string(), params);
+    run_function(module(MODULE), function, ProcessConfig::default()).map_err(|e| {
+        match e.exit_code() {
+            Some(code) => format!("exit code {}", code),
+            None => e.error.to_string(),
+        }
+    })
+}
+
+#[test]
+fn spawn_with_params() {
+    let params = vec![
+        Param::I32(1),
+        Param::I64(2),
+        Param::F32(3.0),
+        Param::F64(4.0),
+    ];
+    assert_eq!(run("check", params), Ok(()));
+
+    assert_eq!(
+        run("check", vec![Param::I32(1)]),
+        Err("Function `check` takes parameters [I32, I64, F32, F64], but got [I32]".to_string())
+    );
+    assert_eq!(
+        run("missing", Vec::new()),
+        Err("No exported function `missing`".to_string())
+    );
+}
+
+#[test]
+fn spawn_from_guest() {
+    start_executor();
+
+    // Spawning with the right parameters succeeded and the process didn't trap, spawning with the
+    // wrong ones failed.
+    assert_eq!(run("_start", Vec::new()), Err("exit code 1".to_string()));
+}
@@ -0,0 +1,60 @@
+//! Checks that the standard streams of a process can be redirected into in-memory buffers.
+
+mod common;
+
+use common::{module, run};
+use lunatic_runtime::api::process::ProcessConfig;
+use lunatic_runtime::api::wasi::{Buffer, Input, Output};
+
+// Reads up to 64 bytes from stdin and writes "hello\nworld\n" followed by them to stdout.
+const ECHO: &str = r#"
+(module
+    (import "wasi_snapshot_preview1" "fd_read"
+        (func $fd_read (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (memory 1)
+    (data (i32.const 64) "hello\nworld\n")
+    (func (export "_start")
+        ;; iovec for the read: buf = 76, len = 64
+        (i32.store (i32.const 0) (i32.const 76))
+        (i32.store (i32.const 4) (i32.const 64))
+        (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+        ;; ciovec for the write: buf = 64, len = 12 + bytes read
+        (i32.store (i32.const 0) (i32.const 64))
+        (i32.store (i32.const 4) (i32.add (i32.const 12) (i32.load (i32.const 8))))
+        (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+)
+"#;
+
+fn run_echo(config: ProcessConfig) {
+    run(module(ECHO), config).map_err(|e| e.error).unwrap();
+}
+
+#[test]
+fn capture_stdout() {
+    let stdout = Buffer::new();
+    let mut config = ProcessConfig::default();
+    config.wasi.stdin = Input::Buffer(Buffer::from(b"!\n".to_vec()));
+    config.wasi.stdout = Output::Buffer(stdout.clone());
+    run_echo(config);
+
+    assert_eq!(stdout.contents(), b"hello\nworld\n!\n");
+}
+
+#[test]
+fn prefix_stdout() {
+    let stdout = Buffer::new();
+    let mut config = ProcessConfig::default();
+    config.wasi.stdin = Input::Null;
+    config.wasi.stdout = Output::Prefixed(Box::new(Output::Buffer(stdout.clone())));
+    run_echo(config);
+
+    let stdout = String::from_utf8(stdout.contents()).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    let prefix = &lines[0][..lines[0].find(' ').unwrap() + 1];
+    assert!(prefix.starts_with('[') && prefix.ends_with("] "));
+    assert_eq!(lines[0], format!("{}hello", prefix));
+    assert_eq!(lines[1], format!("{}world", prefix));
+}
@@ -0,0 +1,119 @@
+//! Checks that networking calls give up with `TimedOut` once their timeout expires.
+
+mod common;
+
+use common::{module, run};
+use lunatic_runtime::api::networking::dns::NameResolver;
+use lunatic_runtime::api::networking::tcp::{TcpListener, TcpStream};
+use lunatic_runtime::api::networking::timeout::with_timeout;
+use lunatic_runtime::api::networking::{NetworkBackend, VirtualNetwork};
+use lunatic_runtime::api::process::ProcessConfig;
+
+use std::io::{self, IoSlice, IoSliceMut};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::thread;
+use std::time::Duration;
+
+// Calls the host functions with a timeout of 50 ms, each of them has to fail with `timedout` (73).
+// Exits with the number of the first call that didn't.
+const TIMEOUTS: &str = r#"
+(module
+    (import "lunatic" "tcp_bind" (func $tcp_bind (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_accept_timeout"
+        (func $tcp_accept_timeout (param i32 i64 i32) (result i32)))
+    (import "lunatic" "tcp_connect_timeout"
+        (func $tcp_connect_timeout (param i32 i32 i32 i64 i32) (result i32)))
+    (import "lunatic" "resolve_timeout"
+        (func $resolve_timeout (param i32 i32 i64 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 64) "\0a\00\00\01")
+    (data (i32.const 72) "\0a\00\00\02")
+    (data (i32.const 80) "slow:80")
+    (func $check (param $status i32) (param $exit_code i32)
+        (if (i32.ne (local.get $status) (i32.const 73))
+            (then (call $proc_exit (local.get $exit_code)))))
+    (func (export "_start")
+        ;; the listener id is stored at 0, nobody connects to it
+        (if (call $tcp_bind (i32.const 64) (i32.const 4) (i32.const 0) (i32.const 0))
+            (then (call $proc_exit (i32.const 1))))
+        (call $check (call $tcp_accept_timeout (i32.load (i32.const 0)) (i64.const 50)
+            (i32.const 4)) (i32.const 2))
+        ;; 10.0.0.2 is further away than the timeout
+        (call $check (call $tcp_connect_timeout (i32.const 72) (i32.const 4) (i32.const 80)
+            (i64.const 50) (i32.const 4)) (i32.const 3))
+        (call $check (call $resolve_timeout (i32.const 80) (i32.const 7) (i64.const 50)
+            (i32.const 4)) (i32.const 4)))
+)
+"#;
+
+// Takes longer to answer than the timeouts.
+struct SlowResolver;
+
+impl NameResolver for SlowResolver {
+    fn resolve(&self, _: &str) -> Result<Vec<SocketAddr>, io::Error> {
+        thread::sleep(Duration::from_secs(1));
+        Ok(vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 80)])
+    }
+}
+
+#[test]
+fn host_functions_time_out() {
+    let network = VirtualNetwork::new();
+    network.set_latency(Duration::from_secs(1));
+    let mut config = ProcessConfig::default();
+    config.network_backend =
+        NetworkBackend::Virtual(network.interface(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+    config.resolver(SlowResolver);
+
+    let result = run(module(TIMEOUTS), config);
+    if let Err(error) = result {
+        panic!(
+            "call {:?} didn't time out: {}",
+            error.exit_code(),
+            error.error
+        );
+    }
+}
+
+#[test]
+fn accept_times_out() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let listener = TcpListener::from_std(listener).unwrap();
+
+    smol::block_on(async {
+        let result = with_timeout(Some(Duration::from_millis(50)), listener.accept()).await;
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::TimedOut);
+    });
+}
+
+#[test]
+fn read_timeout_is_shared_by_clones() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let listener = TcpListener::from_std(listener).unwrap();
+
+    smol::block_on(async {
+        let client = TcpStream::connect(&[127, 0, 0, 1], port).await.unwrap();
+        let server = listener.accept().await.unwrap();
+        server.set_read_timeout(Some(Duration::from_millis(50)));
+
+        let mut buf = [0; 16];
+        let result = server
+            .clone()
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await;
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::TimedOut);
+
+        // Data arriving within the timeout is read as usual.
+        client
+            .write_vectored(&[IoSlice::new(b"hello")])
+            .await
+            .unwrap();
+        let read = server
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(&buf[..read], b"hello");
+    });
+}
@@ -0,0 +1,248 @@
+//! Checks that TLS streams can be established over TCP streams.
+//!
+//! `tests/tls` contains a self-signed CA and a certificate for `localhost` issued by it.
+
+mod common;
+
+use common::{module, run};
+use lunatic_runtime::api::networking::tcp::TcpStream;
+use lunatic_runtime::api::networking::tls::{TlsClientConfig, TlsServerConfig, TlsStream};
+use lunatic_runtime::api::process::ProcessConfig;
+
+use std::io::{self, IoSlice, IoSliceMut};
+use std::time::Duration;
+
+const CA: &[u8] = include_bytes!("tls/ca.pem");
+const CERT: &[u8] = include_bytes!("tls/cert.pem");
+const KEY: &[u8] = include_bytes!("tls/key.pem");
+
+// Connects to a listener on 127.0.0.1 that closes the connection right away, fails the TLS
+// handshake on top of the connection and then tries to use the TCP stream id.
+const TLS_CONNECT_TAKES_STREAM: &str = r#"
+(module
+    (import "lunatic" "tcp_bind" (func $tcp_bind (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_listener_local_addr"
+        (func $listener_addr (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_connect" (func $tcp_connect (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_accept" (func $tcp_accept (param i32 i32) (result i32)))
+    (import "lunatic" "close_tcp_stream" (func $close_tcp_stream (param i32)))
+    (import "lunatic" "tls_client_config"
+        (func $tls_client_config (param i32 i32 i32) (result i32)))
+    (import "lunatic" "tls_connect" (func $tls_connect (param i32 i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "tcp_stream_local_addr"
+        (func $local_addr (param i32 i32 i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 64) "\7f\00\00\01")
+    (data (i32.const 80) "localhost")
+    (func (export "_start")
+        ;; the ids of the listener, client and server end are stored at 0, 4 and 8
+        (if (call $tcp_bind (i32.const 64) (i32.const 4) (i32.const 0) (i32.const 0))
+            (then (call $proc_exit (i32.const 1))))
+        ;; the port of the listener is stored at 116
+        (if (call $listener_addr (i32.load (i32.const 0))
+                (i32.const 96) (i32.const 112) (i32.const 116) (i32.const 120) (i32.const 124))
+            (then (call $proc_exit (i32.const 2))))
+        (if (call $tcp_connect (i32.const 64) (i32.const 4) (i32.load16_u (i32.const 116))
+                (i32.const 4))
+            (then (call $proc_exit (i32.const 3))))
+        (if (call $tcp_accept (i32.load (i32.const 0)) (i32.const 8))
+            (then (call $proc_exit (i32.const 4))))
+        (call $close_tcp_stream (i32.load (i32.const 8)))
+        ;; the id of the config is stored at 12
+        (if (call $tls_client_config (i32.const 0) (i32.const 0) (i32.const 12))
+            (then (call $proc_exit (i32.const 5))))
+        (if (i32.eqz (call $tls_connect (i32.load (i32.const 12)) (i32.load (i32.const 4))
+                (i32.const 80) (i32.const 9) (i32.const 16)))
+            (then (call $proc_exit (i32.const 6))))
+        ;; traps, the stream isn't in the resource table anymore
+        (drop (call $local_addr (i32.load (i32.const 4))
+            (i32.const 96) (i32.const 112) (i32.const 116) (i32.const 120) (i32.const 124))))
+)
+"#;
+
+#[test]
+fn echo_over_tls() {
+    let server_config = TlsServerConfig::new(CERT, KEY).unwrap();
+    let client_config = TlsClientConfig::new(Some(CA)).unwrap();
+
+    smol::block_on(async {
+        let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = smol::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let stream = TlsStream::accept(&server_config, TcpStream::new(stream))
+                .await
+                .unwrap();
+            let mut buf = [0; 16];
+            let read = stream
+                .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+                .await
+                .unwrap();
+            stream
+                .write_vectored(&[IoSlice::new(&buf[..read])])
+                .await
+                .unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let stream = smol::net::TcpStream::connect(address).await.unwrap();
+        let stream = TlsStream::connect(&client_config, TcpStream::new(stream), "localhost")
+            .await
+            .unwrap();
+        stream
+            .write_vectored(&[IoSlice::new(b"hello")])
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        let mut buf = [0; 16];
+        let read = stream
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(&buf[..read], b"hello");
+        server.await;
+    });
+}
+
+#[test]
+fn untrusted_certificate() {
+    let server_config = TlsServerConfig::new(CERT, KEY).unwrap();
+    // Only trusts the Mozilla root certificates.
+    let client_config = TlsClientConfig::new(None).unwrap();
+
+    smol::block_on(async {
+        let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = smol::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            assert!(TlsStream::accept(&server_config, TcpStream::new(stream))
+                .await
+                .is_err());
+        });
+
+        let stream = smol::net::TcpStream::connect(address).await.unwrap();
+        assert!(
+            TlsStream::connect(&client_config, TcpStream::new(stream), "localhost")
+                .await
+                .is_err()
+        );
+        server.await;
+    });
+}
+
+#[test]
+fn write_while_reading() {
+    let server_config = TlsServerConfig::new(CERT, KEY).unwrap();
+    let client_config = TlsClientConfig::new(Some(CA)).unwrap();
+
+    smol::block_on(async {
+        let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = smol::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let stream = TlsStream::accept(&server_config, TcpStream::new(stream))
+                .await
+                .unwrap();
+            // Only answers after it received the client's message.
+            let mut buf = [0; 16];
+            let read = stream
+                .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+                .await
+                .unwrap();
+            stream
+                .write_vectored(&[IoSlice::new(&buf[..read])])
+                .await
+                .unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let stream = smol::net::TcpStream::connect(address).await.unwrap();
+        let stream = TlsStream::connect(&client_config, TcpStream::new(stream), "localhost")
+            .await
+            .unwrap();
+        let reader = stream.clone();
+        let reading = smol::spawn(async move {
+            let mut buf = [0; 16];
+            let read = reader
+                .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+                .await
+                .unwrap();
+            buf[..read].to_vec()
+        });
+        // Give the reader time to start waiting for data.
+        smol::Timer::after(Duration::from_millis(50)).await;
+
+        stream
+            .write_vectored(&[IoSlice::new(b"hello")])
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        assert_eq!(reading.await, b"hello");
+        server.await;
+    });
+}
+
+#[test]
+fn timeouts_apply_to_tls_streams() {
+    let server_config = TlsServerConfig::new(CERT, KEY).unwrap();
+    let client_config = TlsClientConfig::new(Some(CA)).unwrap();
+
+    smol::block_on(async {
+        let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        // The server never answers the handshake.
+        let stream = TcpStream::new(smol::net::TcpStream::connect(address).await.unwrap());
+        let (_silent, _) = listener.accept().await.unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(50)));
+        let err = TlsStream::connect(&client_config, stream, "localhost")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // The client never starts the handshake.
+        let _silent = smol::net::TcpStream::connect(address).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let stream = TcpStream::new(stream);
+        stream.set_read_timeout(Some(Duration::from_millis(50)));
+        let err = TlsStream::accept(&server_config, stream)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // The server never sends any data.
+        let server = smol::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let stream = TlsStream::accept(&server_config, TcpStream::new(stream))
+                .await
+                .unwrap();
+            smol::Timer::after(Duration::from_millis(500)).await;
+            drop(stream);
+        });
+        let tcp_stream = TcpStream::new(smol::net::TcpStream::connect(address).await.unwrap());
+        let stream = TlsStream::connect(&client_config, tcp_stream.clone(), "localhost")
+            .await
+            .unwrap();
+        tcp_stream.set_read_timeout(Some(Duration::from_millis(50)));
+        let mut buf = [0; 16];
+        let err = stream
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        server.await;
+    });
+}
+
+#[test]
+fn tls_connect_takes_tcp_stream() {
+    let error = run(module(TLS_CONNECT_TAKES_STREAM), ProcessConfig::default())
+        .err()
+        .unwrap();
+
+    assert_eq!(error.exit_code(), None);
+    assert!(format!("{:?}", error.error).contains("TcpStream not found"));
+}
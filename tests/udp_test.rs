@@ -0,0 +1,46 @@
+//! Checks that UDP sockets can exchange datagrams over the loopback interface.
+
+use lunatic_runtime::api::networking::udp::UdpSocket;
+
+use std::net::{IpAddr, Ipv4Addr};
+
+const LOCALHOST: [u8; 4] = [127, 0, 0, 1];
+
+#[test]
+fn udp_loopback() {
+    smol::block_on(async {
+        let a = UdpSocket::bind(&LOCALHOST, 0).await.unwrap();
+        let b = UdpSocket::bind(&LOCALHOST, 0).await.unwrap();
+        let a_addr = a.local_addr().unwrap();
+        let b_addr = b.local_addr().unwrap();
+        assert_eq!(a_addr.ip(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_ne!(a_addr.port(), 0);
+
+        let sent = a
+            .send_to(b"hello", &LOCALHOST, b_addr.port())
+            .await
+            .unwrap();
+        assert_eq!(sent, 5);
+        let mut buf = [0; 16];
+        let (received, from) = b.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..received], b"hello");
+        assert_eq!(from, a_addr);
+
+        // Connected sockets only receive datagrams from their peer.
+        let c = UdpSocket::bind(&LOCALHOST, 0).await.unwrap();
+        b.connect(&LOCALHOST, a_addr.port()).await.unwrap();
+        c.send_to(b"ignored", &LOCALHOST, b_addr.port())
+            .await
+            .unwrap();
+        a.send_to(b"world", &LOCALHOST, b_addr.port())
+            .await
+            .unwrap();
+        let received = b.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..received], b"world");
+
+        assert_eq!(b.send(b"reply").await.unwrap(), 5);
+        let (received, from) = a.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..received], b"reply");
+        assert_eq!(from, b_addr);
+    });
+}
@@ -0,0 +1,96 @@
+//! Checks that processes can connect to Unix domain sockets and exchange data over them.
+#![cfg(unix)]
+
+mod common;
+
+use common::module;
+use lunatic_runtime::api::networking::{NetworkBackend, VirtualNetwork};
+use lunatic_runtime::api::process::ProcessConfig;
+use lunatic_runtime::api::wasi::{Buffer, Output};
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::{env, fs, process};
+
+// Binds a socket at PATH and connects to it. The client sends "hello", the server echoes it back
+// and the client writes what it received to stdout. Exits with 1, 2 or 3 if binding, connecting
+// or accepting fails.
+const ECHO: &str = r#"
+(module
+    (import "lunatic" "unix_bind" (func $bind (param i32 i32 i32) (result i32)))
+    (import "lunatic" "unix_connect" (func $connect (param i32 i32 i32) (result i32)))
+    (import "lunatic" "unix_accept" (func $accept (param i32 i32) (result i32)))
+    (import "lunatic" "unix_write_vectored" (func $write (param i32 i32 i32 i32) (result i32)))
+    (import "lunatic" "unix_read_vectored" (func $read (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (memory 1)
+    (data (i32.const 64) "hello")
+    (data (i32.const 256) "PATH")
+    ;; Writes the bytes at 128 received by the last read.
+    (func $write_received (param $stream i32)
+        (i32.store (i32.const 40) (i32.const 128))
+        (i32.store (i32.const 44) (i32.load (i32.const 32)))
+        (drop (call $write (local.get $stream) (i32.const 40) (i32.const 1) (i32.const 28))))
+    ;; Reads up to 64 bytes to 128.
+    (func $read_received (param $stream i32)
+        (i32.store (i32.const 48) (i32.const 128))
+        (i32.store (i32.const 52) (i32.const 64))
+        (drop (call $read (local.get $stream) (i32.const 48) (i32.const 1) (i32.const 32))))
+    (func (export "_start")
+        ;; the ids of the listener, client and server are stored at 16, 20 and 24
+        (if (call $bind (i32.const 256) (i32.const PATH_LEN) (i32.const 16))
+            (then (call $proc_exit (i32.const 1))))
+        (if (call $connect (i32.const 256) (i32.const PATH_LEN) (i32.const 20))
+            (then (call $proc_exit (i32.const 2))))
+        (if (call $accept (i32.load (i32.const 16)) (i32.const 24))
+            (then (call $proc_exit (i32.const 3))))
+
+        (i32.store (i32.const 40) (i32.const 64))
+        (i32.store (i32.const 44) (i32.const 5))
+        (drop (call $write (i32.load (i32.const 20)) (i32.const 40) (i32.const 1) (i32.const 28)))
+        (call $read_received (i32.load (i32.const 24)))
+        (call $write_received (i32.load (i32.const 24)))
+        (call $read_received (i32.load (i32.const 20)))
+
+        (i32.store (i32.const 40) (i32.const 128))
+        (i32.store (i32.const 44) (i32.load (i32.const 32)))
+        (drop (call $fd_write (i32.const 1) (i32.const 40) (i32.const 1) (i32.const 28))))
+)
+"#;
+
+fn run(path: &str, mut config: ProcessConfig) -> (Option<u32>, Vec<u8>) {
+    let stdout = Buffer::new();
+    config.wasi.stdout = Output::Buffer(stdout.clone());
+    let wat = ECHO
+        .replace("PATH_LEN", &path.len().to_string())
+        .replace("PATH", path);
+    let result = common::run(module(&wat), config);
+    let exit_code = match result {
+        Ok(()) => None,
+        Err(error) => Some(error.exit_code().expect("process trapped")),
+    };
+    (exit_code, stdout.contents())
+}
+
+#[test]
+fn unix_socket_echo() {
+    let directory = env::temp_dir().join(format!("lunatic-unix-socket-{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("echo.sock");
+
+    let (exit_code, stdout) = run(path.to_str().unwrap(), ProcessConfig::default());
+    assert_eq!(exit_code, None);
+    assert_eq!(stdout, b"hello");
+
+    // Unix sockets would escape a virtual network.
+    let mut config = ProcessConfig::default();
+    let network = VirtualNetwork::new();
+    config.network_backend =
+        NetworkBackend::Virtual(network.interface(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))));
+    let (exit_code, stdout) = run(directory.join("virtual.sock").to_str().unwrap(), config);
+    assert_eq!(exit_code, Some(1));
+    assert!(stdout.is_empty());
+
+    fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,232 @@
+//! Checks that sockets on a virtual network behave like their host counterparts.
+
+use lunatic_runtime::api::networking::timeout::with_timeout;
+use lunatic_runtime::api::networking::tls::{TlsClientConfig, TlsServerConfig, TlsStream};
+use lunatic_runtime::api::networking::{NetworkBackend, VirtualNetwork};
+
+use std::io::{self, IoSlice, IoSliceMut};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+const A: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+const B: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+fn backends(network: &VirtualNetwork) -> (NetworkBackend, NetworkBackend) {
+    (
+        NetworkBackend::Virtual(network.interface(A)),
+        NetworkBackend::Virtual(network.interface(B)),
+    )
+}
+
+#[test]
+fn tcp_echo_with_latency() {
+    let network = VirtualNetwork::new();
+    network.set_latency(Duration::from_millis(20));
+    let (a, b) = backends(&network);
+
+    smol::block_on(async {
+        let listener = a.tcp_bind(&[0, 0, 0, 0], 0).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = smol::spawn(async move {
+            let stream = listener.accept().await.unwrap();
+            let mut buf = [0; 16];
+            let read = stream
+                .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+                .await
+                .unwrap();
+            stream
+                .write_vectored(&[IoSlice::new(&buf[..read])])
+                .await
+                .unwrap();
+        });
+
+        let start = Instant::now();
+        let stream = b.tcp_connect(&[10, 0, 0, 1], port).await.unwrap();
+        assert_eq!(stream.local_addr().unwrap().ip(), B);
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+        stream
+            .write_vectored(&[IoSlice::new(b"hello")])
+            .await
+            .unwrap();
+        let mut buf = [0; 16];
+        let read = stream
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(&buf[..read], b"hello");
+        // Connecting, sending and answering each take one trip.
+        assert!(start.elapsed() >= Duration::from_millis(60));
+
+        server.await;
+        // The server's end was dropped, which closes the stream.
+        let read = stream
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(read, 0);
+    });
+}
+
+#[test]
+fn partitions_and_refused_connections() {
+    let network = VirtualNetwork::new();
+    let (a, b) = backends(&network);
+
+    smol::block_on(async {
+        let err = b.tcp_connect(&[10, 0, 0, 1], 80).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+        let listener = a.tcp_bind(&[10, 0, 0, 1], 80).await.unwrap();
+        let err = a.tcp_bind(&[127, 0, 0, 1], 80).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        let err = a.tcp_bind(&[10, 0, 0, 2], 80).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrNotAvailable);
+
+        let stream = b.tcp_connect(&[10, 0, 0, 1], 80).await.unwrap();
+        let _server_end = listener.accept().await.unwrap();
+
+        network.partition(&[A], &[B]);
+        let err = b.tcp_connect(&[10, 0, 0, 1], 80).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        let err = stream
+            .write_vectored(&[IoSlice::new(b"lost")])
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        network.heal();
+        assert!(b.tcp_connect(&[10, 0, 0, 1], 80).await.is_ok());
+    });
+}
+
+#[test]
+fn ephemeral_ports_run_out() {
+    let network = VirtualNetwork::new();
+    let (a, _) = backends(&network);
+
+    smol::block_on(async {
+        let mut listeners = Vec::new();
+        for _ in 49152..=65535 {
+            listeners.push(a.tcp_bind(&[0, 0, 0, 0], 0).await.unwrap());
+        }
+        let err = a.tcp_bind(&[0, 0, 0, 0], 0).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        let port = listeners[0].local_addr().unwrap().port();
+        let err = a.tcp_bind(&[0, 0, 0, 0], port).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        // Closing a listener frees its port.
+        drop(listeners.remove(0));
+        let listener = a.tcp_bind(&[0, 0, 0, 0], 0).await.unwrap();
+        assert_eq!(listener.local_addr().unwrap().port(), port);
+    });
+}
+
+#[test]
+fn tcp_writes_wait_for_the_reader() {
+    let network = VirtualNetwork::new();
+    let (a, b) = backends(&network);
+
+    smol::block_on(async {
+        let listener = a.tcp_bind(&[10, 0, 0, 1], 80).await.unwrap();
+        let stream = b.tcp_connect(&[10, 0, 0, 1], 80).await.unwrap();
+        let server_end = listener.accept().await.unwrap();
+
+        stream
+            .write_vectored(&[IoSlice::new(b"hello world")])
+            .await
+            .unwrap();
+        let timeout = Some(Duration::from_millis(50));
+        let more = [IoSlice::new(b"more")];
+        let write = || stream.write_vectored(&more);
+        let mut written = 1;
+        let err = loop {
+            match with_timeout(timeout, write()).await {
+                Ok(_) => written += 1,
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(written > 1 && written < 100);
+
+        // The first write is read into both buffers at once, which makes room for another one.
+        let (mut hello, mut world) = ([0; 5], [0; 6]);
+        let read = server_end
+            .read_vectored(&mut [IoSliceMut::new(&mut hello), IoSliceMut::new(&mut world)])
+            .await
+            .unwrap();
+        assert_eq!(read, 11);
+        assert_eq!((&hello, &world), (b"hello", b" world"));
+        with_timeout(timeout, write()).await.unwrap();
+    });
+}
+
+#[test]
+fn tls_over_virtual_network() {
+    let server_config = TlsServerConfig::new(
+        include_bytes!("tls/cert.pem"),
+        include_bytes!("tls/key.pem"),
+    )
+    .unwrap();
+    let client_config = TlsClientConfig::new(Some(include_bytes!("tls/ca.pem"))).unwrap();
+    let network = VirtualNetwork::new();
+    let (a, b) = backends(&network);
+
+    smol::block_on(async {
+        let listener = a.tcp_bind(&[0, 0, 0, 0], 443).await.unwrap();
+        let server = smol::spawn(async move {
+            let stream = listener.accept().await.unwrap();
+            let stream = TlsStream::accept(&server_config, stream).await.unwrap();
+            stream
+                .write_vectored(&[IoSlice::new(b"secret")])
+                .await
+                .unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let stream = b.tcp_connect(&[10, 0, 0, 1], 443).await.unwrap();
+        let stream = TlsStream::connect(&client_config, stream, "localhost")
+            .await
+            .unwrap();
+        let mut buf = [0; 16];
+        let read = stream
+            .read_vectored(&mut [IoSliceMut::new(&mut buf)])
+            .await
+            .unwrap();
+        assert_eq!(&buf[..read], b"secret");
+        server.await;
+    });
+}
+
+// Sends 100 datagrams and returns which ones arrived.
+fn received_datagrams(seed: u64) -> Vec<u8> {
+    let network = VirtualNetwork::new();
+    network.set_packet_loss(0.5);
+    network.set_seed(seed);
+    let (a, b) = backends(&network);
+
+    smol::block_on(async {
+        let receiver = a.udp_bind(&[10, 0, 0, 1], 5000).await.unwrap();
+        let sender = b.udp_bind(&[0, 0, 0, 0], 0).await.unwrap();
+        for i in 0..100u8 {
+            sender.send_to(&[i], &[10, 0, 0, 1], 5000).await.unwrap();
+        }
+
+        let mut received = Vec::new();
+        let mut buf = [0; 1];
+        let timeout = Some(Duration::from_millis(50));
+        while let Ok((_, from)) = with_timeout(timeout, receiver.recv_from(&mut buf)).await {
+            assert_eq!(from.ip(), B);
+            received.push(buf[0]);
+        }
+        received
+    })
+}
+
+#[test]
+fn udp_packet_loss_is_reproducible() {
+    let received = received_datagrams(7);
+    assert!(received.len() > 20 && received.len() < 80);
+    assert_eq!(received, received_datagrams(7));
+    assert_ne!(received, received_datagrams(8));
+}
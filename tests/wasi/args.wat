@@ -0,0 +1,47 @@
+;; Writes every argument and environment variable on its own line.
+(module
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "args_sizes_get"
+        (func $args_sizes_get (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "args_get"
+        (func $args_get (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "environ_sizes_get"
+        (func $environ_sizes_get (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "environ_get"
+        (func $environ_get (param i32 i32) (result i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 256) "\n")
+    (func $print (param $ptr i32) (param $len i32)
+        (i32.store (i32.const 0) (local.get $ptr))
+        (i32.store (i32.const 4) (local.get $len))
+        (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+    ;; Prints `$count` nul terminated strings pointed to from `$list`.
+    (func $print_list (param $list i32) (param $count i32)
+        (local $str i32)
+        (local $len i32)
+        (block $done
+            (loop $next
+                (br_if $done (i32.eqz (local.get $count)))
+                (local.set $str (i32.load (local.get $list)))
+                (local.set $len (i32.const 0))
+                (block $end
+                    (loop $char
+                        (br_if $end (i32.eqz
+                            (i32.load8_u (i32.add (local.get $str) (local.get $len)))))
+                        (local.set $len (i32.add (local.get $len) (i32.const 1)))
+                        (br $char)))
+                (call $print (local.get $str) (local.get $len))
+                (call $print (i32.const 256) (i32.const 1))
+                (local.set $list (i32.add (local.get $list) (i32.const 4)))
+                (local.set $count (i32.sub (local.get $count) (i32.const 1)))
+                (br $next))))
+    (func (export "_start")
+        ;; Pointers are stored at 1024, the strings at 2048.
+        (drop (call $args_sizes_get (i32.const 16) (i32.const 20)))
+        (drop (call $args_get (i32.const 1024) (i32.const 2048)))
+        (call $print_list (i32.const 1024) (i32.load (i32.const 16)))
+        (drop (call $environ_sizes_get (i32.const 16) (i32.const 20)))
+        (drop (call $environ_get (i32.const 1024) (i32.const 2048)))
+        (call $print_list (i32.const 1024) (i32.load (i32.const 16))))
+)
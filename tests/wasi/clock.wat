@@ -0,0 +1,65 @@
+;; Checks that every clock id reads the matching clock.
+(module
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "clock_time_get"
+        (func $clock_time_get (param i32 i64 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "clock_res_get"
+        (func $clock_res_get (param i32 i32) (result i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 256) "realtime ok\n")
+    (data (i32.const 272) "realtime wrong\n")
+    (data (i32.const 288) "monotonic ok\n")
+    (data (i32.const 304) "monotonic wrong\n")
+    (data (i32.const 320) "cputime ok\n")
+    (data (i32.const 336) "cputime wrong\n")
+    (data (i32.const 352) "resolution ok\n")
+    (data (i32.const 368) "resolution wrong\n")
+    (data (i32.const 388) "invalid clock ok\n")
+    (data (i32.const 408) "invalid clock wrong\n")
+    (func $print (param $ptr i32) (param $len i32)
+        (i32.store (i32.const 0) (local.get $ptr))
+        (i32.store (i32.const 4) (local.get $len))
+        (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+    ;; Prints the first message if `$ok` is set, otherwise the second one.
+    (func $check (param $ok i32) (param $ok_ptr i32) (param $ok_len i32)
+            (param $wrong_ptr i32) (param $wrong_len i32)
+        (if (local.get $ok)
+            (then (call $print (local.get $ok_ptr) (local.get $ok_len)))
+            (else (call $print (local.get $wrong_ptr) (local.get $wrong_len)))))
+    ;; Returns 1 if the clock has a non-zero resolution.
+    (func $has_resolution (param $id i32) (result i32)
+        (i32.and
+            (i32.eqz (call $clock_res_get (local.get $id) (i32.const 16)))
+            (i64.ne (i64.load (i32.const 16)) (i64.const 0))))
+    (func (export "_start")
+        ;; The realtime clock is past 2020-01-01.
+        (call $check
+            (i32.and
+                (i32.eqz (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 16)))
+                (i64.gt_u (i64.load (i32.const 16)) (i64.const 1577836800000000000)))
+            (i32.const 256) (i32.const 12) (i32.const 272) (i32.const 15))
+        ;; The monotonic clock never goes back.
+        (call $check
+            (i32.and
+                (i32.and
+                    (i32.eqz (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 24)))
+                    (i32.eqz (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 32))))
+                (i64.ge_u (i64.load (i32.const 32)) (i64.load (i32.const 24))))
+            (i32.const 288) (i32.const 13) (i32.const 304) (i32.const 16))
+        ;; The process has been running for less than a day of CPU time.
+        (call $check
+            (i32.and
+                (i32.eqz (call $clock_time_get (i32.const 2) (i64.const 1) (i32.const 16)))
+                (i64.lt_u (i64.load (i32.const 16)) (i64.const 86400000000000)))
+            (i32.const 320) (i32.const 11) (i32.const 336) (i32.const 14))
+        (call $check
+            (i32.and
+                (i32.and (call $has_resolution (i32.const 0)) (call $has_resolution (i32.const 1)))
+                (i32.and (call $has_resolution (i32.const 2)) (call $has_resolution (i32.const 3))))
+            (i32.const 352) (i32.const 14) (i32.const 368) (i32.const 17))
+        ;; Unknown clock ids fail with `inval`.
+        (call $check
+            (i32.eq (call $clock_time_get (i32.const 42) (i64.const 1) (i32.const 16)) (i32.const 28))
+            (i32.const 388) (i32.const 17) (i32.const 408) (i32.const 20)))
+)
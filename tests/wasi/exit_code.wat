@@ -0,0 +1,18 @@
+;; Exits with code 3 through `proc_exit`, nothing after the call runs.
+(module
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit"
+        (func $proc_exit (param i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 256) "exiting\n")
+    (data (i32.const 272) "not reached\n")
+    (func $print (param $ptr i32) (param $len i32)
+        (i32.store (i32.const 0) (local.get $ptr))
+        (i32.store (i32.const 4) (local.get $len))
+        (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+    (func (export "_start")
+        (call $print (i32.const 256) (i32.const 8))
+        (call $proc_exit (i32.const 3))
+        (call $print (i32.const 272) (i32.const 12)))
+)
@@ -0,0 +1,40 @@
+;; Writes a file, reads it back and closes it twice.
+(module
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_read"
+        (func $fd_read (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_seek"
+        (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_close"
+        (func $fd_close (param i32) (result i32)))
+    (import "wasi_snapshot_preview1" "path_open"
+        (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 256) "data.txt")
+    (data (i32.const 272) "file contents\n")
+    (data (i32.const 288) "double close ok\n")
+    (data (i32.const 304) "double close wrong\n")
+    (func $write (param $fd i32) (param $ptr i32) (param $len i32)
+        (i32.store (i32.const 0) (local.get $ptr))
+        (i32.store (i32.const 4) (local.get $len))
+        (drop (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8))))
+    (func (export "_start")
+        (local $fd i32)
+        ;; open with OFLAGS_CREAT | OFLAGS_TRUNC and the rights to read, seek and write,
+        ;; the new fd is stored at 16
+        (drop (call $path_open (i32.const 3) (i32.const 0) (i32.const 256) (i32.const 8)
+            (i32.const 9) (i64.const 0x46) (i64.const 0) (i32.const 0) (i32.const 16)))
+        (local.set $fd (i32.load (i32.const 16)))
+        (call $write (local.get $fd) (i32.const 272) (i32.const 14))
+        (drop (call $fd_seek (local.get $fd) (i64.const 0) (i32.const 0) (i32.const 24)))
+        ;; iovec for the read: buf = 512, len = 64
+        (i32.store (i32.const 0) (i32.const 512))
+        (i32.store (i32.const 4) (i32.const 64))
+        (drop (call $fd_read (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 8)))
+        (call $write (i32.const 1) (i32.const 512) (i32.load (i32.const 8)))
+        (drop (call $fd_close (local.get $fd)))
+        (if (i32.eq (call $fd_close (local.get $fd)) (i32.const 8))
+            (then (call $write (i32.const 1) (i32.const 288) (i32.const 16)))
+            (else (call $write (i32.const 1) (i32.const 304) (i32.const 19)))))
+)
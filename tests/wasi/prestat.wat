@@ -0,0 +1,26 @@
+;; Writes the name of the preopened directory and checks that it's the only one.
+(module
+    (import "wasi_snapshot_preview1" "fd_write"
+        (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_prestat_get"
+        (func $fd_prestat_get (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_prestat_dir_name"
+        (func $fd_prestat_dir_name (param i32 i32 i32) (result i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 256) "\n")
+    (data (i32.const 272) "badf ok\n")
+    (data (i32.const 288) "badf wrong\n")
+    (func $print (param $ptr i32) (param $len i32)
+        (i32.store (i32.const 0) (local.get $ptr))
+        (i32.store (i32.const 4) (local.get $len))
+        (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+    (func (export "_start")
+        ;; The prestat of fd 3 is stored at 16, the name length at 20.
+        (drop (call $fd_prestat_get (i32.const 3) (i32.const 16)))
+        (drop (call $fd_prestat_dir_name (i32.const 3) (i32.const 512) (i32.load (i32.const 20))))
+        (call $print (i32.const 512) (i32.load (i32.const 20)))
+        (call $print (i32.const 256) (i32.const 1))
+        (if (i32.eq (call $fd_prestat_get (i32.const 4) (i32.const 16)) (i32.const 8))
+            (then (call $print (i32.const 272) (i32.const 8)))
+            (else (call $print (i32.const 288) (i32.const 11)))))
+)
@@ -0,0 +1,79 @@
+//! Runs the WASI conformance corpus in `tests/wasi`.
+//!
+//! Every `<name>.wat` program is compiled and run with the arguments `<name>.wasm first second`, the
+//! environment variable `WASI_TEST=yes` and an empty in-memory filesystem. Its output has to match
+//! `<name>.stdout` and its exit code `<name>.exit`.
+
+mod common;
+
+use common::module;
+use lunatic_runtime::api::process::ProcessConfig;
+use lunatic_runtime::api::wasi::{Buffer, Input, MemoryFileSystem, Output};
+
+use std::fs;
+use std::path::Path;
+
+fn run(wat: &Path) -> (Vec<u8>, u32) {
+    let stdout = Buffer::new();
+    let mut config = ProcessConfig::default();
+    config
+        .wasi
+        .arg(
+            wat.with_extension("wasm")
+                .file_name()
+                .unwrap()
+                .to_string_lossy(),
+        )
+        .arg("first")
+        .arg("second")
+        .env("WASI_TEST", "yes")
+        .filesystem(MemoryFileSystem::new());
+    config.wasi.stdin = Input::Null;
+    config.wasi.stdout = Output::Buffer(stdout.clone());
+
+    let result = common::run(module(&fs::read_to_string(wat).unwrap()), config);
+    let exit_code = match result {
+        Ok(()) => 0,
+        Err(error) => match error.exit_code() {
+            Some(code) => code,
+            None => panic!("{} failed: {}", wat.display(), error.error),
+        },
+    };
+    (stdout.contents(), exit_code)
+}
+
+#[test]
+fn wasi_conformance() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/wasi");
+    let mut programs: Vec<_> = fs::read_dir(corpus)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "wat"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty());
+
+    let mut failures = Vec::new();
+    for wat in programs {
+        let expected_stdout = fs::read_to_string(wat.with_extension("stdout")).unwrap();
+        let expected_exit_code: u32 = fs::read_to_string(wat.with_extension("exit"))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+
+        let (stdout, exit_code) = run(&wat);
+        let stdout = String::from_utf8_lossy(&stdout);
+        if stdout != expected_stdout || exit_code != expected_exit_code {
+            failures.push(format!(
+                "{}: expected exit code {} and stdout {:?}, got exit code {} and stdout {:?}",
+                wat.display(),
+                expected_exit_code,
+                expected_stdout,
+                exit_code,
+                stdout
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
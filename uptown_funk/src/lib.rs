@@ -121,6 +121,7 @@ where
     D: 'static,
 {
     message: String,
+    exit_status: Option<i32>,
     data: Option<D>,
 }
 
@@ -134,13 +135,29 @@ impl Trap<()> {
     pub fn new<I: Into<String>>(message: I) -> Self {
         Self {
             message: message.into(),
+            exit_status: None,
             data: None,
         }
     }
 
+    /// Creates a trap that stops the instance with an exit status, the runtime reports it as
+    /// `wasmtime::Trap::i32_exit` or `I32Exit`.
+    pub fn i32_exit(status: i32) -> Self {
+        Self {
+            message: I32Exit(status).to_string(),
+            exit_status: Some(status),
+            data: None,
+        }
+    }
+
+    pub fn i32_exit_status(&self) -> Option<i32> {
+        self.exit_status
+    }
+
     pub fn with_data<D: 'static>(self, data: D) -> Trap<D> {
         Trap {
             message: self.message,
+            exit_status: self.exit_status,
             data: Some(data),
         }
     }
@@ -176,10 +193,25 @@ impl<S> ToWasm<S> for Trap {
 #[cfg(feature = "vm-wasmtime")]
 impl From<Trap> for wasmtime::Trap {
     fn from(trap: Trap) -> Self {
-        wasmtime::Trap::new(trap.message)
+        match trap.exit_status {
+            Some(status) => wasmtime::Trap::i32_exit(status),
+            None => wasmtime::Trap::new(trap.message),
+        }
     }
 }
 
+/// Error raised by wasmer instances stopped with `Trap::i32_exit`.
+#[derive(Debug)]
+pub struct I32Exit(pub i32);
+
+impl std::fmt::Display for I32Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Exited with i32 exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for I32Exit {}
+
 #[repr(C)]
 pub struct IoVecT {
     pub ptr: u32,
@@ -13,6 +13,15 @@ impl<T> HashMapStore<T> {
         }
     }
 
+    /// Creates a store that never hands out the id 0, so that functions returning an id can use it
+    /// to signal that no resource was created.
+    pub fn new_nonzero() -> Self {
+        Self {
+            id_seed: 1,
+            store: HashMap::new(),
+        }
+    }
+
     pub fn add(&mut self, item: T) -> u32 {
         let id = self.id_seed;
         self.store.insert(id, item);
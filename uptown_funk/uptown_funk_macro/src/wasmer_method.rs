@@ -42,7 +42,10 @@ pub fn wrap(namespace: &LitStr, method: &ImplItemMethod) -> Result<TokenStream2,
                 Ok(#from_host_return_transformations(result)?)
             })() {
                 Ok(result) => result,
-                Err(trap) => unsafe { wasmer::raise_user_trap(Box::new(trap.with_data(state_wrapper.clone()))) }
+                Err(trap) => match trap.i32_exit_status() {
+                    Some(status) => unsafe { wasmer::raise_user_trap(Box::new(uptown_funk::I32Exit(status))) },
+                    None => unsafe { wasmer::raise_user_trap(Box::new(trap.with_data(state_wrapper.clone()))) },
+                }
             }
         };
 